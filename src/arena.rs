@@ -0,0 +1,542 @@
+// A singly-linked stack whose nodes live in an arena.
+//
+// Improvements over `second::List`:
+//     - no allocation per push: nodes live in one `Vec` (a slab) and link to each other by `u32`
+//       index, and popped slots are reused through a free list
+//     - `push` returns a generational `Handle` to the element, which stops working once that
+//       element is popped, even if its slot has been reused since
+//     - `compact` renumbers the live nodes so that the list is laid out in order again after
+//       lots of pushing and popping, and returns the new handle for each live element
+//
+// The API otherwise matches `second::List`.
+
+use std::collections::HashMap;
+use std::iter::FromIterator;
+use std::marker::PhantomData;
+
+//////////////////////////////////////////////////////////////////////////////
+// Data structures
+//
+// Memory layout:
+//
+// slots: [(A, gen 0, next 2), (C, gen 3, next NIL), (B, gen 1, next 1), (free, gen 5, next NIL)]
+// head:  0
+// free:  3
+//
+// which is the stack A -> B -> C with one free slot.
+//
+
+pub struct List<T> {
+    slots: Vec<Slot<T>>,
+    head: Index,
+    free: Index,
+    len: usize,
+    // every slot created from now on starts at this generation (see `compact`)
+    min_generation: u32,
+}
+
+type Index = u32;
+
+// the "null" index
+const NIL: Index = Index::MAX;
+
+// A slot is occupied when `elem` is Some. The `next` link of a free slot points at the next free
+// slot. A slot's generation is bumped every time its element is popped.
+struct Slot<T> {
+    elem: Option<T>,
+    generation: u32,
+    next: Index,
+}
+
+// A reference to an element pushed onto a `List`, valid until that element is popped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Handle {
+    index: Index,
+    generation: u32,
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Implementation
+
+impl<T> List<T> {
+    // return a new, empty list
+    pub fn new() -> Self {
+        List {
+            slots: Vec::new(),
+            head: NIL,
+            free: NIL,
+            len: 0,
+            min_generation: 0,
+        }
+    }
+
+    // return a new, empty list with room for `capacity` elements before it reallocates
+    pub fn with_capacity(capacity: usize) -> Self {
+        List {
+            slots: Vec::with_capacity(capacity),
+            ..List::new()
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // push an element onto the stack, returning a handle to it
+    pub fn push(&mut self, x: T) -> Handle {
+        let index = if self.free != NIL {
+            // reuse the most recently freed slot
+            let index = self.free;
+            let slot = &mut self.slots[index as usize];
+            self.free = slot.next;
+            slot.elem = Some(x);
+            slot.next = self.head;
+            index
+        } else {
+            let index = self.slots.len();
+            assert!(index < NIL as usize, "arena list is full");
+            self.slots.push(Slot {
+                elem: Some(x),
+                generation: self.min_generation,
+                next: self.head,
+            });
+            index as Index
+        };
+
+        self.head = index;
+        self.len += 1;
+        Handle {
+            index,
+            generation: self.slots[index as usize].generation,
+        }
+    }
+
+    // pop an element from the stack, returning either Some(value) or None if
+    // the stack is empty.
+    pub fn pop(&mut self) -> Option<T> {
+        if self.head == NIL {
+            return None;
+        }
+        let index = self.head;
+        let slot = &mut self.slots[index as usize];
+        let elem = slot.elem.take();
+        self.head = slot.next;
+
+        // retire any handles to this slot and put it on the free list
+        slot.generation = slot.generation.wrapping_add(1);
+        slot.next = self.free;
+        self.free = index;
+        self.len -= 1;
+        elem
+    }
+
+    // peek at the element at the head of the list
+    pub fn peek(&self) -> Option<&T> {
+        self.slot(self.head).and_then(|slot| slot.elem.as_ref())
+    }
+
+    // peek at the element at the head of the list, return a mutable ref
+    pub fn peek_mut(&mut self) -> Option<&mut T> {
+        let head = self.head;
+        self.slot_mut(head).and_then(|slot| slot.elem.as_mut())
+    }
+
+    // look up an element by handle, returning None if it has been popped
+    pub fn get(&self, handle: Handle) -> Option<&T> {
+        self.slot(handle.index)
+            .filter(|slot| slot.generation == handle.generation)
+            .and_then(|slot| slot.elem.as_ref())
+    }
+
+    // look up an element by handle, returning None if it has been popped
+    pub fn get_mut(&mut self, handle: Handle) -> Option<&mut T> {
+        self.slot_mut(handle.index)
+            .filter(|slot| slot.generation == handle.generation)
+            .and_then(|slot| slot.elem.as_mut())
+    }
+
+    // Renumber the live nodes so that they sit at the front of the arena in stack order, and
+    // release the free slots.
+    //
+    // After a lot of churn neighbouring nodes can end up far apart in the arena. Compacting puts
+    // them back next to each other. Every outstanding `Handle` is invalidated, since the live
+    // nodes all get a generation newer than any handle issued so far. The returned map takes the
+    // handle of each live element to its new handle; handles to popped elements aren't in it.
+    pub fn compact(&mut self) -> HashMap<Handle, Handle> {
+        let generation = self
+            .slots
+            .iter()
+            .map(|slot| slot.generation)
+            .fold(self.min_generation, u32::max)
+            .wrapping_add(1);
+
+        let mut slots = Vec::with_capacity(self.len);
+        let mut moved = HashMap::with_capacity(self.len);
+        let mut cur = self.head;
+        while cur != NIL {
+            let old = Handle {
+                index: cur,
+                generation: self.slots[cur as usize].generation,
+            };
+            let slot = &mut self.slots[cur as usize];
+            cur = slot.next;
+            // the node we're moving is followed by the next one we'll move
+            let index = slots.len() as Index;
+            moved.insert(old, Handle { index, generation });
+            slots.push(Slot {
+                elem: slot.elem.take(),
+                generation,
+                next: if cur == NIL { NIL } else { index + 1 },
+            });
+        }
+
+        self.slots = slots;
+        self.head = if self.len == 0 { NIL } else { 0 };
+        self.free = NIL;
+        // Slots past the end may be recreated later. Start them at the new generation too, so
+        // they don't match handles issued for the slots that used to be there.
+        self.min_generation = generation;
+        moved
+    }
+
+    fn slot(&self, index: Index) -> Option<&Slot<T>> {
+        self.slots.get(index as usize)
+    }
+
+    fn slot_mut(&mut self, index: Index) -> Option<&mut Slot<T>> {
+        self.slots.get_mut(index as usize)
+    }
+}
+
+impl<T> Default for List<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Build a list in iteration order, like `second::List`. Each node is linked to the slot right
+// after it, so the new list is laid out like a freshly compacted one.
+impl<T> FromIterator<T> for List<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut list = List::new();
+        for elem in iter {
+            let index = list.slots.len();
+            assert!(index < NIL as usize, "arena list is full");
+            list.slots.push(Slot {
+                elem: Some(elem),
+                generation: 0,
+                next: index as Index + 1,
+            });
+        }
+        list.len = list.slots.len();
+        if let Some(last) = list.slots.last_mut() {
+            last.next = NIL;
+            list.head = 0;
+        }
+        list
+    }
+}
+
+// No Drop impl needed: dropping the `Vec` of slots is already iterative.
+
+//////////////////////////////////////////////////////////////////////////////
+// Iteration
+
+//
+// IntoIter
+//
+pub struct IntoIter<T>(List<T>);
+
+impl<T> IntoIterator for List<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter(self)
+    }
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.pop()
+    }
+}
+
+//
+// Iter
+//
+pub struct Iter<'a, T> {
+    slots: &'a [Slot<T>],
+    next: Index,
+}
+
+impl<T> List<T> {
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            slots: &self.slots,
+            next: self.head,
+        }
+    }
+}
+
+impl<'a, T> IntoIterator for &'a List<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let slots = self.slots;
+        slots.get(self.next as usize).and_then(|slot| {
+            self.next = slot.next;
+            slot.elem.as_ref()
+        })
+    }
+}
+
+//
+// IterMut
+//
+// The borrow checker can't see that following the `next` links visits every slot at most once,
+// so this walks the slots through a raw pointer. The `&'a mut List<T>` we were created from
+// guarantees nobody else touches the slots meanwhile.
+pub struct IterMut<'a, T> {
+    slots: *mut Slot<T>,
+    len: usize,
+    next: Index,
+    _boo: PhantomData<&'a mut T>,
+}
+
+impl<T> List<T> {
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        IterMut {
+            slots: self.slots.as_mut_ptr(),
+            len: self.slots.len(),
+            next: self.head,
+            _boo: PhantomData,
+        }
+    }
+}
+
+impl<'a, T> IntoIterator for &'a mut List<T> {
+    type Item = &'a mut T;
+    type IntoIter = IterMut<'a, T>;
+
+    fn into_iter(self) -> IterMut<'a, T> {
+        self.iter_mut()
+    }
+}
+
+impl<'a, T> Iterator for IterMut<'a, T> {
+    type Item = &'a mut T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next == NIL {
+            return None;
+        }
+        debug_assert!((self.next as usize) < self.len);
+        // The links form a single acyclic chain, so this slot hasn't been handed out before.
+        let slot = unsafe { &mut *self.slots.add(self.next as usize) };
+        self.next = slot.next;
+        slot.elem.as_mut()
+    }
+}
+
+// The raw pointer opts us out of these; IterMut is really a `&'a mut` into the slots.
+unsafe impl<'a, T: Send> Send for IterMut<'a, T> {}
+unsafe impl<'a, T: Sync> Sync for IterMut<'a, T> {}
+
+//////////////////////////////////////////////////////////////////////////////
+// TESTS
+
+#[cfg(test)]
+mod test {
+    use super::List;
+
+    #[test]
+    fn basic() {
+        let mut list = List::new();
+
+        // test pop on empty list
+        assert_eq!(list.pop(), None);
+        assert!(list.is_empty());
+
+        // push items onto the list
+        list.push(0);
+        list.push(1);
+        list.push(2);
+        assert_eq!(list.len(), 3);
+
+        // check the popped items
+        assert_eq!(list.pop(), Some(2));
+        assert_eq!(list.pop(), Some(1));
+
+        // push more onto the pre-popped list
+        list.push(3);
+        list.push(4);
+
+        assert_eq!(list.pop(), Some(4));
+        assert_eq!(list.pop(), Some(3));
+        assert_eq!(list.pop(), Some(0));
+
+        // test for exhaustion
+        assert_eq!(list.pop(), None);
+        assert_eq!(list.len(), 0);
+    }
+
+    #[test]
+    fn peek() {
+        let mut list = List::new();
+        assert_eq!(list.peek(), None);
+        assert_eq!(list.peek_mut(), None);
+        list.push(1);
+        list.push(2);
+        assert_eq!(list.peek(), Some(&2));
+        if let Some(x) = list.peek_mut() {
+            *x = 42;
+        }
+        assert_eq!(list.pop(), Some(42));
+        assert_eq!(list.peek(), Some(&1));
+    }
+
+    #[test]
+    fn iters() {
+        let mut list: List<i32> = vec![1, 2, 3].into_iter().collect();
+        assert_eq!(list.iter().collect::<Vec<_>>(), vec![&1, &2, &3]);
+
+        for x in &mut list {
+            *x *= 10;
+        }
+        let mut iter_mut = list.iter_mut();
+        assert_eq!(iter_mut.next(), Some(&mut 10));
+        assert_eq!(iter_mut.next(), Some(&mut 20));
+        assert_eq!(iter_mut.next(), Some(&mut 30));
+        assert_eq!(iter_mut.next(), None);
+
+        let mut into_iter = list.into_iter();
+        assert_eq!(into_iter.next(), Some(10));
+        assert_eq!(into_iter.next(), Some(20));
+        assert_eq!(into_iter.next(), Some(30));
+        assert_eq!(into_iter.next(), None);
+    }
+
+    #[test]
+    fn slots_are_reused() {
+        let mut list = List::new();
+        list.push(0);
+        for i in 1..100 {
+            list.push(i);
+            assert_eq!(list.pop(), Some(i));
+        }
+        assert_eq!(list.slots.len(), 2);
+        assert_eq!(list.iter().collect::<Vec<_>>(), vec![&0]);
+    }
+
+    #[test]
+    fn handles() {
+        let mut list = List::new();
+        let a = list.push("a");
+        let b = list.push("b");
+        assert_eq!(list.get(a), Some(&"a"));
+        assert_eq!(list.get(b), Some(&"b"));
+
+        *list.get_mut(a).unwrap() = "A";
+        assert_eq!(list.iter().collect::<Vec<_>>(), vec![&"b", &"A"]);
+
+        // a popped element's handle goes stale...
+        assert_eq!(list.pop(), Some("b"));
+        assert_eq!(list.get(b), None);
+        assert_eq!(list.get_mut(b), None);
+
+        // ...and stays stale when its slot is reused
+        let c = list.push("c");
+        assert_eq!(c.index, b.index);
+        assert_eq!(list.get(b), None);
+        assert_eq!(list.get(c), Some(&"c"));
+        assert_eq!(list.get(a), Some(&"A"));
+    }
+
+    #[test]
+    fn compact() {
+        let mut list = List::new();
+        let mut handles = Vec::new();
+        for i in 0..10 {
+            handles.push(list.push(i));
+        }
+        // churn: free some slots, then refill part of them out of order
+        for _ in 0..6 {
+            list.pop();
+        }
+        handles.push(list.push(100));
+        handles.push(list.push(101));
+        assert_eq!(list.slots.len(), 10);
+        let before: Vec<i32> = list.iter().copied().collect();
+
+        let moved = list.compact();
+
+        // same contents, laid out in order at the front of the arena
+        assert_eq!(list.iter().copied().collect::<Vec<_>>(), before);
+        assert_eq!(list.slots.len(), 6);
+        assert_eq!(list.head, 0);
+        for (i, slot) in list.slots.iter().enumerate().take(5) {
+            assert_eq!(slot.next as usize, i + 1);
+        }
+
+        // Every old handle is stale, even ones whose index is now occupied. The live elements'
+        // handles map to new ones that reach the same elements.
+        assert_eq!(moved.len(), 6);
+        for &handle in &handles {
+            assert_eq!(list.get(handle), None);
+        }
+        for (i, handle) in handles.iter().enumerate() {
+            // 4 to 9 were popped, and two of their slots were reused for 100 and 101
+            let elem = match i {
+                0..=3 => Some(i as i32),
+                10 => Some(100),
+                11 => Some(101),
+                _ => None,
+            };
+            let new = moved.get(handle).copied();
+            assert_eq!(new.and_then(|new| list.get(new)).copied(), elem);
+        }
+        *list.get_mut(moved[&handles[11]]).unwrap() = 1010;
+        assert_eq!(list.peek(), Some(&1010));
+
+        // the list keeps working, and new slots don't revive old handles either
+        let mut fresh = Vec::new();
+        for i in 0..8 {
+            fresh.push(list.push(200 + i));
+        }
+        assert_eq!(list.get(fresh[7]), Some(&207));
+        assert_eq!(list.len(), 14);
+        for _ in 0..14 {
+            assert!(list.pop().is_some());
+        }
+        assert_eq!(list.pop(), None);
+
+        let mut empty: List<i32> = List::new();
+        assert!(empty.compact().is_empty());
+        assert_eq!(empty.pop(), None);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // too slow under Miri
+    fn test_drop() {
+        let mut list = List::new();
+        for i in 0..1000000 {
+            list.push(i);
+        }
+        // list is dropped
+    }
+}
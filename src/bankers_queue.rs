@@ -0,0 +1,427 @@
+// A persistent FIFO queue with amortized O(1) operations, built from `third::List`s.
+//
+// Features:
+//     - persistent: `push_back` and `pop_front` return a new queue, and every old version stays
+//       valid and shares its nodes with the new ones
+//     - amortized O(1) `push_back`, `pop_front` and `peek`, even when old versions are reused
+//     - O(1) `len` and `clone`
+//
+// Based on:
+// Chris Okasaki, "Purely Functional Data Structures", 1998, section 6.3.2.
+//
+// The queue is a front, holding the oldest elements in order, and a back list holding the newest
+// elements in reverse order. We push onto the back and pop from the front. When the back gets
+// longer than the front, the front becomes `front ++ reverse(back)` and the back is emptied.
+//
+// That rotation costs O(n), but the n pushes before it already paid for it. This is where
+// persistence gets in the way: with plain lists, a version that is one push away from a rotation
+// could be pushed onto again and again, and every one of those pushes would redo the O(n)
+// rotation. So the front is a lazy stream instead of a list. The rotation is suspended, and the
+// stream is only evaluated one cell at a time, as the cells are popped:
+//
+//     - `++` is incremental: each cell of `front ++ rest` costs O(1), and the cells of `rest`
+//       are only reached once `front` runs out
+//     - `reverse` is monolithic: the first cell of `reverse(back)` reverses the whole list, but
+//       it isn't reached until every element of `front` has been popped, and there were more
+//       of those than there are elements in `back`
+//
+// Every cell is memoized, and every version of the queue that shares the cell shares the work of
+// evaluating it, so no version can make the queue pay for a rotation twice.
+
+use crate::third::List;
+use std::cell::{OnceCell, RefCell};
+use std::rc::Rc;
+
+//////////////////////////////////////////////////////////////////////////////
+// Data structures
+
+pub struct Queue<T> {
+    front_len: usize,
+    // the oldest elements, oldest first
+    front: Rc<Lazy<T>>,
+    back_len: usize,
+    // the newest elements, newest first
+    back: List<T>,
+}
+
+// A stream cell, evaluated at most once and shared by every version of the queue that has it.
+struct Lazy<T> {
+    value: OnceCell<Cell<T>>,
+    // taken when the cell is evaluated
+    pending: RefCell<Option<Pending<T>>>,
+}
+
+enum Cell<T> {
+    Nil,
+    // The element is the head of the list, so it's shared rather than copied. The rest of the
+    // list isn't used: it's also the start of the next cell, if that cell came from this list.
+    Cons(List<T>, Rc<Lazy<T>>),
+}
+
+enum Pending<T> {
+    // the elements of a list that has already been evaluated
+    List(List<T>),
+    // `reverse(back)`, evaluated all at once
+    Reverse(List<T>),
+    // `front ++ rest`, evaluated one cell at a time
+    Append(Rc<Lazy<T>>, Rc<Lazy<T>>),
+}
+
+pub struct Iter<'a, T> {
+    front: &'a Lazy<T>,
+    // the back of the queue, popped from the end
+    back: Vec<&'a T>,
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Implementation
+
+impl<T> Lazy<T> {
+    fn evaluated(cell: Cell<T>) -> Rc<Self> {
+        Rc::new(Lazy {
+            value: OnceCell::from(cell),
+            pending: RefCell::new(None),
+        })
+    }
+
+    fn suspended(pending: Pending<T>) -> Rc<Self> {
+        Rc::new(Lazy {
+            value: OnceCell::new(),
+            pending: RefCell::new(Some(pending)),
+        })
+    }
+
+    // Move out the streams this cell refers to, so they can be dropped without recursing.
+    fn take_children(&mut self, children: &mut Vec<Rc<Lazy<T>>>) {
+        if let Some(Cell::Cons(_, rest)) = self.value.take() {
+            children.push(rest);
+        }
+        if let Some(Pending::Append(front, rest)) = self.pending.get_mut().take() {
+            children.push(front);
+            children.push(rest);
+        }
+    }
+}
+
+impl<T: Clone> Lazy<T> {
+    // Evaluate the cell, or return it if it already has been.
+    fn force(&self) -> &Cell<T> {
+        self.value.get_or_init(|| {
+            let pending = self.pending.borrow_mut().take();
+            match pending {
+                Some(Pending::List(list)) => list_cell(list),
+                Some(Pending::Reverse(back)) => list_cell(reverse(&back)),
+                // `front` is a suffix of an older front, and `++`s only nest as deep as there
+                // have been rotations, which is O(log n), so this recursion is shallow
+                Some(Pending::Append(front, rest)) => match front.force() {
+                    Cell::Nil => rest.force().clone(),
+                    Cell::Cons(list, front) => Cell::Cons(
+                        list.clone(),
+                        Lazy::suspended(Pending::Append(Rc::clone(front), rest)),
+                    ),
+                },
+                None => panic!("stream cell was lost by a panic while it was evaluated"),
+            }
+        })
+    }
+}
+
+// The first cell of a stream of the elements of `list`.
+fn list_cell<T>(list: List<T>) -> Cell<T> {
+    match list.tail() {
+        Some(tail) => Cell::Cons(list, Lazy::suspended(Pending::List(tail))),
+        None => Cell::Nil,
+    }
+}
+
+// Return `list` reversed. The elements are copied.
+fn reverse<T: Clone>(list: &List<T>) -> List<T> {
+    let mut reversed = List::new();
+    for elem in list.iter() {
+        reversed = reversed.append(elem.clone());
+    }
+    reversed
+}
+
+// O(1): a cell is a list handle and a pointer to the next cell.
+impl<T> Clone for Cell<T> {
+    fn clone(&self) -> Self {
+        match self {
+            Cell::Nil => Cell::Nil,
+            Cell::Cons(list, rest) => Cell::Cons(list.clone(), Rc::clone(rest)),
+        }
+    }
+}
+
+// A non-recursive Drop implementation, for long streams.
+impl<T> Drop for Lazy<T> {
+    fn drop(&mut self) {
+        let mut children = Vec::new();
+        self.take_children(&mut children);
+        while let Some(child) = children.pop() {
+            // if some other stream still shares the child, it's not ours to take apart
+            if let Ok(mut lazy) = Rc::try_unwrap(child) {
+                lazy.take_children(&mut children);
+            }
+        }
+    }
+}
+
+impl<T> Queue<T> {
+    pub fn new() -> Self {
+        Queue {
+            front_len: 0,
+            front: Lazy::evaluated(Cell::Nil),
+            back_len: 0,
+            back: List::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.front_len + self.back_len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T: Clone> Queue<T> {
+    // peek at the oldest element
+    pub fn peek(&self) -> Option<&T> {
+        match self.front.force() {
+            Cell::Cons(list, _) => list.head(),
+            Cell::Nil => None,
+        }
+    }
+
+    // return a new queue with `elem` pushed onto the back
+    pub fn push_back(&self, elem: T) -> Queue<T> {
+        Queue {
+            front_len: self.front_len,
+            front: Rc::clone(&self.front),
+            back_len: self.back_len + 1,
+            back: self.back.append(elem),
+        }
+        .check()
+    }
+
+    // return the oldest element and a new queue without it, or None if the queue is empty
+    pub fn pop_front(&self) -> Option<(T, Queue<T>)> {
+        let (list, rest) = match self.front.force() {
+            Cell::Cons(list, rest) => (list, rest),
+            Cell::Nil => return None,
+        };
+        let elem = list.head()?.clone();
+        let rest = Queue {
+            front_len: self.front_len - 1,
+            front: Rc::clone(rest),
+            back_len: self.back_len,
+            back: self.back.clone(),
+        }
+        .check();
+        Some((elem, rest))
+    }
+
+    // iterate from the oldest element to the newest
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            front: &self.front,
+            back: self.back.iter().collect(),
+        }
+    }
+
+    // Restore the invariant: the back is never longer than the front.
+    fn check(self) -> Self {
+        if self.back_len <= self.front_len {
+            return self;
+        }
+        let rest = Lazy::suspended(Pending::Reverse(self.back));
+        Queue {
+            front_len: self.front_len + self.back_len,
+            front: Lazy::suspended(Pending::Append(self.front, rest)),
+            back_len: 0,
+            back: List::new(),
+        }
+    }
+}
+
+impl<T> Default for Queue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// O(1): the new queue shares everything with this one.
+impl<T> Clone for Queue<T> {
+    fn clone(&self) -> Self {
+        Queue {
+            front_len: self.front_len,
+            front: Rc::clone(&self.front),
+            back_len: self.back_len,
+            back: self.back.clone(),
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Iteration
+
+impl<'a, T: Clone> IntoIterator for &'a Queue<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+// Iterating evaluates the front, like popping every element would.
+impl<'a, T: Clone> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.front.force() {
+            Cell::Cons(list, rest) => {
+                self.front = rest;
+                list.head()
+            }
+            Cell::Nil => self.back.pop(),
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Tests
+
+#[cfg(test)]
+mod test {
+    use super::Queue;
+    use std::cell::Cell;
+    use std::collections::VecDeque;
+
+    #[test]
+    fn basics() {
+        let queue = Queue::new();
+        assert!(queue.pop_front().is_none());
+        assert!(queue.is_empty());
+
+        let queue = queue.push_back(1).push_back(2).push_back(3);
+        assert_eq!(queue.len(), 3);
+        assert_eq!(queue.peek(), Some(&1));
+
+        let (x, queue) = queue.pop_front().unwrap();
+        assert_eq!(x, 1);
+        let queue = queue.push_back(4).push_back(5);
+        assert_eq!(queue.iter().collect::<Vec<_>>(), vec![&2, &3, &4, &5]);
+
+        let (x, queue) = queue.pop_front().unwrap();
+        assert_eq!(x, 2);
+        let (x, queue) = queue.pop_front().unwrap();
+        assert_eq!(x, 3);
+        let (x, queue) = queue.pop_front().unwrap();
+        assert_eq!(x, 4);
+        let (x, queue) = queue.pop_front().unwrap();
+        assert_eq!(x, 5);
+        assert!(queue.pop_front().is_none());
+        assert_eq!(queue.peek(), None);
+    }
+
+    #[test]
+    fn old_versions_are_unchanged() {
+        let mut versions = vec![Queue::new()];
+        for i in 0..20 {
+            let next = versions.last().unwrap().push_back(i);
+            versions.push(next);
+        }
+        for _ in 0..10 {
+            let (_, next) = versions.last().unwrap().pop_front().unwrap();
+            versions.push(next);
+        }
+
+        for (i, queue) in versions.iter().enumerate() {
+            let expected: Vec<i32> = if i <= 20 {
+                (0..i as i32).collect()
+            } else {
+                (i as i32 - 20..20).collect()
+            };
+            assert_eq!(queue.iter().copied().collect::<Vec<_>>(), expected);
+            assert_eq!(queue.len(), expected.len());
+            assert_eq!(queue.peek(), expected.first());
+        }
+    }
+
+    // Random pushes and pops, checked against a VecDeque.
+    #[test]
+    fn against_vecdeque() {
+        let mut queue = Queue::new();
+        let mut model = VecDeque::new();
+        let mut seed: u32 = 17;
+        for i in 0..2000 {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            if (seed >> 16).is_multiple_of(3) {
+                match queue.pop_front() {
+                    Some((x, rest)) => {
+                        assert_eq!(Some(x), model.pop_front());
+                        queue = rest;
+                    }
+                    None => assert!(model.is_empty()),
+                }
+            } else {
+                queue = queue.push_back(i);
+                model.push_back(i);
+            }
+            assert_eq!(queue.peek(), model.front());
+            assert_eq!(queue.len(), model.len());
+        }
+        assert!(queue.iter().eq(model.iter()));
+    }
+
+    // A version whose next pop has to do an expensive rotation can be popped over and over. The
+    // rotation is only ever done once.
+    #[test]
+    fn rotations_are_shared() {
+        thread_local!(static CLONES: Cell<usize> = const { Cell::new(0) });
+        struct Counted;
+        impl Clone for Counted {
+            fn clone(&self) -> Self {
+                CLONES.with(|c| c.set(c.get() + 1));
+                Counted
+            }
+        }
+
+        // The last push rotates: the front becomes the old front of 511 elements, followed by the
+        // reverse of the 512 in the back.
+        let mut queue = Queue::new();
+        for _ in 0..1023 {
+            queue = queue.push_back(Counted);
+        }
+        for _ in 0..511 {
+            queue = queue.pop_front().unwrap().1;
+        }
+
+        // The old front is used up, so the next pop has to reverse the back.
+        let before = CLONES.with(|c| c.get());
+        for _ in 0..100 {
+            drop(queue.pop_front());
+        }
+        // One reverse, which copies the 512 elements of the back, plus one clone of the popped
+        // element per pop. Appending the reversed back to the front doesn't copy the front.
+        assert_eq!(CLONES.with(|c| c.get()) - before, 512 + 100);
+    }
+
+    #[test]
+    fn test_drop() {
+        let mut queue = Queue::new();
+        for i in 0..1000000 {
+            queue = queue.push_back(i);
+        }
+        // a long chain of pops that never forces the front
+        let mut popped = queue.clone();
+        for _ in 0..1000 {
+            popped = popped.pop_front().unwrap().1;
+        }
+        assert_eq!(popped.len(), 999000);
+        // both queues are dropped here
+    }
+}
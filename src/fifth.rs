@@ -1,11 +1,22 @@
-// A basic mutable queue implementation (that doesn't end up working)
+// A mutable queue implementation using only safe code
 //
 // Features:
 //     - mutable queue API
 //     - fast push and pop
+//     - no `unsafe`
 //
 // Inspired by:
 // https://rust-unofficial.github.io/too-many-lists/second.html
+//
+// The first attempt at this queue kept a `&'a mut Node<T>` pointing at the tail. That ties
+// `push(&'a mut self)` to the lifetime of the queue itself, so after one push the queue is
+// mutably borrowed forever and can't be used again.
+//
+// Instead, the nodes live in a `Vec` owned by the queue and link to each other by index. An
+// index is just a number, so holding on to the tail doesn't borrow anything. Popped slots are
+// threaded onto a free list and reused by later pushes.
+
+#![forbid(unsafe_code)]
 
 //////////////////////////////////////////////////////////////////////////////
 // Data structures
@@ -14,73 +25,158 @@
 //
 // [] = stack
 // () = heap
-// [ptr] -> (A, Some(ptr)) -> (B, Some(ptr)) -> (C, None)
-// [ptr] ----------------------------------------^
+//
+// nodes: [(A, Some(2)), (C, None), (B, Some(1))]
+// head:  Some(0)
+// tail:  Some(1)
+//
+// which is the queue A -> B -> C. A free slot has `elem: None` and its `next` points at the
+// next free slot (if any).
 //
 
-pub struct Queue<'a, T> {
-    head: Link<T>,
-    tail: WeakLink<'a, T>,
+pub struct Queue<T> {
+    nodes: Vec<Node<T>>,
+    head: Link,
+    tail: Link,
+    free: Link,
 }
 
-type Link<T> = Option<Box<Node<T>>>;
-type WeakLink<'a, T> = Option<&'a mut Node<T>>;
+type Link = Option<usize>;
 
 struct Node<T> {
-    elem: T,
-    next: Link<T>,
+    elem: Option<T>,
+    next: Link,
 }
 
-impl<'a, T> Queue<'a, T> {
+impl<T> Queue<T> {
     pub fn new() -> Self {
         Queue {
+            nodes: Vec::new(),
             head: None,
             tail: None,
+            free: None,
         }
     }
 
-    pub fn push(&'a mut self, x: T) {
-        let new_node = Box::new(Node {
-            elem: x,
+    // Push an element onto the tail of the queue.
+    pub fn push(&mut self, x: T) {
+        let new_node = Node {
+            elem: Some(x),
             next: None, // new tail doesn't point to anything
-        });
-
-        let new_tail = match self.tail.take() {
-            Some(last_node) => {
-                // non-empty queue case
-                last_node.next = Some(new_node);
-                // last_node.next :: Option<Box<Node>>
-                // last_node.next.as_mut() :: Option<&mut Box<node>>
-                // &mut **mr_box_node :: &mut Node
-                last_node
-                    .next
-                    .as_mut()
-                    .map(|mr_box_node| &mut **mr_box_node)
+        };
+
+        // reuse a free slot if there is one, otherwise grow the vector
+        let idx = match self.free {
+            Some(idx) => {
+                self.free = self.nodes[idx].next;
+                self.nodes[idx] = new_node;
+                idx
             }
             None => {
-                // empty queue case
-                self.head = Some(new_node);
-                self.head.as_mut().map(|mr_box_node| &mut **mr_box_node)
+                self.nodes.push(new_node);
+                self.nodes.len() - 1
             }
         };
 
-        self.tail = new_tail;
+        match self.tail {
+            // non-empty queue case
+            Some(tail) => self.nodes[tail].next = Some(idx),
+            // empty queue case
+            None => self.head = Some(idx),
+        }
+        self.tail = Some(idx);
     }
 
+    // Pop an element off of the head of the queue.
     pub fn pop(&mut self) -> Option<T> {
-        self.head.take().map(|first_node| {
-            let first_node_val = *first_node;
-            self.head = first_node_val.next;
+        self.head.take().map(|idx| {
+            let node = &mut self.nodes[idx];
+            let elem = node
+                .elem
+                .take()
+                .expect("queue links only point at occupied slots");
+            self.head = node.next;
+
+            // put the slot on the free list
+            node.next = self.free;
+            self.free = Some(idx);
 
             if self.head.is_none() {
+                // The queue is empty, so every slot is free. Throw them away rather than
+                // keeping the high water mark around forever.
                 self.tail = None;
+                self.free = None;
+                self.nodes.clear();
             }
 
-            first_node_val.elem
+            elem
         })
     }
+
+    // Peek at the element at the head of the queue.
+    pub fn peek(&self) -> Option<&T> {
+        self.head.and_then(|idx| self.nodes[idx].elem.as_ref())
+    }
+}
+
+impl<T> Default for Queue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Iteration
+
+pub struct IntoIter<T>(Queue<T>);
+
+impl<T> IntoIterator for Queue<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter(self)
+    }
 }
 
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.pop()
+    }
+}
+
+pub struct Iter<'a, T> {
+    nodes: &'a [Node<T>],
+    next: Link,
+}
+
+impl<T> Queue<T> {
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            nodes: &self.nodes,
+            next: self.head,
+        }
+    }
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let nodes = self.nodes;
+        self.next.take().and_then(|idx| {
+            let node = &nodes[idx];
+            self.next = node.next;
+            node.elem.as_ref()
+        })
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Unit Tests
+
 #[cfg(test)]
 mod test {
     use super::Queue;
@@ -93,8 +189,84 @@ mod test {
 
         // Populate queue
         queue.push(1);
+        assert_eq!(queue.pop(), Some(1));
+        assert_eq!(queue.pop(), None);
+
+        // Check that items are popped in FIFO order
+        queue.push(2);
+        queue.push(3);
+        queue.push(4);
+        assert_eq!(queue.pop(), Some(2));
+        assert_eq!(queue.pop(), Some(3));
+
+        // Push more onto the partially popped queue
+        queue.push(5);
+        queue.push(6);
+        assert_eq!(queue.pop(), Some(4));
+        assert_eq!(queue.pop(), Some(5));
+        assert_eq!(queue.pop(), Some(6));
+        assert_eq!(queue.pop(), None);
+
+        // Check the queue still works after being exhausted
+        queue.push(7);
+        assert_eq!(queue.pop(), Some(7));
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[test]
+    fn peek() {
+        let mut queue = Queue::new();
+        assert_eq!(queue.peek(), None);
+        queue.push(1);
+        queue.push(2);
+        assert_eq!(queue.peek(), Some(&1));
+        assert_eq!(queue.pop(), Some(1));
+        assert_eq!(queue.peek(), Some(&2));
+    }
+
+    #[test]
+    fn slots_are_reused() {
+        let mut queue = Queue::new();
+        queue.push(1);
+        queue.push(2);
+        for i in 3..100 {
+            assert_eq!(queue.pop(), Some(i - 2));
+            queue.push(i);
+        }
+        // only ever two elements live at once, so popped slots must have been reused
+        assert_eq!(queue.nodes.len(), 2);
+        assert_eq!(queue.pop(), Some(98));
+        assert_eq!(queue.pop(), Some(99));
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[test]
+    fn into_iter() {
+        let mut queue = Queue::new();
+        queue.push(1);
+        queue.push(2);
+        queue.push(3);
+
+        let mut iter = queue.into_iter();
+        assert_eq!(iter.next(), Some(1));
+        assert_eq!(iter.next(), Some(2));
+        assert_eq!(iter.next(), Some(3));
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn iter() {
+        let mut queue = Queue::new();
+        queue.push(1);
+        queue.push(2);
+        queue.push(3);
+        assert_eq!(queue.pop(), Some(1));
+        queue.push(4);
 
-        // XXX doesn't work to borrow mutable queue more than once!
-        // assert_eq!(queue.pop(), Some(1));
+        let mut iter = queue.iter();
+        assert_eq!(iter.next(), Some(&2));
+        assert_eq!(iter.next(), Some(&3));
+        assert_eq!(iter.next(), Some(&4));
+        assert_eq!(iter.next(), None);
     }
 }
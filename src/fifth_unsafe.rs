@@ -6,7 +6,20 @@
 //     - uses raw pointers
 //
 // Inspired by:
-// https://rust-unofficial.github.io/too-many-lists/second.html
+// https://rust-unofficial.github.io/too-many-lists/fifth.html
+//
+// An earlier version of this queue owned its nodes through `Box`es in `head` and `next` and kept
+// a raw `tail` pointer into the last box. Moving a `Box` asserts unique ownership of its contents,
+// which under Stacked Borrows invalidates every raw pointer derived from it, so the `tail` pointer
+// was dangling (as far as the borrow model is concerned) right after each `push`.
+//
+// Now every link is a raw pointer. A node's `Box` only exists briefly: it is created and turned
+// into a raw pointer in `push`, and rebuilt with `Box::from_raw` when the node is freed in `pop`.
+// Nothing in between ever creates a `&mut Node` that could invalidate the other pointers.
+//
+// Run the tests under Miri to check this:
+//
+//     cargo +nightly miri test fifth_unsafe
 
 //////////////////////////////////////////////////////////////////////////////
 // Data structures
@@ -14,18 +27,20 @@
 // Linked list layout:
 // [] = stack
 // () = heap
-// [ptr] -> (A, Some(ptr)) -> (B, Some(ptr)) -> (C, None)
-// [ptr] ----------------------------------------^
+// [ptr] -> (A, ptr) -> (B, ptr) -> (C, null)
+// [ptr] ----------------------------^
 //
 
+use std::iter::FusedIterator;
 use std::ptr;
 
 pub struct Queue<T> {
     head: Link<T>,
-    tail: *mut Node<T>,
+    tail: Link<T>,
+    len: usize,
 }
 
-type Link<T> = Option<Box<Node<T>>>;
+type Link<T> = *mut Node<T>;
 
 struct Node<T> {
     elem: T,
@@ -35,70 +50,106 @@ struct Node<T> {
 impl<T> Queue<T> {
     pub fn new() -> Self {
         Queue {
-            head: None,
+            head: ptr::null_mut(),
             tail: ptr::null_mut(),
+            len: 0,
         }
     }
 
+    // the number of elements in the queue, in O(1)
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
     // Push an element onto the tail of the queue.
     //
-    // [ptr] -> (A, Some(ptr)) -> (B, Some(ptr)) -> (C, None)
-    // [ptr] ---------------------------------------^
+    // [ptr] -> (A, ptr) -> (B, ptr) -> (C, null)
+    // [ptr] ----------------------------^
     //
     // becomes
     //
-    // [ptr] -> (A, Some(ptr)) -> (B, Some(ptr)) -> (C, Some(ptr)) -> (x, None)
-    // [ptr] ---------------------------------------------------------^
+    // [ptr] -> (A, ptr) -> (B, ptr) -> (C, ptr) -> (x, null)
+    // [ptr] ---------------------------------------^
     //
     pub fn push(&mut self, x: T) {
-        let mut new_node = Box::new(Node {
+        // Leak the box straight away; from here on the node is only reached through raw pointers
+        // until `pop` takes ownership back.
+        let new_tail = Box::into_raw(Box::new(Node {
             elem: x,
-            next: None, // new tail doesn't point to anything
-        });
-
-        // Take a pointer to the node in the box, coerce it to a raw pointer. Box has a
-        // stable address, even when moved, so this is OK as long as we are careful not to
-        // use the raw pointer after the Box is dropped.
-        let raw_new_node: *mut _ = &mut *new_node;
+            next: ptr::null_mut(), // new tail doesn't point to anything
+        }));
 
-        // Instead of take()'ing self.tail, we branch on whether it is null or not
         if self.tail.is_null() {
-            // Case: empty queue. assign to self.head
-            self.head = Some(new_node);
+            // Case: empty queue. The new node is also the head.
+            self.head = new_tail;
         } else {
-            // Case: non-empty queue. Set next field in the node pointed to by current
-            // self.tail to the new boxed node.
+            // Case: non-empty queue. Link the current tail to the new node.
             unsafe {
-                (*self.tail).next = Some(new_node); // UNSAFE dereference
+                (*self.tail).next = new_tail; // UNSAFE dereference
             }
         }
 
-        self.tail = raw_new_node;
-        // When we pop, we have to make sure we null out this pointer because at that point
-        // the Box could be dropped (it's out of our control).
+        self.tail = new_tail;
+        self.len += 1;
     }
 
     // Pop an element off of the head of the queue.
     //
-    // [ptr] -> (A, Some(ptr)) -> (B, Some(ptr)) -> (C, None)
-    // [ptr] ---------------------------------------^
+    // [ptr] -> (A, ptr) -> (B, ptr) -> (C, null)
+    // [ptr] ----------------------------^
     //
     // becomes
     //
-    // [ptr] -> (B, Some(ptr)) -> (C, None)
-    // [ptr] ---------------------^
+    // [ptr] -> (B, ptr) -> (C, null)
+    // [ptr] ---------------^
     //
     // and A is returned (if the queue was non-empty).
     pub fn pop(&mut self) -> Option<T> {
-        // self.head :: Link === Option<Box<Node>>
-        // self.tail :: *mut Node
-        self.head.take().map(|box_node| {
-            if box_node.next.is_none() {
+        if self.head.is_null() {
+            return None;
+        }
+
+        unsafe {
+            // Take back ownership of the head node so that it is freed at the end of this scope.
+            let head = Box::from_raw(self.head);
+            self.head = head.next;
+
+            if self.head.is_null() {
+                // We just popped the last node, so `tail` points at freed memory. Null it out.
                 self.tail = ptr::null_mut();
             }
-            self.head = box_node.next;
-            box_node.elem
-        })
+
+            self.len -= 1;
+            Some(head.elem)
+        }
+    }
+
+    // Peek at the element at the head of the queue.
+    pub fn peek(&self) -> Option<&T> {
+        unsafe { self.head.as_ref().map(|node| &node.elem) }
+    }
+
+    // Peek at the element at the head of the queue, returning a mutable ref.
+    pub fn peek_mut(&mut self) -> Option<&mut T> {
+        unsafe { self.head.as_mut().map(|node| &mut node.elem) }
+    }
+}
+
+impl<T> Default for Queue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// The nodes are only reachable through raw pointers, so nothing frees them unless we do. Popping
+// everything frees them one at a time without recursion.
+impl<T> Drop for Queue<T> {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
     }
 }
 
@@ -107,8 +158,11 @@ impl<T> Queue<T> {
 
 pub struct IntoIter<T>(Queue<T>);
 
-impl<T> Queue<T> {
-    pub fn into_iter(self) -> IntoIter<T> {
+impl<T> IntoIterator for Queue<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
         IntoIter(self)
     }
 }
@@ -119,16 +173,29 @@ impl<T> Iterator for IntoIter<T> {
     fn next(&mut self) -> Option<Self::Item> {
         self.0.pop()
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.0.len, Some(self.0.len))
+    }
 }
 
+impl<T> ExactSizeIterator for IntoIter<T> {}
+impl<T> FusedIterator for IntoIter<T> {}
+
 pub struct Iter<'a, T> {
     next: Option<&'a Node<T>>,
+    len: usize,
 }
 
 impl<T> Queue<T> {
-    pub fn iter(&self) -> Iter<T> {
-        Iter {
-            next: self.head.as_ref().map(|ref_box_node| &**ref_box_node),
+    pub fn iter(&self) -> Iter<'_, T> {
+        // `as_ref` turns a possibly-null raw pointer into an Option<&Node>. The shared borrow of
+        // `self` keeps the queue from being mutated while the iterator is alive.
+        unsafe {
+            Iter {
+                next: self.head.as_ref(),
+                len: self.len,
+            }
         }
     }
 }
@@ -138,18 +205,65 @@ impl<'a, T> Iterator for Iter<'a, T> {
 
     fn next(&mut self) -> Option<Self::Item> {
         self.next.take().map(|ref_node| {
-            self.next = ref_node.next.as_ref().map(|ref_box_node| &**ref_box_node);
+            self.next = unsafe { ref_node.next.as_ref() };
+            self.len -= 1;
             &ref_node.elem
         })
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
 }
 
+impl<'a, T> ExactSizeIterator for Iter<'a, T> {}
+impl<'a, T> FusedIterator for Iter<'a, T> {}
+
+pub struct IterMut<'a, T> {
+    next: Option<&'a mut Node<T>>,
+    len: usize,
+}
+
+impl<T> Queue<T> {
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        unsafe {
+            IterMut {
+                next: self.head.as_mut(),
+                len: self.len,
+            }
+        }
+    }
+}
+
+impl<'a, T> Iterator for IterMut<'a, T> {
+    type Item = &'a mut T;
+
+    // We must take() `self.next` here because &mut is not Copy.
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next.take().map(|ref_node| {
+            self.next = unsafe { ref_node.next.as_mut() };
+            self.len -= 1;
+            &mut ref_node.elem
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, T> ExactSizeIterator for IterMut<'a, T> {}
+impl<'a, T> FusedIterator for IterMut<'a, T> {}
+
 //////////////////////////////////////////////////////////////////////////////
 // Unit Tests
 
 #[cfg(test)]
 mod test {
     use super::Queue;
+    use std::collections::VecDeque;
+    use std::rc::Rc;
+
     #[test]
     fn basics() {
         let mut queue = Queue::new();
@@ -211,4 +325,176 @@ mod test {
         assert_eq!(iter.next(), Some(&4));
         assert_eq!(iter.next(), None);
     }
+
+    #[test]
+    fn peek() {
+        let mut queue = Queue::new();
+        assert_eq!(queue.peek(), None);
+        assert_eq!(queue.peek_mut(), None);
+
+        queue.push(1);
+        queue.push(2);
+        assert_eq!(queue.peek(), Some(&1));
+        if let Some(x) = queue.peek_mut() {
+            *x = 10;
+        }
+        assert_eq!(queue.pop(), Some(10));
+        assert_eq!(queue.peek_mut(), Some(&mut 2));
+        assert_eq!(queue.pop(), Some(2));
+        assert_eq!(queue.peek(), None);
+    }
+
+    #[test]
+    fn iter_mut() {
+        let mut queue = Queue::new();
+        queue.push(1);
+        queue.push(2);
+        queue.push(3);
+
+        let mut iter = queue.iter_mut();
+        assert_eq!(iter.next(), Some(&mut 1));
+        assert_eq!(iter.next(), Some(&mut 2));
+        assert_eq!(iter.next(), Some(&mut 3));
+        assert_eq!(iter.next(), None);
+
+        // mutate through the iterator, then keep using the queue
+        for x in queue.iter_mut() {
+            *x *= 10;
+        }
+        queue.push(4);
+        assert_eq!(queue.pop(), Some(10));
+        assert_eq!(queue.iter_mut().next(), Some(&mut 20));
+        assert_eq!(queue.iter().copied().collect::<Vec<_>>(), vec![20, 30, 4]);
+    }
+
+    #[test]
+    fn len_and_size_hint() {
+        let mut queue = Queue::new();
+        assert_eq!(queue.len(), 0);
+        assert!(queue.is_empty());
+        assert_eq!(queue.pop(), None);
+        assert_eq!(queue.len(), 0);
+
+        queue.push(1);
+        queue.push(2);
+        queue.push(3);
+        assert_eq!(queue.len(), 3);
+        assert!(!queue.is_empty());
+
+        let mut iter = queue.iter();
+        assert_eq!(iter.len(), 3);
+        iter.next();
+        assert_eq!(iter.size_hint(), (2, Some(2)));
+        let mut iter_mut = queue.iter_mut();
+        iter_mut.next();
+        iter_mut.next();
+        assert_eq!(iter_mut.len(), 1);
+        iter_mut.next();
+        assert_eq!(iter_mut.len(), 0);
+        assert_eq!(iter_mut.next(), None);
+
+        assert_eq!(queue.pop(), Some(1));
+        assert_eq!(queue.len(), 2);
+        let mut into_iter = queue.into_iter();
+        assert_eq!(into_iter.len(), 2);
+        into_iter.next();
+        into_iter.next();
+        assert_eq!(into_iter.len(), 0);
+        assert_eq!(into_iter.next(), None);
+    }
+
+    // Dropping a long queue must free the nodes one at a time. A Drop impl that recursed down the
+    // `next` links would overflow the stack here.
+    #[test]
+    #[cfg_attr(miri, ignore)] // too slow under Miri
+    fn test_drop() {
+        let mut queue = Queue::new();
+        for i in 0..1000000 {
+            queue.push(i);
+        }
+        // queue is dropped
+    }
+
+    // Interleave pushes, pops and iteration so that Miri can check that none of them invalidates
+    // the pointers held by the others.
+    #[test]
+    fn miri_food() {
+        let mut queue = Queue::new();
+
+        queue.push(1);
+        queue.push(2);
+        queue.push(3);
+        assert_eq!(queue.pop(), Some(1));
+        queue.push(4);
+        assert_eq!(queue.pop(), Some(2));
+        queue.push(5);
+        assert_eq!(queue.iter().copied().collect::<Vec<_>>(), vec![3, 4, 5]);
+
+        assert_eq!(queue.pop(), Some(3));
+        assert_eq!(queue.pop(), Some(4));
+        assert_eq!(queue.iter().copied().collect::<Vec<_>>(), vec![5]);
+        queue.push(6);
+        assert_eq!(queue.pop(), Some(5));
+        assert_eq!(queue.pop(), Some(6));
+        assert_eq!(queue.iter().next(), None);
+        assert_eq!(queue.pop(), None);
+
+        // the tail must be reset properly when the queue runs dry
+        queue.push(7);
+        assert_eq!(queue.iter().copied().collect::<Vec<_>>(), vec![7]);
+        queue.push(8);
+        assert_eq!(queue.pop(), Some(7));
+        assert_eq!(queue.pop(), Some(8));
+    }
+
+    // Drive the queue and a `VecDeque` through the same pattern of operations and check that they
+    // agree after every step.
+    #[test]
+    fn interleaved_against_vecdeque() {
+        let mut queue = Queue::new();
+        let mut model = VecDeque::new();
+
+        for round in 0..50 {
+            // push a few, pop a few, with the balance shifting over the rounds
+            for i in 0..(round % 7) {
+                queue.push(round * 10 + i);
+                model.push_back(round * 10 + i);
+            }
+            for _ in 0..(round % 5) {
+                assert_eq!(queue.pop(), model.pop_front());
+            }
+            assert!(queue.iter().eq(model.iter()));
+            assert_eq!(queue.len(), model.len());
+        }
+
+        while let Some(x) = model.pop_front() {
+            assert_eq!(queue.pop(), Some(x));
+        }
+        assert_eq!(queue.pop(), None);
+    }
+
+    // Every element must be dropped exactly once, whether it is popped, left in the queue or left
+    // in a partially consumed iterator.
+    #[test]
+    fn drops_elements() {
+        let elem = Rc::new(0);
+
+        let mut queue = Queue::new();
+        for _ in 0..10 {
+            queue.push(elem.clone());
+        }
+        drop(queue.pop());
+        assert_eq!(Rc::strong_count(&elem), 10);
+        drop(queue);
+        assert_eq!(Rc::strong_count(&elem), 1);
+
+        let mut queue = Queue::new();
+        for _ in 0..10 {
+            queue.push(elem.clone());
+        }
+        let mut iter = queue.into_iter();
+        drop(iter.next());
+        drop(iter);
+        assert_eq!(Rc::strong_count(&elem), 1);
+    }
 }
@@ -0,0 +1,992 @@
+// A persistent sequence: a 2-3 finger tree annotated with a monoid measure.
+//
+// Features:
+//     - push, peek and pop at both ends in amortized O(1), even when old versions are reused
+//     - concatenation and splitting in O(log n)
+//     - persistent, with `Rc` sharing: every operation returns a new tree and leaves the old one
+//       alone, and the two share all but O(log n) nodes
+//     - every subtree caches its measure: a user-supplied monoid summary of its elements. With
+//       `Size` (the default) that's the number of elements, which gives indexing and `split_at`.
+//       With a "max" measure it's a priority queue, and with a "last key" measure an ordered
+//       sequence; `split` and `find` search by any measure in O(log n).
+//
+// Based on:
+// Ralf Hinze and Ross Paterson, "Finger trees: a simple general-purpose data structure", Journal
+// of Functional Programming, 2006.
+//
+// A finger tree is either empty, a single node, or "deep": a prefix and a suffix of 1 to 4 nodes
+// each (the "digits"), and a finger tree of 2-3 nodes in the middle. Each level down, the nodes
+// hold 2-3 nodes of the level above, so the tree is O(log n) levels deep and both ends are always
+// within reach:
+//
+// level 0:  [a b]  ---------------  middle  ---------------  [y z]
+// level 1:           [(c d) (e f g)] --- middle --- [(w x)]
+// level 2:                            ...
+//
+// In the paper every level has a different type (`FingerTree (Node a)`), which Rust's generics
+// can't express. Here nodes are untyped: a `Node` is either an element or a branch of 2-3 nodes,
+// and the code keeps every level at the right depth.
+//
+// Pushing onto a full digit moves three nodes down a level, and popping the last node of a digit
+// borrows one from the level below. Either can cascade down every level, which the earlier cheap
+// operations pay for. With persistence, a version that's one push away from a cascade could be
+// pushed onto again and again, and every one of those pushes would redo it. So, as in the paper,
+// the middle tree is lazy: what happens to it is suspended, and memoized. It's done the first
+// time anybody needs the middle, and every version that shares the suspension shares the result.
+// The middle an operation is suspended on is always evaluated first, so suspensions never pile
+// up more than one per level.
+
+use crate::{random_access, second, third};
+use std::cell::{OnceCell, RefCell};
+use std::fmt::{self, Debug};
+use std::iter::FromIterator;
+use std::rc::Rc;
+
+//////////////////////////////////////////////////////////////////////////////
+// Measures
+
+// A monoid that summarizes a sequence of elements. `combine` must be associative, with `identity`
+// as its identity element; the measure of a sequence is the `combine` of its elements' measures.
+pub trait Measure<T> {
+    type Value: Clone;
+
+    fn identity() -> Self::Value;
+    fn combine(left: &Self::Value, right: &Self::Value) -> Self::Value;
+    fn measure(elem: &T) -> Self::Value;
+}
+
+// The number of elements.
+pub struct Size;
+
+impl<T> Measure<T> for Size {
+    type Value = usize;
+
+    fn identity() -> usize {
+        0
+    }
+
+    fn combine(left: &usize, right: &usize) -> usize {
+        left + right
+    }
+
+    fn measure(_: &T) -> usize {
+        1
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Data structures
+
+pub struct FingerTree<T, M: Measure<T> = Size> {
+    tree: Tree<T, M>,
+}
+
+enum Tree<T, M: Measure<T>> {
+    Empty,
+    Single(Link<T, M>),
+    Deep(Rc<Deep<T, M>>),
+}
+
+struct Deep<T, M: Measure<T>> {
+    measure: M::Value,
+    prefix: Digit<T, M>,
+    // one level down: every node in here is a branch of nodes from this level
+    middle: Rc<Lazy<T, M>>,
+    suffix: Digit<T, M>,
+}
+
+// A suspended tree, evaluated at most once and shared by every version of the tree that has it.
+struct Lazy<T, M: Measure<T>> {
+    value: OnceCell<Tree<T, M>>,
+    // taken when the tree is evaluated
+    pending: RefCell<Option<Pending<T, M>>>,
+}
+
+// What to do to an evaluated tree to get the suspended one.
+enum Pending<T, M: Measure<T>> {
+    PushFront(Link<T, M>, Tree<T, M>),
+    PushBack(Tree<T, M>, Link<T, M>),
+    // the tree without its first node
+    PopFront(Tree<T, M>),
+    // the tree without its last node
+    PopBack(Tree<T, M>),
+}
+
+enum Node<T, M: Measure<T>> {
+    Leaf(T),
+    // 2 or 3 nodes from the level below
+    Branch(M::Value, Vec<Link<T, M>>),
+}
+
+type Link<T, M> = Rc<Node<T, M>>;
+
+// 1 to 4 nodes (0 while a tree is being taken apart)
+type Digit<T, M> = Vec<Link<T, M>>;
+
+pub struct Iter<'a, T, M: Measure<T>> {
+    // what's left to walk, next one last
+    stack: Vec<Part<'a, T, M>>,
+}
+
+enum Part<'a, T, M: Measure<T>> {
+    Tree(&'a Tree<T, M>),
+    Node(&'a Node<T, M>),
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Implementation
+
+impl<T, M: Measure<T>> Node<T, M> {
+    fn measure(&self) -> M::Value {
+        match self {
+            Node::Leaf(elem) => M::measure(elem),
+            Node::Branch(measure, _) => measure.clone(),
+        }
+    }
+
+    fn branch(children: Vec<Link<T, M>>) -> Rc<Self> {
+        Rc::new(Node::Branch(measure_of(&children), children))
+    }
+
+    // The children of a branch, as a digit.
+    fn children(&self) -> &[Link<T, M>] {
+        match self {
+            Node::Branch(_, children) => children,
+            Node::Leaf(_) => unreachable!("elements are only ever at the top level"),
+        }
+    }
+
+    fn elem(&self) -> &T {
+        match self {
+            Node::Leaf(elem) => elem,
+            Node::Branch(..) => unreachable!("branches are never at the top level"),
+        }
+    }
+}
+
+fn measure_of<T, M: Measure<T>>(nodes: &[Link<T, M>]) -> M::Value {
+    nodes
+        .iter()
+        .fold(M::identity(), |acc, node| M::combine(&acc, &node.measure()))
+}
+
+// Group 2 to 12 nodes into branches of 2 or 3.
+fn branches<T, M: Measure<T>>(mut nodes: Vec<Link<T, M>>) -> Vec<Link<T, M>> {
+    let mut result = Vec::new();
+    while nodes.len() > 4 {
+        let rest = nodes.split_off(3);
+        result.push(Node::branch(nodes));
+        nodes = rest;
+    }
+    if nodes.len() == 4 {
+        let rest = nodes.split_off(2);
+        result.push(Node::branch(nodes));
+        nodes = rest;
+    }
+    result.push(Node::branch(nodes));
+    result
+}
+
+// The index of the first node in a digit where `pred` becomes true, starting from the measure
+// `acc`, and the measure of everything before that node. The last node is taken if `pred` never
+// becomes true.
+fn search_digit<T, M, P>(pred: &P, acc: &M::Value, digit: &[Link<T, M>]) -> (usize, M::Value)
+where
+    M: Measure<T>,
+    P: Fn(&M::Value) -> bool,
+{
+    let mut acc = acc.clone();
+    for (i, node) in digit.iter().enumerate() {
+        let next = M::combine(&acc, &node.measure());
+        if pred(&next) || i == digit.len() - 1 {
+            return (i, acc);
+        }
+        acc = next;
+    }
+    unreachable!("digits are never empty")
+}
+
+// Split a digit around the node `search_digit` finds: (the nodes before it, the node, the nodes
+// after it).
+fn split_digit<T, M, P>(
+    pred: &P,
+    acc: &M::Value,
+    digit: &[Link<T, M>],
+) -> (Digit<T, M>, Link<T, M>, Digit<T, M>)
+where
+    M: Measure<T>,
+    P: Fn(&M::Value) -> bool,
+{
+    let (i, _) = search_digit(pred, acc, digit);
+    (
+        digit[..i].to_vec(),
+        digit[i].clone(),
+        digit[i + 1..].to_vec(),
+    )
+}
+
+impl<T, M: Measure<T>> Lazy<T, M> {
+    fn evaluated(tree: Tree<T, M>) -> Rc<Self> {
+        Rc::new(Lazy {
+            value: OnceCell::from(tree),
+            pending: RefCell::new(None),
+        })
+    }
+
+    fn suspended(pending: Pending<T, M>) -> Rc<Self> {
+        Rc::new(Lazy {
+            value: OnceCell::new(),
+            pending: RefCell::new(Some(pending)),
+        })
+    }
+
+    // Evaluate the tree, or return it if it already has been.
+    fn force(&self) -> &Tree<T, M> {
+        self.value.get_or_init(|| match self.pending.take() {
+            Some(Pending::PushFront(node, tree)) => tree.push_front(node),
+            Some(Pending::PushBack(tree, node)) => tree.push_back(node),
+            Some(Pending::PopFront(tree)) => tree.view_front().map_or(Tree::Empty, |(_, t)| t),
+            Some(Pending::PopBack(tree)) => tree.view_back().map_or(Tree::Empty, |(t, _)| t),
+            None => panic!("suspended tree was lost by a panic while it was evaluated"),
+        })
+    }
+}
+
+impl<T, M: Measure<T>> Tree<T, M> {
+    fn measure(&self) -> M::Value {
+        match self {
+            Tree::Empty => M::identity(),
+            Tree::Single(node) => node.measure(),
+            Tree::Deep(deep) => deep.measure.clone(),
+        }
+    }
+
+    // A deep tree. This needs the measure of the middle, so it evaluates it.
+    fn deep(prefix: Digit<T, M>, middle: Rc<Lazy<T, M>>, suffix: Digit<T, M>) -> Self {
+        let measure = M::combine(
+            &M::combine(&measure_of(&prefix), &middle.force().measure()),
+            &measure_of(&suffix),
+        );
+        Tree::with_measure(measure, prefix, middle, suffix)
+    }
+
+    // A deep tree whose measure is already known, which leaves the middle alone.
+    fn with_measure(
+        measure: M::Value,
+        prefix: Digit<T, M>,
+        middle: Rc<Lazy<T, M>>,
+        suffix: Digit<T, M>,
+    ) -> Self {
+        Tree::Deep(Rc::new(Deep {
+            measure,
+            prefix,
+            middle,
+            suffix,
+        }))
+    }
+
+    fn front_node(&self) -> Option<&Link<T, M>> {
+        match self {
+            Tree::Empty => None,
+            Tree::Single(node) => Some(node),
+            Tree::Deep(deep) => deep.prefix.first(),
+        }
+    }
+
+    fn back_node(&self) -> Option<&Link<T, M>> {
+        match self {
+            Tree::Empty => None,
+            Tree::Single(node) => Some(node),
+            Tree::Deep(deep) => deep.suffix.last(),
+        }
+    }
+
+    fn from_digit(digit: &[Link<T, M>]) -> Self {
+        digit
+            .iter()
+            .fold(Tree::Empty, |tree, node| tree.push_back(node.clone()))
+    }
+
+    fn push_front(&self, node: Link<T, M>) -> Self {
+        match self {
+            Tree::Empty => Tree::Single(node),
+            Tree::Single(other) => {
+                let middle = Lazy::evaluated(Tree::Empty);
+                Tree::deep(vec![node], middle, vec![other.clone()])
+            }
+            Tree::Deep(deep) => {
+                let measure = M::combine(&node.measure(), &deep.measure);
+                if let [b, c, d, e] = &deep.prefix[..] {
+                    // the prefix is full, move three of it down a level, later
+                    let branch = Node::branch(vec![c.clone(), d.clone(), e.clone()]);
+                    let pending = Pending::PushFront(branch, deep.middle.force().clone());
+                    let middle = Lazy::suspended(pending);
+                    Tree::with_measure(measure, vec![node, b.clone()], middle, deep.suffix.clone())
+                } else {
+                    let mut prefix = vec![node];
+                    prefix.extend(deep.prefix.iter().cloned());
+                    Tree::with_measure(measure, prefix, deep.middle.clone(), deep.suffix.clone())
+                }
+            }
+        }
+    }
+
+    fn push_back(&self, node: Link<T, M>) -> Self {
+        match self {
+            Tree::Empty => Tree::Single(node),
+            Tree::Single(other) => {
+                let middle = Lazy::evaluated(Tree::Empty);
+                Tree::deep(vec![other.clone()], middle, vec![node])
+            }
+            Tree::Deep(deep) => {
+                let measure = M::combine(&deep.measure, &node.measure());
+                if let [e, d, c, b] = &deep.suffix[..] {
+                    // the suffix is full, move three of it down a level, later
+                    let branch = Node::branch(vec![e.clone(), d.clone(), c.clone()]);
+                    let pending = Pending::PushBack(deep.middle.force().clone(), branch);
+                    let middle = Lazy::suspended(pending);
+                    Tree::with_measure(measure, deep.prefix.clone(), middle, vec![b.clone(), node])
+                } else {
+                    let mut suffix = deep.suffix.clone();
+                    suffix.push(node);
+                    Tree::with_measure(measure, deep.prefix.clone(), deep.middle.clone(), suffix)
+                }
+            }
+        }
+    }
+
+    // The first node, and the tree without it.
+    fn view_front(&self) -> Option<(Link<T, M>, Self)> {
+        match self {
+            Tree::Empty => None,
+            Tree::Single(node) => Some((node.clone(), Tree::Empty)),
+            Tree::Deep(deep) => Some((
+                deep.prefix[0].clone(),
+                Tree::deep_front(deep.prefix[1..].to_vec(), &deep.middle, deep.suffix.clone()),
+            )),
+        }
+    }
+
+    // The tree without its last node, and the last node.
+    fn view_back(&self) -> Option<(Self, Link<T, M>)> {
+        match self {
+            Tree::Empty => None,
+            Tree::Single(node) => Some((Tree::Empty, node.clone())),
+            Tree::Deep(deep) => {
+                let (last, suffix) = deep.suffix.split_last()?;
+                Some((
+                    Tree::deep_back(deep.prefix.clone(), &deep.middle, suffix.to_vec()),
+                    last.clone(),
+                ))
+            }
+        }
+    }
+
+    // A deep tree whose prefix may be empty, in which case it borrows one from the middle. Only
+    // the first node of the middle is needed for that; taking it off the middle is suspended.
+    fn deep_front(prefix: Digit<T, M>, middle: &Rc<Lazy<T, M>>, suffix: Digit<T, M>) -> Self {
+        if !prefix.is_empty() {
+            return Tree::deep(prefix, middle.clone(), suffix);
+        }
+        let middle = middle.force();
+        match middle.front_node() {
+            Some(node) => {
+                let measure = M::combine(&middle.measure(), &measure_of(&suffix));
+                let rest = Lazy::suspended(Pending::PopFront(middle.clone()));
+                Tree::with_measure(measure, node.children().to_vec(), rest, suffix)
+            }
+            None => Tree::from_digit(&suffix),
+        }
+    }
+
+    // A deep tree whose suffix may be empty, in which case it borrows one from the middle.
+    fn deep_back(prefix: Digit<T, M>, middle: &Rc<Lazy<T, M>>, suffix: Digit<T, M>) -> Self {
+        if !suffix.is_empty() {
+            return Tree::deep(prefix, middle.clone(), suffix);
+        }
+        let middle = middle.force();
+        match middle.back_node() {
+            Some(node) => {
+                let measure = M::combine(&measure_of(&prefix), &middle.measure());
+                let rest = Lazy::suspended(Pending::PopBack(middle.clone()));
+                Tree::with_measure(measure, prefix, rest, node.children().to_vec())
+            }
+            None => Tree::from_digit(&prefix),
+        }
+    }
+
+    // `left`, then the nodes `between`, then `right`.
+    fn concat(left: &Self, between: Vec<Link<T, M>>, right: &Self) -> Self {
+        match (left, right) {
+            (Tree::Empty, _) => between
+                .into_iter()
+                .rev()
+                .fold(right.clone(), |tree, node| tree.push_front(node)),
+            (_, Tree::Empty) => between
+                .into_iter()
+                .fold(left.clone(), |tree, node| tree.push_back(node)),
+            (Tree::Single(node), _) => {
+                Tree::concat(&Tree::Empty, between, right).push_front(node.clone())
+            }
+            (_, Tree::Single(node)) => {
+                Tree::concat(left, between, &Tree::Empty).push_back(node.clone())
+            }
+            (Tree::Deep(l), Tree::Deep(r)) => {
+                // everything that meets in the middle goes down a level
+                let mut nodes = l.suffix.clone();
+                nodes.extend(between);
+                nodes.extend(r.prefix.iter().cloned());
+                let middle = Tree::concat(l.middle.force(), branches(nodes), r.middle.force());
+                Tree::deep(l.prefix.clone(), Lazy::evaluated(middle), r.suffix.clone())
+            }
+        }
+    }
+
+    // Split a non-empty tree at the first node where `pred` becomes true, starting from the
+    // measure `acc`: (the tree before it, the node, the tree after it).
+    fn split<P>(&self, pred: &P, acc: &M::Value) -> (Self, Link<T, M>, Self)
+    where
+        P: Fn(&M::Value) -> bool,
+    {
+        let deep = match self {
+            Tree::Empty => unreachable!("only non-empty trees are split"),
+            Tree::Single(node) => return (Tree::Empty, node.clone(), Tree::Empty),
+            Tree::Deep(deep) => deep,
+        };
+
+        let acc_prefix = M::combine(acc, &measure_of(&deep.prefix));
+        if pred(&acc_prefix) {
+            let (before, node, after) = split_digit(pred, acc, &deep.prefix);
+            return (
+                Tree::from_digit(&before),
+                node,
+                Tree::deep_front(after, &deep.middle, deep.suffix.clone()),
+            );
+        }
+
+        let middle = deep.middle.force();
+        let acc_middle = M::combine(&acc_prefix, &middle.measure());
+        if pred(&acc_middle) {
+            // split the middle at a branch, then split the branch
+            let (middle_before, branch, middle_after) = middle.split(pred, &acc_prefix);
+            let acc = M::combine(&acc_prefix, &middle_before.measure());
+            let (before, node, after) = split_digit(pred, &acc, branch.children());
+            return (
+                Tree::deep_back(deep.prefix.clone(), &Lazy::evaluated(middle_before), before),
+                node,
+                Tree::deep_front(after, &Lazy::evaluated(middle_after), deep.suffix.clone()),
+            );
+        }
+
+        let (before, node, after) = split_digit(pred, &acc_middle, &deep.suffix);
+        (
+            Tree::deep_back(deep.prefix.clone(), &deep.middle, before),
+            node,
+            Tree::from_digit(&after),
+        )
+    }
+
+    // Like `split`, but only find the node, without building the trees around it. Also returns
+    // the measure of everything before the node.
+    fn find<P>(&self, pred: &P, acc: &M::Value) -> Option<(&Link<T, M>, M::Value)>
+    where
+        P: Fn(&M::Value) -> bool,
+    {
+        let deep = match self {
+            Tree::Empty => return None,
+            Tree::Single(node) => return Some((node, acc.clone())),
+            Tree::Deep(deep) => deep,
+        };
+
+        let acc_prefix = M::combine(acc, &measure_of(&deep.prefix));
+        if pred(&acc_prefix) {
+            let (i, acc) = search_digit(pred, acc, &deep.prefix);
+            return Some((&deep.prefix[i], acc));
+        }
+
+        let middle = deep.middle.force();
+        let acc_middle = M::combine(&acc_prefix, &middle.measure());
+        if pred(&acc_middle) {
+            // find the branch in the middle, then the node in the branch
+            let (branch, acc) = middle.find(pred, &acc_prefix)?;
+            let (i, acc) = search_digit(pred, &acc, branch.children());
+            return Some((&branch.children()[i], acc));
+        }
+
+        let (i, acc) = search_digit(pred, &acc_middle, &deep.suffix);
+        Some((&deep.suffix[i], acc))
+    }
+}
+
+impl<T, M: Measure<T>> Clone for Tree<T, M> {
+    fn clone(&self) -> Self {
+        match self {
+            Tree::Empty => Tree::Empty,
+            Tree::Single(node) => Tree::Single(node.clone()),
+            Tree::Deep(deep) => Tree::Deep(deep.clone()),
+        }
+    }
+}
+
+impl<T, M: Measure<T>> FingerTree<T, M> {
+    pub fn new() -> Self {
+        FingerTree { tree: Tree::Empty }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        matches!(self.tree, Tree::Empty)
+    }
+
+    // The measure of the whole sequence, in O(1).
+    pub fn measure(&self) -> M::Value {
+        self.tree.measure()
+    }
+
+    pub fn push_front(&self, elem: T) -> Self {
+        FingerTree {
+            tree: self.tree.push_front(Rc::new(Node::Leaf(elem))),
+        }
+    }
+
+    pub fn push_back(&self, elem: T) -> Self {
+        FingerTree {
+            tree: self.tree.push_back(Rc::new(Node::Leaf(elem))),
+        }
+    }
+
+    pub fn front(&self) -> Option<&T> {
+        match &self.tree {
+            Tree::Empty => None,
+            Tree::Single(node) => Some(node.elem()),
+            Tree::Deep(deep) => Some(deep.prefix[0].elem()),
+        }
+    }
+
+    pub fn back(&self) -> Option<&T> {
+        match &self.tree {
+            Tree::Empty => None,
+            Tree::Single(node) => Some(node.elem()),
+            Tree::Deep(deep) => deep.suffix.last().map(|node| node.elem()),
+        }
+    }
+
+    // the first element, and the sequence without it
+    pub fn pop_front(&self) -> Option<(&T, Self)> {
+        let (_, tree) = self.tree.view_front()?;
+        Some((self.front()?, FingerTree { tree }))
+    }
+
+    // the last element, and the sequence without it
+    pub fn pop_back(&self) -> Option<(&T, Self)> {
+        let (tree, _) = self.tree.view_back()?;
+        Some((self.back()?, FingerTree { tree }))
+    }
+
+    // `self` followed by `other`, in O(log(min(n, m))).
+    pub fn concat(&self, other: &Self) -> Self {
+        FingerTree {
+            tree: Tree::concat(&self.tree, Vec::new(), &other.tree),
+        }
+    }
+
+    // Split the sequence in two, at the first element where `pred` becomes true of the measure
+    // of everything up to and including it. That element starts the second half. `pred` has to
+    // be monotone: once it's true of a prefix, it must stay true of every longer one. If it's
+    // never true, the second half is empty.
+    pub fn split<P>(&self, pred: P) -> (Self, Self)
+    where
+        P: Fn(&M::Value) -> bool,
+    {
+        if self.is_empty() || !pred(&self.measure()) {
+            return (self.clone(), FingerTree::new());
+        }
+        let (before, node, after) = self.tree.split(&pred, &M::identity());
+        (
+            FingerTree { tree: before },
+            FingerTree {
+                tree: after.push_front(node),
+            },
+        )
+    }
+
+    // The element `split` would split at, without splitting. None if `pred` is never true.
+    pub fn find<P>(&self, pred: P) -> Option<&T>
+    where
+        P: Fn(&M::Value) -> bool,
+    {
+        if !pred(&self.measure()) {
+            return None;
+        }
+        let (node, _) = self.tree.find(&pred, &M::identity())?;
+        Some(node.elem())
+    }
+
+    pub fn iter(&self) -> Iter<'_, T, M> {
+        Iter {
+            stack: vec![Part::Tree(&self.tree)],
+        }
+    }
+}
+
+impl<T> FingerTree<T, Size> {
+    pub fn len(&self) -> usize {
+        self.measure()
+    }
+
+    // the element at `index`, in O(log n)
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.find(|&len| len > index)
+    }
+
+    // The first `index` elements, and the rest, in O(log n).
+    pub fn split_at(&self, index: usize) -> (Self, Self) {
+        self.split(|&len| len > index)
+    }
+}
+
+impl<T, M: Measure<T>> Default for FingerTree<T, M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Cloning a tree only copies the pointer to its root; the nodes themselves are shared.
+impl<T, M: Measure<T>> Clone for FingerTree<T, M> {
+    fn clone(&self) -> Self {
+        FingerTree {
+            tree: self.tree.clone(),
+        }
+    }
+}
+
+// The default, recursive Drop is fine here: a tree is only O(log n) levels deep, and so are its
+// nodes. A suspended middle only holds trees from the level below it, so it doesn't add depth.
+
+//////////////////////////////////////////////////////////////////////////////
+// Iteration
+
+impl<'a, T, M: Measure<T>> IntoIterator for &'a FingerTree<T, M> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T, M>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, T, M: Measure<T>> Iterator for Iter<'a, T, M> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(part) = self.stack.pop() {
+            match part {
+                Part::Node(Node::Leaf(elem)) => return Some(elem),
+                Part::Node(Node::Branch(_, children)) => {
+                    self.stack
+                        .extend(children.iter().rev().map(|node| Part::Node(node)));
+                }
+                Part::Tree(Tree::Empty) => {}
+                Part::Tree(Tree::Single(node)) => self.stack.push(Part::Node(node)),
+                Part::Tree(Tree::Deep(deep)) => {
+                    self.stack
+                        .extend(deep.suffix.iter().rev().map(|node| Part::Node(node)));
+                    self.stack.push(Part::Tree(deep.middle.force()));
+                    self.stack
+                        .extend(deep.prefix.iter().rev().map(|node| Part::Node(node)));
+                }
+            }
+        }
+        None
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Conversions and standard traits
+
+// Every other sequence in the crate converts through `FromIterator`, e.g.
+// `queue.iter().cloned().collect::<FingerTree<_>>()`, or `list.into_iter().collect()` for the ones
+// that can be consumed. The `From` impls below are only shorthand for that.
+impl<T, M: Measure<T>> FromIterator<T> for FingerTree<T, M> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        iter.into_iter()
+            .fold(FingerTree::new(), |tree, elem| tree.push_back(elem))
+    }
+}
+
+impl<T: Clone, M: Measure<T>> From<&third::List<T>> for FingerTree<T, M> {
+    fn from(list: &third::List<T>) -> Self {
+        list.iter().cloned().collect()
+    }
+}
+
+impl<T, M: Measure<T>> From<second::List<T>> for FingerTree<T, M> {
+    fn from(list: second::List<T>) -> Self {
+        list.into_iter().collect()
+    }
+}
+
+impl<T: Clone, M: Measure<T>> From<&random_access::List<T>> for FingerTree<T, M> {
+    fn from(list: &random_access::List<T>) -> Self {
+        list.iter().cloned().collect()
+    }
+}
+
+impl<T: Debug, M: Measure<T>> Debug for FingerTree<T, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: PartialEq, M: Measure<T>> PartialEq for FingerTree<T, M> {
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Eq, M: Measure<T>> Eq for FingerTree<T, M> {}
+
+//////////////////////////////////////////////////////////////////////////////
+// Tests
+
+#[cfg(test)]
+mod test {
+    use super::{FingerTree, Lazy, Measure, Tree};
+    use crate::{bankers_queue, fourth, random_access, second, sixth, third, unrolled};
+    use std::collections::VecDeque;
+    use std::rc::Rc;
+
+    #[test]
+    fn basics() {
+        let tree: FingerTree<i32> = FingerTree::new();
+        assert!(tree.is_empty());
+        assert_eq!(tree.len(), 0);
+        assert_eq!(tree.front(), None);
+        assert!(tree.pop_front().is_none());
+        assert!(tree.pop_back().is_none());
+
+        let tree = tree.push_back(2).push_back(3).push_front(1);
+        assert_eq!(tree.len(), 3);
+        assert_eq!(tree.front(), Some(&1));
+        assert_eq!(tree.back(), Some(&3));
+
+        let (first, rest) = tree.pop_front().unwrap();
+        assert_eq!(first, &1);
+        let (last, rest) = rest.pop_back().unwrap();
+        assert_eq!(last, &3);
+        assert_eq!(rest.iter().collect::<Vec<_>>(), vec![&2]);
+
+        // the old version is unchanged
+        assert_eq!(tree.iter().copied().collect::<Vec<_>>(), vec![1, 2, 3]);
+    }
+
+    // Random pushes and pops at both ends, checked against VecDeque.
+    #[test]
+    fn against_vecdeque() {
+        let mut tree = FingerTree::new();
+        let mut model = VecDeque::new();
+        let mut seed: u32 = 17;
+        for i in 0..5000 {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            match (seed >> 16) % 5 {
+                0 => {
+                    if let Some((x, rest)) = tree.pop_front() {
+                        assert_eq!(Some(x), model.front());
+                        model.pop_front();
+                        tree = rest;
+                    }
+                }
+                1 => {
+                    if let Some((x, rest)) = tree.pop_back() {
+                        assert_eq!(Some(x), model.back());
+                        model.pop_back();
+                        tree = rest;
+                    }
+                }
+                2 | 3 => {
+                    tree = tree.push_back(i);
+                    model.push_back(i);
+                }
+                _ => {
+                    tree = tree.push_front(i);
+                    model.push_front(i);
+                }
+            }
+            assert_eq!(tree.len(), model.len());
+            assert_eq!(tree.front(), model.front());
+            assert_eq!(tree.back(), model.back());
+        }
+        assert!(tree.iter().eq(model.iter()));
+    }
+
+    // Concatenating trees of every small size, then splitting at and looking up every index.
+    #[test]
+    fn concat_and_split() {
+        for n in 0..40 {
+            for m in [0, 1, 5, 17, 40] {
+                let left: FingerTree<i32> = (0..n).collect();
+                let right: FingerTree<i32> = (n..n + m).collect();
+                let tree = left.concat(&right);
+                assert_eq!(tree.len(), (n + m) as usize);
+                assert!(tree.iter().copied().eq(0..n + m));
+
+                for i in 0..=(n + m) as usize {
+                    let (before, after) = tree.split_at(i);
+                    assert_eq!(before.len(), i);
+                    assert!(before.iter().copied().eq(0..i as i32));
+                    assert!(after.iter().copied().eq(i as i32..n + m));
+                    assert_eq!(tree.get(i), after.front());
+                }
+            }
+        }
+    }
+
+    // Splitting and gluing a big tree back together, in a different order.
+    #[test]
+    fn rearrange() {
+        let tree: FingerTree<i32> = (0..10000).collect();
+        let (a, rest) = tree.split_at(1234);
+        let (b, c) = rest.split_at(5000);
+        let tree2 = c.concat(&a).concat(&b);
+        assert_eq!(tree2.len(), 10000);
+        assert_eq!(tree2.get(0), Some(&6234));
+        assert_eq!(tree2.get(3766), Some(&0));
+        assert_eq!(tree2.get(5000), Some(&1234));
+        assert_eq!(tree2.get(10000), None);
+        let expected = (6234..10000).chain(0..1234).chain(1234..6234);
+        assert!(tree2.iter().copied().eq(expected));
+
+        // and the original is untouched
+        assert!(tree.iter().copied().eq(0..10000));
+    }
+
+    fn middle<T, M: Measure<T>>(tree: &FingerTree<T, M>) -> &Rc<Lazy<T, M>> {
+        match &tree.tree {
+            Tree::Deep(deep) => &deep.middle,
+            _ => panic!("not a deep tree"),
+        }
+    }
+
+    // Pushing onto a full prefix suspends the push onto the middle. Versions made from that one
+    // share the suspension, so it's only ever evaluated once.
+    #[test]
+    fn middle_is_lazy() {
+        let full: FingerTree<i32> = (0..5).fold(FingerTree::new(), |tree, i| tree.push_front(i));
+        let pushed = full.push_front(5);
+        assert!(middle(&pushed).value.get().is_none());
+        assert_eq!(pushed.len(), 6);
+        assert_eq!(pushed.front(), Some(&5));
+
+        let pushed_again = pushed.push_front(6).push_back(-1);
+        assert!(Rc::ptr_eq(middle(&pushed), middle(&pushed_again)));
+        assert!(pushed_again.iter().copied().eq((-1..=6).rev()));
+        // iterating evaluated the middle, for both versions
+        assert!(middle(&pushed).value.get().is_some());
+
+        // popping the last node of the suffix borrows one from the middle, and suspends the rest
+        let (_, popped) = pushed.pop_back().unwrap();
+        assert!(middle(&popped).value.get().is_none());
+        assert!(popped.iter().copied().eq((1..=5).rev()));
+    }
+
+    // Reusing one old version over and over, at both ends.
+    #[test]
+    fn reuse_old_versions() {
+        let base: FingerTree<i32> = (0..1000).collect();
+        for i in 0..1000 {
+            let front = base.push_front(-i);
+            assert_eq!(front.front(), Some(&-i));
+            assert_eq!(front.len(), 1001);
+            let (last, back) = base.pop_back().unwrap();
+            assert_eq!((last, back.back()), (&999, Some(&998)));
+            let (first, rest) = front.pop_front().unwrap();
+            assert_eq!(first, &-i);
+            assert_eq!(rest.get(500), Some(&500));
+        }
+        assert!(base.iter().copied().eq(0..1000));
+    }
+
+    // The largest element in a sequence: a priority queue.
+    struct Max;
+
+    impl Measure<u32> for Max {
+        type Value = Option<u32>;
+
+        fn identity() -> Option<u32> {
+            None
+        }
+
+        fn combine(left: &Option<u32>, right: &Option<u32>) -> Option<u32> {
+            (*left).max(*right)
+        }
+
+        fn measure(elem: &u32) -> Option<u32> {
+            Some(*elem)
+        }
+    }
+
+    #[test]
+    fn priority_queue() {
+        let mut queue: FingerTree<u32, Max> = FingerTree::new();
+        let mut seed: u32 = 5;
+        for _ in 0..1000 {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            queue = queue.push_back((seed >> 16) % 1000);
+        }
+        let mut popped = Vec::new();
+        while let Some(max) = queue.measure() {
+            // split just before the first largest element, and take it out
+            let (before, rest) = queue.split(|m| *m >= Some(max));
+            let (elem, after) = rest.pop_front().unwrap();
+            popped.push(*elem);
+            queue = before.concat(&after);
+        }
+        assert_eq!(popped.len(), 1000);
+        assert!(popped.windows(2).all(|w| w[0] >= w[1]));
+    }
+
+    #[test]
+    fn conversions() {
+        let mut third = third::List::new();
+        let mut second = second::List::new();
+        let mut random = random_access::List::new();
+        for i in (0..100).rev() {
+            third = third.append(i);
+            second.push(i);
+            random = random.cons(i);
+        }
+
+        let from_third: FingerTree<i32> = FingerTree::from(&third);
+        let from_random: FingerTree<i32> = FingerTree::from(&random);
+        let from_second: FingerTree<i32> = FingerTree::from(second);
+        assert!(from_third.iter().copied().eq(0..100));
+        assert_eq!(from_third, from_second);
+        assert_eq!(from_third, from_random);
+        assert_eq!(format!("{:?}", from_third.split_at(3).0), "[0, 1, 2]");
+
+        // everything else collects
+        let sixth: sixth::List<i32> = (0..100).collect();
+        let unrolled: unrolled::List<i32> = (0..100).collect();
+        let queue = (0..100).fold(bankers_queue::Queue::new(), |q, i| q.push_back(i));
+        let mut fourth = fourth::List::new();
+        for i in 0..100 {
+            fourth.push_back(i);
+        }
+        assert_eq!(from_third, sixth.iter().copied().collect());
+        assert_eq!(from_third, unrolled.iter().copied().collect());
+        assert_eq!(from_third, queue.iter().copied().collect());
+        assert_eq!(from_third, fourth.into_iter().collect());
+    }
+
+    #[test]
+    fn test_drop() {
+        let mut tree = FingerTree::new();
+        for i in 0..1000000 {
+            tree = tree.push_back(i);
+        }
+        assert_eq!(tree.get(765432), Some(&765432));
+        let (left, right) = tree.split_at(500000);
+        let tree2 = right.concat(&left);
+        assert_eq!(tree2.front(), Some(&500000));
+        // tree and tree2 share most of their nodes and are both dropped here
+    }
+}
@@ -14,6 +14,8 @@ use std::mem;
 // A newtype around Link to hide implementation details of `Node`
 pub struct List {
     head: Link,
+    // the number of nodes, kept up to date by `push` and `pop`
+    len: usize,
 }
 
 // An ADT the represents a link list Link, either the link is empty (end of the list) or it is a
@@ -36,7 +38,19 @@ struct Node {
 impl List {
     // return a new, empty list
     pub fn new() -> Self {
-        List { head: Link::Empty }
+        List {
+            head: Link::Empty,
+            len: 0,
+        }
+    }
+
+    // the number of elements on the stack, in O(1)
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
     }
 
     // push an integer onto the stack
@@ -48,6 +62,7 @@ impl List {
             next: mem::replace(&mut self.head, Link::Empty),
         });
         self.head = Link::More(new_box_node);
+        self.len += 1;
     }
 
     // pop an integer from the stack, returning either Some(value) or None if
@@ -63,10 +78,33 @@ impl List {
             // pointer from
             Link::More(node) => {
                 self.head = node.next;
-                return Some(node.elem);
+                self.len -= 1;
+                Some(node.elem)
             }
         }
     }
+
+    // peek at the element on top of the stack
+    pub fn peek(&self) -> Option<&i32> {
+        match &self.head {
+            Link::Empty => None,
+            Link::More(node) => Some(&node.elem),
+        }
+    }
+
+    // peek at the element on top of the stack, returning a mutable ref
+    pub fn peek_mut(&mut self) -> Option<&mut i32> {
+        match &mut self.head {
+            Link::Empty => None,
+            Link::More(node) => Some(&mut node.elem),
+        }
+    }
+}
+
+impl Default for List {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 // A non-recursive Drop implementation so we don't blow the stack when
@@ -119,6 +157,41 @@ mod test {
         assert_eq!(list.pop(), None);
     }
 
+    #[test]
+    fn peek() {
+        let mut list = List::new();
+        assert_eq!(list.peek(), None);
+        assert_eq!(list.peek_mut(), None);
+
+        list.push(1);
+        list.push(2);
+        assert_eq!(list.peek(), Some(&2));
+        if let Some(x) = list.peek_mut() {
+            *x = 42;
+        }
+        assert_eq!(list.pop(), Some(42));
+        assert_eq!(list.peek(), Some(&1));
+    }
+
+    #[test]
+    fn len() {
+        let mut list = List::new();
+        assert_eq!(list.len(), 0);
+        assert!(list.is_empty());
+
+        list.push(1);
+        list.push(2);
+        assert_eq!(list.len(), 2);
+        assert!(!list.is_empty());
+
+        list.pop();
+        list.pop();
+        // popping an empty list leaves the length at 0
+        assert_eq!(list.pop(), None);
+        assert_eq!(list.len(), 0);
+        assert!(list.is_empty());
+    }
+
     // If the Drop impl for List is commented out above, this test will cause the stack to
     // overflow.
     #[test]
@@ -0,0 +1,389 @@
+// A safe doubly-linked deque implementation.
+//
+// Features:
+//     - mutable deque API, push and pop at both ends
+//     - generic over contained type
+//     - only safe code, using `Rc<RefCell<_>>` for shared mutable nodes
+//
+// Inspired by:
+// https://rust-unofficial.github.io/too-many-lists/fourth.html
+//
+// Every node is pointed at by both of its neighbours, so no single owner exists and we have to
+// reach for reference counting. To avoid `Rc` cycles (which would leak), only the forward `next`
+// links are strong. The `prev` links are `Weak` and are upgraded whenever we need to walk
+// backwards.
+
+use std::cell::{Ref, RefCell, RefMut};
+use std::rc::{Rc, Weak};
+
+//////////////////////////////////////////////////////////////////////////////
+// Data structures
+//
+// Linked list layout:
+// [] = stack
+// () = heap
+// =>  = strong Rc
+// ~>  = Weak
+//
+// [head] => (A) => (B) => (C) <= [tail]
+//           (A) <~ (B) <~ (C)
+//
+
+pub struct List<T> {
+    head: Link<T>,
+    tail: Link<T>,
+    len: usize,
+}
+
+type Link<T> = Option<Rc<RefCell<Node<T>>>>;
+type WeakLink<T> = Option<Weak<RefCell<Node<T>>>>;
+
+struct Node<T> {
+    elem: T,
+    next: Link<T>,
+    prev: WeakLink<T>,
+}
+
+impl<T> Node<T> {
+    fn new(elem: T) -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(Node {
+            elem,
+            next: None,
+            prev: None,
+        }))
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Implementation
+
+impl<T> List<T> {
+    pub fn new() -> Self {
+        List {
+            head: None,
+            tail: None,
+            len: 0,
+        }
+    }
+
+    // the number of elements in the deque, in O(1)
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // push an element onto the front of the deque
+    pub fn push_front(&mut self, elem: T) {
+        let new_head = Node::new(elem);
+        match self.head.take() {
+            Some(old_head) => {
+                // non-empty deque: link the old head and the new one together
+                old_head.borrow_mut().prev = Some(Rc::downgrade(&new_head));
+                new_head.borrow_mut().next = Some(old_head);
+                self.head = Some(new_head);
+            }
+            None => {
+                // empty deque: the new node is both the head and the tail
+                self.tail = Some(new_head.clone());
+                self.head = Some(new_head);
+            }
+        }
+        self.len += 1;
+    }
+
+    // push an element onto the back of the deque
+    pub fn push_back(&mut self, elem: T) {
+        let new_tail = Node::new(elem);
+        match self.tail.take() {
+            Some(old_tail) => {
+                new_tail.borrow_mut().prev = Some(Rc::downgrade(&old_tail));
+                old_tail.borrow_mut().next = Some(new_tail.clone());
+                self.tail = Some(new_tail);
+            }
+            None => {
+                self.head = Some(new_tail.clone());
+                self.tail = Some(new_tail);
+            }
+        }
+        self.len += 1;
+    }
+
+    // pop an element from the front of the deque, returning None if it is empty
+    pub fn pop_front(&mut self) -> Option<T> {
+        self.head.take().map(|old_head| {
+            match old_head.borrow_mut().next.take() {
+                Some(new_head) => {
+                    new_head.borrow_mut().prev.take();
+                    self.head = Some(new_head);
+                }
+                None => {
+                    // we just emptied the deque, drop the tail's strong ref too
+                    self.tail.take();
+                }
+            }
+            self.len -= 1;
+            // `old_head` is now the only strong reference to the node
+            into_elem(old_head)
+        })
+    }
+
+    // pop an element from the back of the deque, returning None if it is empty
+    pub fn pop_back(&mut self) -> Option<T> {
+        self.tail.take().map(|old_tail| {
+            let prev = old_tail.borrow_mut().prev.take();
+            match prev.and_then(|weak| weak.upgrade()) {
+                Some(new_tail) => {
+                    // dropping `next` here releases the other strong ref to `old_tail`
+                    new_tail.borrow_mut().next.take();
+                    self.tail = Some(new_tail);
+                }
+                None => {
+                    self.head.take();
+                }
+            }
+            self.len -= 1;
+            into_elem(old_tail)
+        })
+    }
+
+    // peek at the element at the front of the deque
+    //
+    // The returned `Ref` keeps the node's `RefCell` borrowed, so the deque can't hand out a
+    // mutable borrow of the same node until the guard is dropped.
+    pub fn peek_front(&self) -> Option<Ref<'_, T>> {
+        self.head
+            .as_ref()
+            .map(|node| Ref::map(node.borrow(), |node| &node.elem))
+    }
+
+    // peek at the element at the back of the deque
+    pub fn peek_back(&self) -> Option<Ref<'_, T>> {
+        self.tail
+            .as_ref()
+            .map(|node| Ref::map(node.borrow(), |node| &node.elem))
+    }
+
+    // peek at the element at the front of the deque, returning a mutable guard
+    pub fn peek_front_mut(&mut self) -> Option<RefMut<'_, T>> {
+        self.head
+            .as_ref()
+            .map(|node| RefMut::map(node.borrow_mut(), |node| &mut node.elem))
+    }
+
+    // peek at the element at the back of the deque, returning a mutable guard
+    pub fn peek_back_mut(&mut self) -> Option<RefMut<'_, T>> {
+        self.tail
+            .as_ref()
+            .map(|node| RefMut::map(node.borrow_mut(), |node| &mut node.elem))
+    }
+}
+
+// Unwrap a node that the caller holds the last strong reference to.
+fn into_elem<T>(node: Rc<RefCell<Node<T>>>) -> T {
+    match Rc::try_unwrap(node) {
+        Ok(node) => node.into_inner().elem,
+        Err(_) => unreachable!("popped node is still shared"),
+    }
+}
+
+impl<T> Default for List<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// A non-recursive Drop implementation. The strong `next` links would otherwise drop the whole
+// chain recursively.
+impl<T> Drop for List<T> {
+    fn drop(&mut self) {
+        while self.pop_front().is_some() {}
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Iteration
+//
+// Borrowing iterators don't work out nicely here: every element sits behind its own `RefCell`
+// and a `Ref` can't outlive the node it came from. Only the consuming iterator is provided.
+
+pub struct IntoIter<T>(List<T>);
+
+impl<T> IntoIterator for List<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter(self)
+    }
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.pop_front()
+    }
+}
+
+impl<T> DoubleEndedIterator for IntoIter<T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.0.pop_back()
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Tests
+
+#[cfg(test)]
+mod test {
+    use super::List;
+    use std::rc::Rc;
+
+    #[test]
+    fn basics() {
+        let mut list = List::new();
+
+        // Check empty list behaves right
+        assert_eq!(list.pop_front(), None);
+        assert_eq!(list.pop_back(), None);
+
+        // Populate list
+        list.push_front(1);
+        list.push_front(2);
+        list.push_front(3);
+
+        // Check normal removal
+        assert_eq!(list.pop_front(), Some(3));
+        assert_eq!(list.pop_front(), Some(2));
+
+        // Push some more just to make sure nothing's corrupted
+        list.push_front(4);
+        list.push_front(5);
+
+        // Check normal removal
+        assert_eq!(list.pop_front(), Some(5));
+        assert_eq!(list.pop_front(), Some(4));
+
+        // Check exhaustion
+        assert_eq!(list.pop_front(), Some(1));
+        assert_eq!(list.pop_front(), None);
+
+        // ---- back -----
+
+        // Check empty list behaves right
+        assert_eq!(list.pop_back(), None);
+
+        // Populate list
+        list.push_back(1);
+        list.push_back(2);
+        list.push_back(3);
+
+        // Check normal removal
+        assert_eq!(list.pop_back(), Some(3));
+        assert_eq!(list.pop_back(), Some(2));
+
+        // Push some more just to make sure nothing's corrupted
+        list.push_back(4);
+        list.push_back(5);
+
+        // Check normal removal
+        assert_eq!(list.pop_back(), Some(5));
+        assert_eq!(list.pop_back(), Some(4));
+
+        // Check exhaustion
+        assert_eq!(list.pop_back(), Some(1));
+        assert_eq!(list.pop_back(), None);
+    }
+
+    #[test]
+    fn mixed_ends() {
+        let mut list = List::new();
+        list.push_back(2);
+        list.push_front(1);
+        list.push_back(3);
+
+        // the deque is 1, 2, 3
+        assert_eq!(list.len(), 3);
+        assert_eq!(list.pop_back(), Some(3));
+        assert_eq!(list.pop_front(), Some(1));
+        assert_eq!(list.pop_back(), Some(2));
+        assert_eq!(list.pop_front(), None);
+        assert_eq!(list.pop_back(), None);
+        assert!(list.is_empty());
+
+        list.push_front(4);
+        assert_eq!(list.len(), 1);
+        assert_eq!(list.pop_back(), Some(4));
+        assert_eq!(list.pop_front(), None);
+        assert_eq!(list.len(), 0);
+    }
+
+    #[test]
+    fn peek() {
+        let mut list = List::new();
+        assert!(list.peek_front().is_none());
+        assert!(list.peek_back().is_none());
+        assert!(list.peek_front_mut().is_none());
+        assert!(list.peek_back_mut().is_none());
+
+        list.push_front(1);
+        list.push_front(2);
+        list.push_front(3);
+
+        assert_eq!(&*list.peek_front().unwrap(), &3);
+        assert_eq!(&mut *list.peek_front_mut().unwrap(), &mut 3);
+        assert_eq!(&*list.peek_back().unwrap(), &1);
+        assert_eq!(&mut *list.peek_back_mut().unwrap(), &mut 1);
+
+        *list.peek_front_mut().unwrap() = 30;
+        *list.peek_back_mut().unwrap() = 10;
+        assert_eq!(list.pop_front(), Some(30));
+        assert_eq!(list.pop_back(), Some(10));
+        assert_eq!(list.pop_back(), Some(2));
+    }
+
+    #[test]
+    fn into_iter() {
+        let mut list = List::new();
+        list.push_front(1);
+        list.push_front(2);
+        list.push_front(3);
+
+        let mut iter = list.into_iter();
+        assert_eq!(iter.next(), Some(3));
+        assert_eq!(iter.next_back(), Some(1));
+        assert_eq!(iter.next(), Some(2));
+        assert_eq!(iter.next_back(), None);
+        assert_eq!(iter.next(), None);
+    }
+
+    // Nodes must not keep each other alive once they leave the list.
+    #[test]
+    fn no_leaks() {
+        let elem = Rc::new(0);
+        let mut list = List::new();
+        for _ in 0..10 {
+            list.push_back(elem.clone());
+            list.push_front(elem.clone());
+        }
+        list.pop_back();
+        list.pop_front();
+        assert_eq!(Rc::strong_count(&elem), 19);
+        drop(list);
+        assert_eq!(Rc::strong_count(&elem), 1);
+    }
+
+    // If the Drop impl for List is commented out above, this test will cause the stack to
+    // overflow.
+    #[test]
+    fn test_drop() {
+        let mut list = List::new();
+        for i in 0..1000000 {
+            list.push_back(i);
+        }
+        // list is dropped
+    }
+}
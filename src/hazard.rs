@@ -0,0 +1,266 @@
+// Hazard pointers: safe memory reclamation for the lock-free data structures.
+//
+// In a lock-free structure a thread can unlink a node while other threads are still reading it,
+// so the unlinking thread can't free the node right away. With hazard pointers every reader first
+// publishes the address it is about to dereference in a "hazard" slot. Unlinked nodes are
+// "retired" instead of freed, and every so often the retired nodes are scanned: any node that no
+// hazard slot points at can't be reached by anyone anymore and is freed.
+//
+// Each data structure owns its own `Domain`. Threads only ever touch a domain through a shared
+// borrow of the structure, so by the time the structure (and the domain with it) is dropped
+// nobody else can be looking at it, and everything left over can be freed at once.
+//
+// Based on:
+// Maged M. Michael, "Hazard Pointers: Safe Memory Reclamation for Lock-Free Objects", 2004.
+
+use std::collections::HashSet;
+use std::ptr;
+use std::sync::atomic::{self, AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+
+// Scan once the retired list grows this far past twice the number of hazard slots. Scanning
+// costs O(retired + hazards), so this keeps reclamation amortized O(1) per retired node.
+const SCAN_THRESHOLD: usize = 64;
+
+//////////////////////////////////////////////////////////////////////////////
+// Data structures
+//
+// hazards: (slot) -> (slot) -> (slot) -> null     (only ever grows, slots are reused)
+// retired: (node) -> (node) -> null               (lock-free stack of retired pointers)
+
+pub(crate) struct Domain {
+    hazards: AtomicPtr<HazardSlot>,
+    num_hazards: AtomicUsize,
+    retired: AtomicPtr<Retired>,
+    num_retired: AtomicUsize,
+}
+
+struct HazardSlot {
+    ptr: AtomicPtr<u8>,
+    active: AtomicBool,
+    next: *mut HazardSlot,
+}
+
+// A retired pointer, type-erased, along with the function that knows how to free it.
+struct Retired {
+    ptr: *mut u8,
+    free: unsafe fn(*mut u8),
+    next: *mut Retired,
+}
+
+// An acquired hazard slot. While it protects a pointer, that pointer won't be freed.
+pub(crate) struct Hazard<'d> {
+    slot: &'d HazardSlot,
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Implementation
+
+impl Domain {
+    pub(crate) fn new() -> Self {
+        Domain {
+            hazards: AtomicPtr::new(ptr::null_mut()),
+            num_hazards: AtomicUsize::new(0),
+            retired: AtomicPtr::new(ptr::null_mut()),
+            num_retired: AtomicUsize::new(0),
+        }
+    }
+
+    // Acquire a hazard slot, reusing an inactive one if there is one.
+    pub(crate) fn hazard(&self) -> Hazard<'_> {
+        let mut cur = self.hazards.load(Ordering::SeqCst);
+        while !cur.is_null() {
+            // slots are never freed while the domain is alive
+            let slot = unsafe { &*cur };
+            if !slot.active.load(Ordering::Relaxed)
+                && slot
+                    .active
+                    .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+            {
+                return Hazard { slot };
+            }
+            cur = slot.next;
+        }
+
+        // every slot is in use, add a new one
+        let new = Box::into_raw(Box::new(HazardSlot {
+            ptr: AtomicPtr::new(ptr::null_mut()),
+            active: AtomicBool::new(true),
+            next: ptr::null_mut(),
+        }));
+        let mut head = self.hazards.load(Ordering::Relaxed);
+        loop {
+            unsafe { (*new).next = head };
+            // SeqCst, so the new slot is in the same total order as the hazards published in it
+            // and the fence in `scan`. A scan that misses the slot is then ordered before the
+            // slot's first `protect`, and that `protect` sees any unlink the scan is freeing.
+            match self
+                .hazards
+                .compare_exchange_weak(head, new, Ordering::SeqCst, Ordering::Relaxed)
+            {
+                Ok(_) => break,
+                Err(actual) => head = actual,
+            }
+        }
+        self.num_hazards.fetch_add(1, Ordering::Relaxed);
+        Hazard {
+            slot: unsafe { &*new },
+        }
+    }
+
+    // Hand `ptr` over to the domain to be freed with `Box::from_raw` once no hazard protects it.
+    //
+    // Safety: `ptr` must have come from `Box::into_raw`, must already be unreachable from the
+    // data structure (so no thread can newly protect it), and must not be retired twice.
+    pub(crate) unsafe fn retire<T>(&self, ptr: *mut T) {
+        unsafe fn free<T>(ptr: *mut u8) {
+            drop(Box::from_raw(ptr as *mut T));
+        }
+
+        let retired = Box::into_raw(Box::new(Retired {
+            ptr: ptr as *mut u8,
+            free: free::<T>,
+            next: ptr::null_mut(),
+        }));
+        // count before pushing, so a concurrent scan can't free it and decrement first
+        let count = self.num_retired.fetch_add(1, Ordering::Relaxed) + 1;
+        self.push_retired(retired, retired);
+
+        if count >= SCAN_THRESHOLD + 2 * self.num_hazards.load(Ordering::Relaxed) {
+            self.scan();
+        }
+    }
+
+    // Push the chain of retired nodes `first -> ... -> last` onto the retired list.
+    fn push_retired(&self, first: *mut Retired, last: *mut Retired) {
+        let mut head = self.retired.load(Ordering::Relaxed);
+        loop {
+            unsafe { (*last).next = head };
+            match self.retired.compare_exchange_weak(
+                head,
+                first,
+                Ordering::Release,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return,
+                Err(actual) => head = actual,
+            }
+        }
+    }
+
+    // Free every retired node that isn't protected by a hazard, and put the rest back.
+    fn scan(&self) {
+        // Take the whole retired list. Concurrent scans each get a disjoint batch.
+        let mut batch = self.retired.swap(ptr::null_mut(), Ordering::Acquire);
+        if batch.is_null() {
+            return;
+        }
+
+        // Pairs with the SeqCst store and re-load in `Hazard::protect`: either the reader's
+        // re-load sees that the node was unlinked (and it retries), or we see its hazard here.
+        // The slot list is loaded SeqCst too, so we can't miss a slot that was added (and used)
+        // before this scan (see `hazard`).
+        atomic::fence(Ordering::SeqCst);
+        let mut protected = HashSet::new();
+        let mut cur = self.hazards.load(Ordering::SeqCst);
+        while !cur.is_null() {
+            let slot = unsafe { &*cur };
+            let ptr = slot.ptr.load(Ordering::SeqCst);
+            if !ptr.is_null() {
+                protected.insert(ptr);
+            }
+            cur = slot.next;
+        }
+
+        let mut keep_first: *mut Retired = ptr::null_mut();
+        let mut keep_last: *mut Retired = ptr::null_mut();
+        let mut freed = 0;
+        while !batch.is_null() {
+            let retired = batch;
+            unsafe {
+                batch = (*retired).next;
+                if protected.contains(&(*retired).ptr) {
+                    // still in use, keep it for a later scan
+                    (*retired).next = keep_first;
+                    keep_first = retired;
+                    if keep_last.is_null() {
+                        keep_last = retired;
+                    }
+                } else {
+                    let retired = Box::from_raw(retired);
+                    (retired.free)(retired.ptr);
+                    freed += 1;
+                }
+            }
+        }
+
+        if !keep_first.is_null() {
+            self.push_retired(keep_first, keep_last);
+        }
+        self.num_retired.fetch_sub(freed, Ordering::Relaxed);
+    }
+}
+
+// We have `&mut self`, so there are no hazards left and nobody else can be retiring. Free it all.
+impl Drop for Domain {
+    fn drop(&mut self) {
+        let mut retired = *self.retired.get_mut();
+        while !retired.is_null() {
+            unsafe {
+                let boxed = Box::from_raw(retired);
+                (boxed.free)(boxed.ptr);
+                retired = boxed.next;
+            }
+        }
+
+        let mut slot = *self.hazards.get_mut();
+        while !slot.is_null() {
+            unsafe {
+                let boxed = Box::from_raw(slot);
+                slot = boxed.next;
+            }
+        }
+    }
+}
+
+impl<'d> Hazard<'d> {
+    // Load the pointer in `src` and protect it. The returned pointer (if non-null) stays valid
+    // until this hazard is reset, protects something else, or is dropped.
+    pub(crate) fn protect<T>(&self, src: &AtomicPtr<T>) -> *mut T {
+        let mut ptr = src.load(Ordering::Relaxed);
+        loop {
+            self.slot.ptr.store(ptr as *mut u8, Ordering::SeqCst);
+            // The node may have been unlinked (and even freed) between the load and publishing
+            // the hazard. If `src` still holds it after the hazard is visible, it wasn't.
+            //
+            // The check is a read-modify-write that leaves `src` as it is, rather than a load.
+            // Every change to `src` is a read-modify-write, so whoever unlinks the node next
+            // reads from our write (or one after it) and synchronizes with it. Our hazard then
+            // happens before their `retire`, and so before the `scan` that could free the node,
+            // which has to see it. A plain load would rely on the SeqCst total order alone.
+            //
+            // It adds zero instead of swapping `ptr` for itself: the node `ptr` came from may
+            // have been freed and a new one allocated at the same address, and `ptr` would still
+            // point into the old allocation. A compare-and-swap would store that stale pointer
+            // back into `src` for everyone else to load.
+            let again = src.fetch_byte_add(0, Ordering::SeqCst);
+            if again == ptr {
+                // return what `src` holds, not `ptr`, for the same reason
+                return again;
+            }
+            ptr = again;
+        }
+    }
+
+    // Stop protecting whatever this hazard protects.
+    pub(crate) fn reset(&self) {
+        self.slot.ptr.store(ptr::null_mut(), Ordering::Release);
+    }
+}
+
+impl<'d> Drop for Hazard<'d> {
+    fn drop(&mut self) {
+        self.reset();
+        self.slot.active.store(false, Ordering::Release);
+    }
+}
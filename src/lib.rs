@@ -1,8 +1,23 @@
-pub mod fifth; // mutable queue using only boxes and &mut
+pub mod arena; // a stack whose nodes live in a Vec-backed slab
+pub mod bankers_queue; // a persistent queue with amortized O(1) operations
+pub mod fifth; // mutable queue using only safe code
 pub mod fifth_unsafe; // mutable queue using raw pointers
+pub mod finger_tree; // a persistent sequence with O(log n) concatenation and splitting
 pub mod first; // a naive stack
+pub mod fourth; // a safe doubly-linked deque using Rc<RefCell>
+mod hazard; // hazard pointers for the lock-free structures
+pub mod ms_queue; // a lock-free queue
+pub mod random_access; // a persistent list with O(log n) indexing
+pub mod real_time_queue; // a persistent queue with worst-case O(1) operations
 pub mod second; // an Ok, generic stack
+pub mod sixth; // a production-quality unsafe deque with cursors
+pub mod stream; // a lazy, memoized persistent list
 pub mod third; // a persistent singly-linked stack
+pub mod third_sync; // a persistent stack that can be shared between threads
+pub mod traits; // Stack, Fifo and PersistentStack traits shared by the lists
+pub mod treiber; // a lock-free stack
+pub mod unrolled; // a stack storing a chunk of elements per node
+pub mod zipper; // a movable, editable focus into a persistent list
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,394 @@
+// A lock-free multi-producer multi-consumer queue (Michael-Scott queue).
+//
+// Features:
+//     - same push/pop shape as `fifth_unsafe::Queue`, but through `&self`, so the queue can be
+//       shared between threads with an `Arc`
+//     - lock-free: a thread that stalls never blocks the others
+//     - nodes are freed safely using hazard pointers (see `hazard.rs`)
+//
+// Based on:
+// Maged M. Michael and Michael L. Scott, "Simple, Fast, and Practical Non-Blocking and Blocking
+// Concurrent Queue Algorithms", 1996.
+//
+// Like `fifth_unsafe::Queue` we keep a pointer to each end, but both are atomic, and the list
+// always starts with a "dummy" node whose element has already been popped (or never existed).
+// The dummy means `head` and `tail` never need to be updated together:
+//
+//   - `push` links the new node after the last node with a CAS on its `next`, then swings `tail`
+//     forward. `tail` can briefly lag one node behind; any thread that notices finishes the job.
+//   - `pop` moves `head` forward by one with a CAS. The node after the old dummy becomes the new
+//     dummy, and we move its element out. The old dummy is retired.
+
+use crate::hazard::Domain;
+use std::marker::PhantomData;
+use std::mem::MaybeUninit;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+//////////////////////////////////////////////////////////////////////////////
+// Data structures
+//
+// Linked list layout:
+// [] = stack
+// () = heap
+// [head] -> (dummy, ptr) -> (A, ptr) -> (B, ptr) -> (C, null)
+// [tail] ------------------------------------------^
+//
+
+pub struct Queue<T> {
+    head: AtomicPtr<Node<T>>,
+    tail: AtomicPtr<Node<T>>,
+    domain: Domain,
+    // we own `T`s, for drop check and so that `Queue<T>: Send` only when `T: Send`
+    _marker: PhantomData<T>,
+}
+
+struct Node<T> {
+    // Initialized until the node becomes the dummy, at which point `pop` moves it out.
+    elem: MaybeUninit<T>,
+    next: AtomicPtr<Node<T>>,
+}
+
+impl<T> Node<T> {
+    fn alloc(elem: MaybeUninit<T>) -> *mut Node<T> {
+        Box::into_raw(Box::new(Node {
+            elem,
+            next: AtomicPtr::new(ptr::null_mut()),
+        }))
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Implementation
+
+impl<T> Queue<T> {
+    pub fn new() -> Self {
+        let dummy = Node::alloc(MaybeUninit::uninit());
+        Queue {
+            head: AtomicPtr::new(dummy),
+            tail: AtomicPtr::new(dummy),
+            domain: Domain::new(),
+            _marker: PhantomData,
+        }
+    }
+
+    // Push an element onto the tail of the queue.
+    pub fn push(&self, elem: T) {
+        let new = Node::alloc(MaybeUninit::new(elem));
+        let hazard = self.domain.hazard();
+        loop {
+            let tail = hazard.protect(&self.tail);
+            // `tail` is protected, so it hasn't been freed
+            let next = unsafe { (*tail).next.load(Ordering::Acquire) };
+
+            if !next.is_null() {
+                // `tail` is lagging behind, help move it on and try again
+                let _ =
+                    self.tail
+                        .compare_exchange(tail, next, Ordering::Release, Ordering::Relaxed);
+                continue;
+            }
+
+            // Release: whoever pops `new` must see its element
+            let linked = unsafe {
+                (*tail).next.compare_exchange(
+                    ptr::null_mut(),
+                    new,
+                    Ordering::Release,
+                    Ordering::Relaxed,
+                )
+            };
+            if linked.is_ok() {
+                // Swing `tail` to the new node. If this fails someone already helped.
+                let _ = self
+                    .tail
+                    .compare_exchange(tail, new, Ordering::Release, Ordering::Relaxed);
+                return;
+            }
+        }
+    }
+
+    // Pop an element off of the head of the queue, returning None if it is empty.
+    pub fn pop(&self) -> Option<T> {
+        let head_hazard = self.domain.hazard();
+        let next_hazard = self.domain.hazard();
+        loop {
+            let head = head_hazard.protect(&self.head);
+            let next = next_hazard.protect(unsafe { &(*head).next });
+            // If `head` is still the head, then `next` can't have been retired yet: it would
+            // have had to become the dummy and be popped past first. Checked with a
+            // compare-and-swap, like `Hazard::protect` does, so that whoever pops `head` next
+            // synchronizes with our hazard on `next`.
+            if !self.still_head(head) {
+                continue;
+            }
+            if next.is_null() {
+                return None;
+            }
+
+            let tail = self.tail.load(Ordering::Acquire);
+            if head == tail {
+                // `tail` is lagging behind the node we're about to make the dummy; it must never
+                // point at a retired node, so help move it on first
+                let _ =
+                    self.tail
+                        .compare_exchange(tail, next, Ordering::Release, Ordering::Relaxed);
+                continue;
+            }
+
+            // SeqCst so the unlink is ordered against other threads publishing hazards on `head`
+            // (see `Hazard::protect` and `Domain::scan`)
+            if self
+                .head
+                .compare_exchange(head, next, Ordering::SeqCst, Ordering::Acquire)
+                .is_ok()
+            {
+                // `next` is the new dummy and its element is ours. Concurrent `peek`s may still
+                // be reading it, but they only copy it.
+                let elem = unsafe { ptr::read((*next).elem.as_ptr()) };
+                head_hazard.reset();
+                next_hazard.reset();
+                unsafe { self.domain.retire(head) };
+                return Some(elem);
+            }
+        }
+    }
+
+    // Peek at the element at the head of the queue.
+    //
+    // Like `treiber::Stack::peek`, this returns a copy: another thread may pop the element (and
+    // drop it) at any moment, even while we're reading it.
+    pub fn peek(&self) -> Option<T>
+    where
+        T: Copy,
+    {
+        let head_hazard = self.domain.hazard();
+        let next_hazard = self.domain.hazard();
+        loop {
+            let head = head_hazard.protect(&self.head);
+            let next = next_hazard.protect(unsafe { &(*head).next });
+            if !self.still_head(head) {
+                continue;
+            }
+            if next.is_null() {
+                return None;
+            }
+            return Some(unsafe { ptr::read((*next).elem.as_ptr()) });
+        }
+    }
+
+    // Whether `head` is still the head, checked by swapping it for itself.
+    fn still_head(&self, head: *mut Node<T>) -> bool {
+        self.head
+            .compare_exchange(head, head, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+    }
+
+    // Whether the queue was empty at the moment we looked.
+    pub fn is_empty(&self) -> bool {
+        let hazard = self.domain.hazard();
+        let head = hazard.protect(&self.head);
+        unsafe { (*head).next.load(Ordering::Acquire).is_null() }
+    }
+}
+
+impl<T> Default for Queue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// A non-recursive Drop implementation. We have `&mut self`, so no other thread is using the
+// queue and the nodes can be freed directly. Retired nodes are freed when `domain` drops.
+impl<T> Drop for Queue<T> {
+    fn drop(&mut self) {
+        // the dummy's element has already been moved out (or never existed)
+        let dummy = unsafe { Box::from_raw(*self.head.get_mut()) };
+        let mut cur = dummy.next.load(Ordering::Relaxed);
+        while !cur.is_null() {
+            let mut node = unsafe { Box::from_raw(cur) };
+            cur = *node.next.get_mut();
+            unsafe { ptr::drop_in_place(node.elem.as_mut_ptr()) };
+        }
+    }
+}
+
+// Sharing the queue lets any thread pop elements that other threads pushed, so both sending and
+// sharing only need `T: Send`. (`Send` comes from `PhantomData<T>` automatically.)
+unsafe impl<T: Send> Sync for Queue<T> {}
+
+//////////////////////////////////////////////////////////////////////////////
+// Unit Tests
+
+#[cfg(test)]
+mod test {
+    use super::Queue;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    // smaller runs under Miri, which is much slower
+    const THREADS: usize = if cfg!(miri) { 3 } else { 8 };
+    const PER_THREAD: usize = if cfg!(miri) { 100 } else { 10000 };
+
+    #[test]
+    fn basics() {
+        let queue = Queue::new();
+
+        // Check empty queue behaves right
+        assert_eq!(queue.pop(), None);
+        assert!(queue.is_empty());
+
+        // Populate queue
+        queue.push(1);
+        assert!(!queue.is_empty());
+        assert_eq!(queue.pop(), Some(1));
+        assert_eq!(queue.pop(), None);
+
+        // Check that items are popped in FIFO order
+        queue.push(2);
+        queue.push(3);
+        queue.push(4);
+        assert_eq!(queue.pop(), Some(2));
+        queue.push(5);
+        assert_eq!(queue.pop(), Some(3));
+        assert_eq!(queue.pop(), Some(4));
+        assert_eq!(queue.pop(), Some(5));
+        assert_eq!(queue.pop(), None);
+
+        // How about for strings? Leave some in the queue to be dropped with it.
+        let str_queue = Queue::new();
+        str_queue.push("a".to_string());
+        str_queue.push("b".to_string());
+        str_queue.push("c".to_string());
+        assert_eq!(str_queue.pop(), Some("a".to_string()));
+    }
+
+    #[test]
+    fn peek() {
+        let queue = Queue::new();
+        assert_eq!(queue.peek(), None);
+        queue.push(1);
+        queue.push(2);
+        assert_eq!(queue.peek(), Some(1));
+        assert_eq!(queue.pop(), Some(1));
+        assert_eq!(queue.peek(), Some(2));
+    }
+
+    // If the Drop impl recursed, this would overflow the stack.
+    #[test]
+    #[cfg_attr(miri, ignore)] // too slow under Miri
+    fn test_drop() {
+        let queue = Queue::new();
+        for i in 0..1000000 {
+            queue.push(i);
+        }
+        // queue is dropped
+    }
+
+    // Producers push (producer, sequence number) pairs while consumers pop. Every pair must be
+    // popped exactly once, and since the queue is FIFO, each consumer must see every producer's
+    // items in the order that producer pushed them.
+    #[test]
+    fn stress_fifo_per_producer() {
+        let queue = Arc::new(Queue::new());
+        let remaining = Arc::new(AtomicUsize::new(THREADS * PER_THREAD));
+
+        let producers: Vec<_> = (0..THREADS)
+            .map(|p| {
+                let queue = Arc::clone(&queue);
+                thread::spawn(move || {
+                    for seq in 0..PER_THREAD {
+                        // boxed, so a use-after-free or double free gets caught
+                        queue.push(Box::new((p, seq)));
+                    }
+                })
+            })
+            .collect();
+        let consumers: Vec<_> = (0..THREADS)
+            .map(|_| {
+                let queue = Arc::clone(&queue);
+                let remaining = Arc::clone(&remaining);
+                thread::spawn(move || {
+                    let mut popped = Vec::new();
+                    while remaining.load(Ordering::Relaxed) > 0 {
+                        if let Some(item) = queue.pop() {
+                            remaining.fetch_sub(1, Ordering::Relaxed);
+                            popped.push(*item);
+                        } else {
+                            thread::yield_now();
+                        }
+                    }
+                    popped
+                })
+            })
+            .collect();
+
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        let mut seen = vec![vec![false; PER_THREAD]; THREADS];
+        for consumer in consumers {
+            let mut last_seen = [None; THREADS];
+            for (p, seq) in consumer.join().unwrap() {
+                assert!(
+                    last_seen[p] < Some(seq),
+                    "producer {}: {} popped after {:?}",
+                    p,
+                    seq,
+                    last_seen[p]
+                );
+                last_seen[p] = Some(seq);
+                assert!(!seen[p][seq], "({}, {}) popped twice", p, seq);
+                seen[p][seq] = true;
+            }
+        }
+        assert!(seen.iter().flatten().all(|&s| s));
+        assert!(queue.is_empty());
+    }
+
+    // Every element must be dropped exactly once: whether it was popped, or still in the queue
+    // when the queue is dropped.
+    #[test]
+    fn drops_each_element_once() {
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct Counted;
+        impl Drop for Counted {
+            fn drop(&mut self) {
+                DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let queue = Arc::new(Queue::new());
+        let handles: Vec<_> = (0..THREADS)
+            .map(|_| {
+                let queue = Arc::clone(&queue);
+                thread::spawn(move || {
+                    for i in 0..PER_THREAD {
+                        queue.push(Counted);
+                        if i % 3 == 0 {
+                            drop(queue.pop());
+                        }
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        drop(queue);
+        assert_eq!(DROPS.load(Ordering::Relaxed), THREADS * PER_THREAD);
+    }
+
+    // These only need to compile.
+    #[allow(dead_code)]
+    fn assert_properties() {
+        fn is_send<T: Send>() {}
+        fn is_sync<T: Sync>() {}
+
+        is_send::<Queue<i32>>();
+        is_sync::<Queue<i32>>();
+        // only needs T: Send to be shared
+        is_sync::<Queue<std::cell::Cell<i32>>>();
+    }
+}
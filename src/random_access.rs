@@ -0,0 +1,490 @@
+// A persistent random-access list (skew-binary random-access list).
+//
+// Improvements over `third::List`:
+//     - O(log n) `get` and `update` of the i-th element, instead of O(n)
+//     - still O(1) `cons`, `head` and `tail`, and still persistent: old versions stay valid and
+//       share their nodes with the new ones
+//
+// Based on:
+// Chris Okasaki, "Purely Functional Random-Access Lists", FPCA 1995.
+//
+// The elements are stored in a list of complete binary trees, smallest first, each holding 2^k - 1
+// elements in preorder. Only the first two trees may have the same size. This is the skew binary
+// representation of the list's length, e.g. 9 = 1 + 1 + 7:
+//
+// [head] -> (size 1, A) -> (size 1, B) -> (size 7,     C     ) -> null
+//                                                   /   \
+//                                                  D     G
+//                                                 / \   / \
+//                                                E   F H   I
+//
+// `cons` either adds a one-element tree at the front, or, when the first two trees are the same
+// size, joins them under the new element. `tail` undoes that. Both are O(1). To find the i-th
+// element we skip whole trees (there are O(log n) of them) and then walk down one (it's O(log n)
+// deep).
+//
+// Like `third::List`, nodes are shared with `Rc`.
+
+use crate::third;
+use std::fmt::{self, Debug};
+use std::rc::Rc;
+
+//////////////////////////////////////////////////////////////////////////////
+// Data structures
+
+pub struct List<T> {
+    head: Link<T>,
+}
+
+type Link<T> = Option<Rc<Spine<T>>>;
+
+// one tree in the list of trees
+struct Spine<T> {
+    // number of elements in `tree`
+    size: usize,
+    tree: Rc<Tree<T>>,
+    next: Link<T>,
+}
+
+enum Tree<T> {
+    Leaf(T),
+    // an element and two trees of the same size
+    Node(T, Rc<Tree<T>>, Rc<Tree<T>>),
+}
+
+pub struct Iter<'a, T> {
+    // the trees still to be walked
+    spine: Option<&'a Spine<T>>,
+    // the subtrees still to be walked in the current tree, next one last
+    trees: Vec<&'a Tree<T>>,
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Implementation
+
+impl<T> Tree<T> {
+    fn elem(&self) -> &T {
+        match self {
+            Tree::Leaf(elem) | Tree::Node(elem, _, _) => elem,
+        }
+    }
+
+    // The element at `index` in this tree of `size` elements.
+    fn get(&self, size: usize, index: usize) -> &T {
+        let (mut tree, mut size, mut index) = (self, size, index);
+        loop {
+            match tree {
+                Tree::Node(_, left, right) if index > 0 => {
+                    size /= 2;
+                    if index <= size {
+                        tree = left;
+                        index -= 1;
+                    } else {
+                        tree = right;
+                        index -= 1 + size;
+                    }
+                }
+                _ => return tree.elem(),
+            }
+        }
+    }
+}
+
+impl<T: Clone> Tree<T> {
+    // A copy of this tree of `size` elements with the element at `index` replaced. Only the
+    // nodes on the path down to it are copied.
+    fn update(&self, size: usize, index: usize, elem: T) -> Tree<T> {
+        // walk down, remembering the path
+        let mut path = Vec::new();
+        let (mut tree, mut size, mut index) = (self, size, index);
+        loop {
+            match tree {
+                Tree::Node(_, left, right) if index > 0 => {
+                    size /= 2;
+                    path.push((tree, index <= size));
+                    if index <= size {
+                        tree = left;
+                        index -= 1;
+                    } else {
+                        tree = right;
+                        index -= 1 + size;
+                    }
+                }
+                _ => break,
+            }
+        }
+
+        // replace the element, then copy the path back up
+        let mut new = match tree {
+            Tree::Leaf(_) => Tree::Leaf(elem),
+            Tree::Node(_, left, right) => Tree::Node(elem, left.clone(), right.clone()),
+        };
+        while let Some((tree, went_left)) = path.pop() {
+            if let Tree::Node(x, left, right) = tree {
+                new = if went_left {
+                    Tree::Node(x.clone(), Rc::new(new), right.clone())
+                } else {
+                    Tree::Node(x.clone(), left.clone(), Rc::new(new))
+                };
+            }
+        }
+        new
+    }
+}
+
+impl<T> List<T> {
+    pub fn new() -> Self {
+        List { head: None }
+    }
+
+    // return a new list with `elem` at the front
+    pub fn cons(&self, elem: T) -> List<T> {
+        if let Some(first) = &self.head {
+            if let Some(second) = &first.next {
+                if first.size == second.size {
+                    return List {
+                        head: Some(Rc::new(Spine {
+                            size: 1 + 2 * first.size,
+                            tree: Rc::new(Tree::Node(
+                                elem,
+                                first.tree.clone(),
+                                second.tree.clone(),
+                            )),
+                            next: second.next.clone(),
+                        })),
+                    };
+                }
+            }
+        }
+        List {
+            head: Some(Rc::new(Spine {
+                size: 1,
+                tree: Rc::new(Tree::Leaf(elem)),
+                next: self.head.clone(),
+            })),
+        }
+    }
+
+    pub fn head(&self) -> Option<&T> {
+        self.head.as_ref().map(|spine| spine.tree.elem())
+    }
+
+    // return the list without its first element, or None if it's empty
+    pub fn tail(&self) -> Option<List<T>> {
+        let first = self.head.as_ref()?;
+        Some(match &*first.tree {
+            Tree::Leaf(_) => List {
+                head: first.next.clone(),
+            },
+            Tree::Node(_, left, right) => {
+                let size = first.size / 2;
+                let right = Rc::new(Spine {
+                    size,
+                    tree: right.clone(),
+                    next: first.next.clone(),
+                });
+                List {
+                    head: Some(Rc::new(Spine {
+                        size,
+                        tree: left.clone(),
+                        next: Some(right),
+                    })),
+                }
+            }
+        })
+    }
+
+    // the element at `index`, or None if the list is too short
+    pub fn get(&self, index: usize) -> Option<&T> {
+        let mut index = index;
+        let mut spine = self.head.as_deref();
+        while let Some(node) = spine {
+            if index < node.size {
+                return Some(node.tree.get(node.size, index));
+            }
+            index -= node.size;
+            spine = node.next.as_deref();
+        }
+        None
+    }
+
+    // O(log n)
+    pub fn len(&self) -> usize {
+        let mut len = 0;
+        let mut spine = self.head.as_deref();
+        while let Some(node) = spine {
+            len += node.size;
+            spine = node.next.as_deref();
+        }
+        len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.is_none()
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            spine: self.head.as_deref(),
+            trees: Vec::new(),
+        }
+    }
+}
+
+impl<T: Clone> List<T> {
+    // Return a new list with the element at `index` replaced by `elem`, or None if the list is
+    // too short. The new list shares everything but the O(log n) nodes on the way to `index`.
+    pub fn update(&self, index: usize, elem: T) -> Option<List<T>> {
+        // the trees before the one `index` is in
+        let mut before = Vec::new();
+        let mut index = index;
+        let mut spine = self.head.as_deref();
+        while let Some(node) = spine {
+            if index < node.size {
+                break;
+            }
+            index -= node.size;
+            before.push(node);
+            spine = node.next.as_deref();
+        }
+        let node = spine?;
+
+        let mut head = Rc::new(Spine {
+            size: node.size,
+            tree: Rc::new(node.tree.update(node.size, index, elem)),
+            next: node.next.clone(),
+        });
+        while let Some(node) = before.pop() {
+            head = Rc::new(Spine {
+                size: node.size,
+                tree: node.tree.clone(),
+                next: Some(head),
+            });
+        }
+        Some(List { head: Some(head) })
+    }
+}
+
+impl<T> Default for List<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Cloning a list only copies the pointer to its head; the nodes themselves are shared.
+impl<T> Clone for List<T> {
+    fn clone(&self) -> Self {
+        List {
+            head: self.head.clone(),
+        }
+    }
+}
+
+// A non-recursive Drop implementation. The trees are only O(log n) deep, but dropping them with
+// a loop as well keeps this in line with `third::List`.
+impl<T> Drop for List<T> {
+    fn drop(&mut self) {
+        let mut trees = Vec::new();
+        let mut spine = self.head.take();
+        while let Some(node) = spine {
+            // stop at the first node that another list still holds
+            match Rc::try_unwrap(node) {
+                Ok(node) => {
+                    trees.push(node.tree);
+                    spine = node.next;
+                }
+                Err(_) => break,
+            }
+        }
+        while let Some(tree) = trees.pop() {
+            if let Ok(Tree::Node(_, left, right)) = Rc::try_unwrap(tree) {
+                trees.push(left);
+                trees.push(right);
+            }
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Iteration
+
+impl<'a, T> IntoIterator for &'a List<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    // Walk each tree in preorder, which is the order of the elements.
+    fn next(&mut self) -> Option<Self::Item> {
+        let tree = match self.trees.pop() {
+            Some(tree) => tree,
+            None => {
+                let spine = self.spine.take()?;
+                self.spine = spine.next.as_deref();
+                &*spine.tree
+            }
+        };
+        if let Tree::Node(_, left, right) = tree {
+            self.trees.push(right);
+            self.trees.push(left);
+        }
+        Some(tree.elem())
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Conversions and standard traits
+
+impl<T: Clone> From<&third::List<T>> for List<T> {
+    fn from(list: &third::List<T>) -> Self {
+        let elems: Vec<&T> = list.iter().collect();
+        let mut result = List::new();
+        for elem in elems.into_iter().rev() {
+            result = result.cons(elem.clone());
+        }
+        result
+    }
+}
+
+impl<T: Clone> From<&List<T>> for third::List<T> {
+    fn from(list: &List<T>) -> Self {
+        let elems: Vec<&T> = list.iter().collect();
+        let mut result = third::List::new();
+        for elem in elems.into_iter().rev() {
+            result = result.append(elem.clone());
+        }
+        result
+    }
+}
+
+impl<T: Debug> Debug for List<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: PartialEq> PartialEq for List<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Eq> Eq for List<T> {}
+
+//////////////////////////////////////////////////////////////////////////////
+// Tests
+
+#[cfg(test)]
+mod test {
+    use super::List;
+    use crate::third;
+
+    // The sizes of the trees, front to back.
+    fn sizes<T>(list: &List<T>) -> Vec<usize> {
+        let mut sizes = Vec::new();
+        let mut spine = list.head.as_deref();
+        while let Some(node) = spine {
+            sizes.push(node.size);
+            spine = node.next.as_deref();
+        }
+        sizes
+    }
+
+    #[test]
+    fn basic() {
+        let list: List<i32> = List::new();
+        assert_eq!(list.head(), None);
+        assert!(list.tail().is_none());
+        assert!(list.is_empty());
+
+        let list2 = list.cons(2).cons(1).cons(0);
+        assert_eq!(list2.head(), Some(&0));
+        assert_eq!(list2.len(), 3);
+        assert_eq!(sizes(&list2), vec![3]);
+        let tail = list2.tail().unwrap();
+        assert_eq!(tail.head(), Some(&1));
+        assert_eq!(sizes(&tail), vec![1, 1]);
+        assert_eq!(tail, List::new().cons(2).cons(1));
+        assert_eq!(format!("{:?}", list2), "[0, 1, 2]");
+    }
+
+    #[test]
+    fn skew_binary_shape() {
+        let mut list = List::new();
+        for i in 0..9 {
+            list = list.cons(i);
+        }
+        // 9 = 1 + 1 + 7
+        assert_eq!(sizes(&list), vec![1, 1, 7]);
+        list = list.cons(9);
+        // 10 = 3 + 7
+        assert_eq!(sizes(&list), vec![3, 7]);
+        assert_eq!(
+            list.iter().copied().collect::<Vec<_>>(),
+            (0..10).rev().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn get_and_update() {
+        let n = 100;
+        let mut list = List::new();
+        for i in (0..n).rev() {
+            list = list.cons(i);
+        }
+        for i in 0..n {
+            assert_eq!(list.get(i), Some(&i));
+        }
+        assert_eq!(list.get(n), None);
+        assert!(list.update(n, 0).is_none());
+
+        // update every element of a fresh version, checking the old one is untouched
+        let mut updated = list.clone();
+        for i in 0..n {
+            updated = updated.update(i, i * 10).unwrap();
+        }
+        for i in 0..n {
+            assert_eq!(list.get(i), Some(&i));
+            assert_eq!(updated.get(i), Some(&(i * 10)));
+        }
+
+        // tails of an updated list still index correctly
+        let mut tail = updated.clone();
+        for i in 0..n {
+            assert_eq!(tail.get(0), Some(&(i * 10)));
+            assert_eq!(tail.len(), n - i);
+            tail = tail.tail().unwrap();
+        }
+        assert!(tail.is_empty());
+    }
+
+    #[test]
+    fn conversions() {
+        let list = third::List::new().append(3).append(2).append(1);
+        let ra = List::from(&list);
+        assert_eq!(ra.iter().collect::<Vec<_>>(), vec![&1, &2, &3]);
+        assert_eq!(ra.get(2), Some(&3));
+        let back = third::List::from(&ra);
+        assert_eq!(back, list);
+        assert_eq!(List::from(&third::List::<i32>::new()), List::new());
+    }
+
+    // A million elements is only a handful of trees, about 20 levels deep.
+    #[test]
+    fn test_drop() {
+        let mut list = List::new();
+        for i in 0..1000000 {
+            list = list.cons(i);
+        }
+        assert_eq!(list.get(999999), Some(&0));
+        let list2 = list.update(500000, 42).unwrap();
+        assert_eq!(list2.get(500000), Some(&42));
+        // list and list2 share most of their nodes and are both dropped here
+    }
+}
@@ -0,0 +1,475 @@
+// A persistent FIFO queue with worst-case O(1) operations, built from `third::List`s.
+//
+// Features:
+//     - persistent: `push_back` and `pop_front` return a new queue, and every old version stays
+//       valid and shares its nodes with the new ones
+//     - worst-case O(1) `push_back`, `pop_front`, `peek`, `len` and `clone`: no operation ever
+//       does more than a constant amount of work, so there are no latency spikes
+//
+// Based on:
+// Robert Hood and Robert Melville, "Real-time queue operations in pure LISP", 1981.
+// Chris Okasaki, "Purely Functional Data Structures", 1998, section 8.2.1.
+//
+// Like `bankers_queue::Queue`, this is a front list and a reversed back list, and when the back
+// gets longer than the front the front becomes `front ++ reverse(back)`. Instead of doing that
+// rotation all at once, it's done a couple of steps at a time by every operation that follows.
+// It's done in two phases:
+//
+// Reversing: reverse both the front and the back, one element of each per step
+//     front: [1, 2, 3]          front_rev: []
+//     back:  [7, 6, 5, 4]       back_rev:  []
+//   ->
+//     front: []                 front_rev: [3, 2, 1]
+//     back:  []                 back_rev:  [4, 5, 6, 7]
+//
+// Appending: move the reversed front onto the reversed back, one element per step
+//     front_rev: [3, 2, 1]      done: [4, 5, 6, 7]
+//   ->
+//     front_rev: []             done: [1, 2, 3, 4, 5, 6, 7]
+//
+// Meanwhile the queue keeps popping from its old front. Elements popped before the rotation is
+// done don't need to be copied onto the new front, so `ok` counts how many elements of
+// `front_rev` are still in the queue, and appending stops early once those are done.
+//
+// The rotation takes about 2n steps, and two steps per operation finish it before the back can
+// get longer than the front again. All the steps build new lists, so every old version of the
+// queue stays valid.
+
+use crate::third::{self, List};
+
+//////////////////////////////////////////////////////////////////////////////
+// Data structures
+
+pub struct Queue<T> {
+    front_len: usize,
+    // the oldest elements, oldest first; while rotating, only a prefix of the front
+    front: List<T>,
+    rotation: Rotation<T>,
+    back_len: usize,
+    // the newest elements, newest first
+    back: List<T>,
+}
+
+enum Rotation<T> {
+    Idle,
+    Reversing {
+        ok: usize,
+        front: List<T>,
+        front_rev: List<T>,
+        back: List<T>,
+        back_rev: List<T>,
+    },
+    Appending {
+        ok: usize,
+        front_rev: List<T>,
+        done: List<T>,
+    },
+    Done(List<T>),
+}
+
+pub struct Iter<'a, T> {
+    front: third::Iter<'a, T>,
+    // the rest of the queue, popped from the end
+    back: Vec<&'a T>,
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Implementation
+
+// Split a list into its head and tail.
+fn uncons<T: Clone>(list: &List<T>) -> Option<(T, List<T>)> {
+    let head = list.head()?.clone();
+    Some((head, list.tail().unwrap_or_default()))
+}
+
+impl<T: Clone> Rotation<T> {
+    // Do one step of the rotation.
+    fn step(self) -> Self {
+        match self {
+            Rotation::Reversing {
+                ok,
+                front,
+                front_rev,
+                back,
+                back_rev,
+            } => match (uncons(&front), uncons(&back)) {
+                (Some((x, front)), Some((y, back))) => Rotation::Reversing {
+                    ok: ok + 1,
+                    front,
+                    front_rev: front_rev.append(x),
+                    back,
+                    back_rev: back_rev.append(y),
+                },
+                // the back starts out one longer than the front
+                (None, Some((y, _))) => Rotation::Appending {
+                    ok,
+                    front_rev,
+                    done: back_rev.append(y),
+                },
+                _ => unreachable!("rotation started with a back that wasn't one longer"),
+            },
+            Rotation::Appending { ok: 0, done, .. } => Rotation::Done(done),
+            Rotation::Appending {
+                ok,
+                front_rev,
+                done,
+            } => {
+                let (x, front_rev) = uncons(&front_rev).expect("`ok` counts `front_rev`");
+                Rotation::Appending {
+                    ok: ok - 1,
+                    front_rev,
+                    done: done.append(x),
+                }
+            }
+            other => other,
+        }
+    }
+}
+
+impl<T> Rotation<T> {
+    // The oldest element was popped from the queue, so it doesn't need to be copied.
+    fn invalidate(self) -> Self {
+        match self {
+            Rotation::Reversing {
+                ok,
+                front,
+                front_rev,
+                back,
+                back_rev,
+            } => Rotation::Reversing {
+                ok: ok - 1,
+                front,
+                front_rev,
+                back,
+                back_rev,
+            },
+            // the element was already copied onto `done`, take it back off
+            Rotation::Appending { ok: 0, done, .. } => {
+                Rotation::Done(done.tail().unwrap_or_default())
+            }
+            Rotation::Appending {
+                ok,
+                front_rev,
+                done,
+            } => Rotation::Appending {
+                ok: ok - 1,
+                front_rev,
+                done,
+            },
+            other => other,
+        }
+    }
+}
+
+impl<T> Clone for Rotation<T> {
+    fn clone(&self) -> Self {
+        match self {
+            Rotation::Idle => Rotation::Idle,
+            Rotation::Reversing {
+                ok,
+                front,
+                front_rev,
+                back,
+                back_rev,
+            } => Rotation::Reversing {
+                ok: *ok,
+                front: front.clone(),
+                front_rev: front_rev.clone(),
+                back: back.clone(),
+                back_rev: back_rev.clone(),
+            },
+            Rotation::Appending {
+                ok,
+                front_rev,
+                done,
+            } => Rotation::Appending {
+                ok: *ok,
+                front_rev: front_rev.clone(),
+                done: done.clone(),
+            },
+            Rotation::Done(done) => Rotation::Done(done.clone()),
+        }
+    }
+}
+
+impl<T> Queue<T> {
+    pub fn new() -> Self {
+        Queue {
+            front_len: 0,
+            front: List::new(),
+            rotation: Rotation::Idle,
+            back_len: 0,
+            back: List::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.front_len + self.back_len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // peek at the oldest element
+    pub fn peek(&self) -> Option<&T> {
+        self.front.head()
+    }
+
+    // iterate from the oldest element to the newest
+    pub fn iter(&self) -> Iter<'_, T> {
+        // The queue is `front`, then whatever the rotation will put after it (which is the back
+        // the rotation started with, reversed), then the current back, reversed. Collect the
+        // last two in reverse order.
+        let mut back: Vec<&T> = self.back.iter().collect();
+        let start = back.len();
+        match &self.rotation {
+            Rotation::Reversing {
+                back: rest,
+                back_rev,
+                ..
+            } => {
+                back.extend(back_rev.iter());
+                back[start..].reverse();
+                back.extend(rest.iter());
+            }
+            Rotation::Appending { done, .. } => {
+                // `done` is some of the old front, then the old back reversed
+                let rotated = self.front_len - self.front.len();
+                let skip = done.len() - rotated;
+                back.extend(done.iter().skip(skip));
+                back[start..].reverse();
+            }
+            Rotation::Idle | Rotation::Done(_) => {}
+        }
+        Iter {
+            front: self.front.iter(),
+            back,
+        }
+    }
+}
+
+impl<T: Clone> Queue<T> {
+    // return a new queue with `elem` pushed onto the back
+    pub fn push_back(&self, elem: T) -> Queue<T> {
+        Queue {
+            front_len: self.front_len,
+            front: self.front.clone(),
+            rotation: self.rotation.clone(),
+            back_len: self.back_len + 1,
+            back: self.back.append(elem),
+        }
+        .check()
+    }
+
+    // return the oldest element and a new queue without it, or None if the queue is empty
+    pub fn pop_front(&self) -> Option<(T, Queue<T>)> {
+        let (elem, front) = uncons(&self.front)?;
+        let rest = Queue {
+            front_len: self.front_len - 1,
+            front,
+            rotation: self.rotation.clone().invalidate(),
+            back_len: self.back_len,
+            back: self.back.clone(),
+        }
+        .check();
+        Some((elem, rest))
+    }
+
+    // Start a rotation if the back got longer than the front, and do two steps of it.
+    fn check(self) -> Self {
+        let mut queue = if self.back_len <= self.front_len {
+            self
+        } else {
+            Queue {
+                front_len: self.front_len + self.back_len,
+                front: self.front.clone(),
+                rotation: Rotation::Reversing {
+                    ok: 0,
+                    front: self.front,
+                    front_rev: List::new(),
+                    back: self.back,
+                    back_rev: List::new(),
+                },
+                back_len: 0,
+                back: List::new(),
+            }
+        };
+        queue.rotation = match queue.rotation.step().step() {
+            Rotation::Done(front) => {
+                queue.front = front;
+                Rotation::Idle
+            }
+            rotation => rotation,
+        };
+        queue
+    }
+}
+
+impl<T> Default for Queue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// O(1): the new queue shares everything with this one.
+impl<T> Clone for Queue<T> {
+    fn clone(&self) -> Self {
+        Queue {
+            front_len: self.front_len,
+            front: self.front.clone(),
+            rotation: self.rotation.clone(),
+            back_len: self.back_len,
+            back: self.back.clone(),
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Iteration
+
+impl<'a, T> IntoIterator for &'a Queue<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.front.next().or_else(|| self.back.pop())
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Tests
+
+#[cfg(test)]
+mod test {
+    use super::Queue;
+    use std::cell::Cell;
+    use std::collections::VecDeque;
+
+    #[test]
+    fn basics() {
+        let queue = Queue::new();
+        assert!(queue.pop_front().is_none());
+        assert!(queue.is_empty());
+
+        let queue = queue.push_back(1).push_back(2).push_back(3);
+        assert_eq!(queue.len(), 3);
+        assert_eq!(queue.peek(), Some(&1));
+
+        let (x, queue) = queue.pop_front().unwrap();
+        assert_eq!(x, 1);
+        let queue = queue.push_back(4).push_back(5);
+        assert_eq!(queue.iter().collect::<Vec<_>>(), vec![&2, &3, &4, &5]);
+
+        let (x, queue) = queue.pop_front().unwrap();
+        assert_eq!(x, 2);
+        let (x, queue) = queue.pop_front().unwrap();
+        assert_eq!(x, 3);
+        let (x, queue) = queue.pop_front().unwrap();
+        assert_eq!(x, 4);
+        let (x, queue) = queue.pop_front().unwrap();
+        assert_eq!(x, 5);
+        assert!(queue.pop_front().is_none());
+        assert_eq!(queue.peek(), None);
+    }
+
+    #[test]
+    fn old_versions_are_unchanged() {
+        let mut versions = vec![Queue::new()];
+        for i in 0..20 {
+            let next = versions.last().unwrap().push_back(i);
+            versions.push(next);
+        }
+        for _ in 0..10 {
+            let (_, next) = versions.last().unwrap().pop_front().unwrap();
+            versions.push(next);
+        }
+
+        for (i, queue) in versions.iter().enumerate() {
+            let expected: Vec<i32> = if i <= 20 {
+                (0..i as i32).collect()
+            } else {
+                (i as i32 - 20..20).collect()
+            };
+            assert_eq!(queue.iter().copied().collect::<Vec<_>>(), expected);
+            assert_eq!(queue.len(), expected.len());
+            assert_eq!(queue.peek(), expected.first());
+        }
+    }
+
+    // Random pushes and pops, checked against a VecDeque. `iter` is checked after every step,
+    // since it has to piece the queue together from the middle of a rotation.
+    #[test]
+    fn against_vecdeque() {
+        let mut queue = Queue::new();
+        let mut model = VecDeque::new();
+        let mut seed: u32 = 17;
+        for i in 0..2000 {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            if (seed >> 16).is_multiple_of(3) {
+                match queue.pop_front() {
+                    Some((x, rest)) => {
+                        assert_eq!(Some(x), model.pop_front());
+                        queue = rest;
+                    }
+                    None => assert!(model.is_empty()),
+                }
+            } else {
+                queue = queue.push_back(i);
+                model.push_back(i);
+            }
+            assert_eq!(queue.peek(), model.front());
+            assert_eq!(queue.len(), model.len());
+            assert!(queue.iter().eq(model.iter()));
+        }
+    }
+
+    // No operation copies more than a handful of elements, however big the queue is.
+    #[test]
+    fn worst_case_constant() {
+        thread_local!(static CLONES: Cell<usize> = const { Cell::new(0) });
+        struct Counted;
+        impl Clone for Counted {
+            fn clone(&self) -> Self {
+                CLONES.with(|c| c.set(c.get() + 1));
+                Counted
+            }
+        }
+        let clones = || CLONES.with(|c| c.get());
+
+        // two rotation steps copy at most two elements each, and popping copies one more
+        const MAX_CLONES: usize = 5;
+        let mut queue = Queue::new();
+        for round in 0..3 {
+            for _ in 0..1000 * (round + 1) {
+                let before = clones();
+                queue = queue.push_back(Counted);
+                assert!(clones() - before <= MAX_CLONES);
+            }
+            for _ in 0..700 {
+                let before = clones();
+                queue = queue.pop_front().unwrap().1;
+                assert!(clones() - before <= MAX_CLONES);
+            }
+        }
+    }
+
+    #[test]
+    fn test_drop() {
+        let mut queue = Queue::new();
+        for i in 0..1000000 {
+            queue = queue.push_back(i);
+        }
+        let popped = queue.pop_front().unwrap().1;
+        assert_eq!(popped.len(), 999999);
+        // both queues are dropped here
+    }
+}
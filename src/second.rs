@@ -7,6 +7,19 @@
 //
 // Inspired by:
 // https://rust-unofficial.github.io/too-many-lists/second.html
+//
+// The list also keeps a pointer to its last node, so that `append` and `CursorMut::splice_after`
+// can link a whole list on in O(1). The nodes are owned through `NodeBox` rather than `Box` so
+// that pointer stays valid (see below).
+
+use std::cmp::Ordering;
+use std::fmt::{self, Debug};
+use std::hash::{Hash, Hasher};
+use std::iter::{FromIterator, FusedIterator};
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::{Deref, DerefMut};
+use std::ptr::NonNull;
 
 //////////////////////////////////////////////////////////////////////////////
 // Data structures
@@ -18,33 +31,118 @@
 
 pub struct List<T> {
     head: Link<T>,
+    // The last node, or None if the list is empty. Everything that changes which node is last
+    // updates it. It's only followed while the list is borrowed mutably, when nothing else can
+    // be looking at the nodes.
+    tail: Option<NonNull<Node<T>>>,
+    // the number of nodes, kept up to date by everything that links or unlinks one
+    len: usize,
 }
 
-type Link<T> = Option<Box<Node<T>>>;
+type Link<T> = Option<NodeBox<T>>;
 
 struct Node<T> {
     elem: T,
     next: Link<T>,
 }
 
+// An owning pointer to a node: a `Box` in all but name. A `Box` asserts that it's the only way
+// to reach its node every time it's moved or borrowed mutably, which would invalidate `tail`
+// (Miri rejects that under Stacked Borrows). `NodeBox` makes no such claim, so `tail` and the
+// `NodeBox` that owns the last node can both be used.
+struct NodeBox<T> {
+    ptr: NonNull<Node<T>>,
+    // for drop check: a `NodeBox` owns its node, like a `Box` would
+    _owns: PhantomData<Box<Node<T>>>,
+}
+
+impl<T> NodeBox<T> {
+    fn new(node: Node<T>) -> Self {
+        NodeBox {
+            // `Box::into_raw` never returns null
+            ptr: unsafe { NonNull::new_unchecked(Box::into_raw(Box::new(node))) },
+            _owns: PhantomData,
+        }
+    }
+
+    // a copy of the pointer, for `tail`
+    fn as_ptr(&self) -> NonNull<Node<T>> {
+        self.ptr
+    }
+
+    // Free the box, and return the node that was in it.
+    fn into_inner(self) -> Node<T> {
+        // `ptr` came from `Box::into_raw`, and forgetting `self` stops it from being freed twice
+        let node = unsafe { Box::from_raw(self.ptr.as_ptr()) };
+        mem::forget(self);
+        *node
+    }
+}
+
+impl<T> Deref for NodeBox<T> {
+    type Target = Node<T>;
+
+    fn deref(&self) -> &Node<T> {
+        // the node is valid for as long as we own it
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T> DerefMut for NodeBox<T> {
+    fn deref_mut(&mut self) -> &mut Node<T> {
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+impl<T> Drop for NodeBox<T> {
+    fn drop(&mut self) {
+        // `ptr` came from `Box::into_raw`
+        unsafe { drop(Box::from_raw(self.ptr.as_ptr())) }
+    }
+}
+
+// A `NodeBox` owns its node just like a `Box`, and a `List` owns all of its nodes, `tail`
+// included.
+unsafe impl<T: Send> Send for NodeBox<T> {}
+unsafe impl<T: Sync> Sync for NodeBox<T> {}
+unsafe impl<T: Send> Send for List<T> {}
+unsafe impl<T: Sync> Sync for List<T> {}
+
 //////////////////////////////////////////////////////////////////////////////
 // Implementation
 
 impl<T> List<T> {
     // return a new, empty list
     pub fn new() -> Self {
-        List { head: None }
+        List {
+            head: None,
+            tail: None,
+            len: 0,
+        }
+    }
+
+    // the number of elements on the stack, in O(1)
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
     }
 
     // push an integer onto the given stack
     pub fn push(&mut self, x: T) {
-        let new_box_node = Box::new(Node {
+        let new_box_node = NodeBox::new(Node {
             elem: x,
             // Option::take extracts the content and replaces it with a new None
             // equivalent to mem::replace(&mut self.head, None)
             next: self.head.take(),
         });
+        if self.tail.is_none() {
+            self.tail = Some(new_box_node.as_ptr());
+        }
         self.head = Some(new_box_node);
+        self.len += 1;
     }
 
     // pop an integer from the stack, returning either Some(value) or None if
@@ -52,7 +150,12 @@ impl<T> List<T> {
     pub fn pop(&mut self) -> Option<T> {
         // map a lambda over the content of self.head that includes self.head in its closure
         self.head.take().map(|node| {
+            let node = node.into_inner();
             self.head = node.next;
+            self.len -= 1;
+            if self.head.is_none() {
+                self.tail = None;
+            }
             node.elem
         })
     }
@@ -70,6 +173,12 @@ impl<T> List<T> {
     }
 }
 
+impl<T> Default for List<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 // A non-recursive Drop implementation so we don't blow the stack when
 // dropping large lists.
 impl<T> Drop for List<T> {
@@ -95,9 +204,12 @@ impl<T> Drop for List<T> {
 pub struct IntoIter<T>(List<T>);
 
 // Provide List<T> with a method for converting to an iterator
-impl<T> List<T> {
+impl<T> IntoIterator for List<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
     // into_iter consumes `self`, returning an `IntoIter<T>`
-    pub fn into_iter(self) -> IntoIter<T> {
+    fn into_iter(self) -> IntoIter<T> {
         IntoIter(self)
     }
 }
@@ -108,27 +220,44 @@ impl<T> Iterator for IntoIter<T> {
     fn next(&mut self) -> Option<Self::Item> {
         self.0.pop()
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.0.len, Some(self.0.len))
+    }
 }
 
+impl<T> ExactSizeIterator for IntoIter<T> {}
+impl<T> FusedIterator for IntoIter<T> {}
+
 //
 // Iter
 //
 pub struct Iter<'a, T> {
     next: Option<&'a Node<T>>,
+    len: usize,
 }
 
 impl<T> List<T> {
     // self needs to live at least as long as the iter. We elide the lifetimes.
     pub fn iter(&self) -> Iter<'_, T> {
         Iter {
-            // remember: map<U, F>(self, f: F) -> Option<U>
-            // turbofish operator ::<> lets us (partially) spec the generic types
-            // or, more janky is to write: &**node in place of &node below
-            next: self.head.as_ref().map::<&Node<T>, _>(|node| &node),
+            // Option::as_deref :: &Option<NodeBox<N>> -> Option<&N>
+            // the equivalent by hand is: self.head.as_ref().map(|node| &**node)
+            next: self.head.as_deref(),
+            len: self.len,
         }
     }
 }
 
+impl<'a, T> IntoIterator for &'a List<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
 impl<'a, T> Iterator for Iter<'a, T> {
     type Item = &'a T;
 
@@ -138,38 +267,735 @@ impl<'a, T> Iterator for Iter<'a, T> {
     // Note: we don't technically need to take() `self.next` here since & is Copy.
     fn next(&mut self) -> Option<Self::Item> {
         self.next.take().map(|node| {
-            // self.next = node.next.map(|nnode| &nnode);
-            self.next = node.next.as_ref().map::<&Node<T>, _>(|node| &node);
+            self.next = node.next.as_deref();
+            self.len -= 1;
             &node.elem
         })
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
 }
 
+impl<'a, T> ExactSizeIterator for Iter<'a, T> {}
+impl<'a, T> FusedIterator for Iter<'a, T> {}
+
 //
 // IterMut
 //
 pub struct IterMut<'a, T> {
     next: Option<&'a mut Node<T>>,
+    len: usize,
 }
 
 impl<T> List<T> {
     pub fn iter_mut(&mut self) -> IterMut<'_, T> {
         IterMut {
-            next: self.head.as_mut().map(|node| &mut **node),
+            next: self.head.as_deref_mut(),
+            len: self.len,
         }
     }
 }
 
+impl<'a, T> IntoIterator for &'a mut List<T> {
+    type Item = &'a mut T;
+    type IntoIter = IterMut<'a, T>;
+
+    fn into_iter(self) -> IterMut<'a, T> {
+        self.iter_mut()
+    }
+}
+
 impl<'a, T> Iterator for IterMut<'a, T> {
     type Item = &'a mut T;
 
     // We must take() `self.next` here because &mut is not Copy.
     fn next(&mut self) -> Option<Self::Item> {
         self.next.take().map(|node| {
-            self.next = node.next.as_mut().map(|next_node| &mut **next_node);
+            self.next = node.next.as_deref_mut();
+            self.len -= 1;
             &mut node.elem
         })
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, T> ExactSizeIterator for IterMut<'a, T> {}
+impl<'a, T> FusedIterator for IterMut<'a, T> {}
+
+//////////////////////////////////////////////////////////////////////////////
+// Sorting
+//
+// The sorts relink the existing nodes instead of moving elements around, so they don't allocate.
+// They're a bottom-up merge sort: the first pass merges runs of 1 node into sorted runs of 2, the
+// next merges those into runs of 4, and so on, until a pass only makes one run. Each pass is a
+// loop over the list, so the sort takes O(n log n) time, O(1) extra memory, and never recurses.
+//
+// Based on:
+// Simon Tatham, "Mergesort For Linked Lists",
+// https://www.chiark.greenend.org.uk/~sgtatham/algorithms/listsort.html
+//
+// Like `slice::sort_by`, if the comparison panics every element is still in the list afterwards,
+// in some unspecified order. Nodes that are being merged or waiting to be are linked back on as
+// the panic unwinds (see `Unmerged` and `Merging`).
+
+impl<T> List<T> {
+    pub fn sort_by<F>(&mut self, mut compare: F)
+    where
+        F: FnMut(&T, &T) -> Ordering,
+    {
+        let mut width = 1;
+        let mut unmerged = Unmerged {
+            list: self,
+            rest: None,
+        };
+        loop {
+            unmerged.rest = unmerged.list.head.take();
+            let mut tail = &mut unmerged.list.head;
+            let mut runs = 0;
+            while unmerged.rest.is_some() {
+                let mut left = unmerged.rest.take();
+                let mut right = split_link(&mut left, width);
+                unmerged.rest = split_link(&mut right, width);
+                tail = merge_links(left, right, tail, &mut compare);
+                runs += 1;
+            }
+            if runs <= 1 {
+                return;
+            }
+            width *= 2;
+        }
+    }
+
+    pub fn sort_by_key<K, F>(&mut self, mut f: F)
+    where
+        K: Ord,
+        F: FnMut(&T) -> K,
+    {
+        self.sort_by(|a, b| f(a).cmp(&f(b)))
+    }
+
+    // Merge sort is stable anyway; this is here for parity with slices.
+    pub fn sort_unstable_by<F>(&mut self, compare: F)
+    where
+        F: FnMut(&T, &T) -> Ordering,
+    {
+        self.sort_by(compare)
+    }
+}
+
+impl<T: Ord> List<T> {
+    pub fn sort(&mut self) {
+        self.sort_by(T::cmp)
+    }
+
+    // Merge `other` into this list. If both are sorted, so is the result. Elements of `self` come
+    // before equal elements of `other`.
+    pub fn merge_sorted(&mut self, mut other: List<T>) {
+        // counted up front, since every node ends up in this list even if `cmp` panics
+        self.len += mem::take(&mut other.len);
+        let left = self.head.take();
+        let right = other.head.take();
+        let unmerged = Unmerged {
+            list: self,
+            rest: None,
+        };
+        merge_links(left, right, &mut unmerged.list.head, &mut T::cmp);
+    }
+}
+
+impl<T: PartialOrd> List<T> {
+    pub fn is_sorted(&self) -> bool {
+        self.iter().zip(self.iter().skip(1)).all(|(a, b)| a <= b)
+    }
+}
+
+// Cut `link` after `n` nodes, and return the rest.
+fn split_link<T>(link: &mut Link<T>, n: usize) -> Link<T> {
+    nth_link(link, n).and_then(Option::take)
+}
+
+// Merge two sorted lists onto the (empty) link `tail`, and return the empty link at the end of
+// the result. On ties, `left` goes first, which keeps the sort stable.
+fn merge_links<'a, T, F>(
+    left: Link<T>,
+    right: Link<T>,
+    tail: &'a mut Link<T>,
+    compare: &mut F,
+) -> &'a mut Link<T>
+where
+    F: FnMut(&T, &T) -> Ordering,
+{
+    let mut merging = Merging {
+        left,
+        right,
+        tail: Some(tail),
+    };
+    while let (Some(l), Some(r)) = (&merging.left, &merging.right) {
+        let from = if compare(&l.elem, &r.elem) == Ordering::Greater {
+            &mut merging.right
+        } else {
+            &mut merging.left
+        };
+        let mut node = from.take().unwrap();
+        *from = node.next.take();
+        let tail = merging.tail.take().unwrap();
+        merging.tail = Some(&mut tail.insert(node).next);
+    }
+    // one side is used up: link the other one in whole
+    let mut tail = merging.tail.take().unwrap();
+    *tail = merging.left.take().or(merging.right.take());
+    while let Some(node) = tail {
+        tail = &mut node.next;
+    }
+    tail
+}
+
+// A merge in progress. If `compare` panics, dropping this links what's left of both halves onto
+// the end of what's been merged so far.
+struct Merging<'a, T> {
+    left: Link<T>,
+    right: Link<T>,
+    // the empty link at the end of the merged nodes, or None once the merge is done
+    tail: Option<&'a mut Link<T>>,
+}
+
+impl<'a, T> Drop for Merging<'a, T> {
+    fn drop(&mut self) {
+        if let Some(mut tail) = self.tail.take() {
+            *tail = self.left.take();
+            while let Some(node) = tail {
+                tail = &mut node.next;
+            }
+            *tail = self.right.take();
+        }
+    }
+}
+
+// A list whose nodes are being sorted, with `rest` holding the runs that the current pass hasn't
+// merged yet. When it's dropped (the sort is done, or the comparison panicked), `rest` goes back
+// on the end of the list and `tail` is pointed at the last node again.
+struct Unmerged<'a, T> {
+    list: &'a mut List<T>,
+    rest: Link<T>,
+}
+
+impl<'a, T> Drop for Unmerged<'a, T> {
+    fn drop(&mut self) {
+        let mut link = &mut self.list.head;
+        while let Some(node) = link {
+            link = &mut node.next;
+        }
+        *link = self.rest.take();
+        self.list.find_tail();
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Editing
+//
+// Like the sorts, these relink the existing nodes: the only node ever allocated is the one
+// `insert` adds. Indices count from the head, like the iterators do.
+
+impl<T> List<T> {
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.iter().nth(index)
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.iter_mut().nth(index)
+    }
+
+    // Insert `elem` so that it ends up at `index`. Panics if `index` is past the end of the list.
+    pub fn insert(&mut self, index: usize, elem: T) {
+        let link = nth_link(&mut self.head, index).expect("insertion index is out of bounds");
+        let next = link.take();
+        let at_end = next.is_none();
+        let node = link.insert(NodeBox::new(Node { elem, next })).as_ptr();
+        if at_end {
+            self.tail = Some(node);
+        }
+        self.len += 1;
+    }
+
+    // Remove and return the element at `index`, or None if there isn't one.
+    pub fn remove(&mut self, index: usize) -> Option<T> {
+        let link = nth_link(&mut self.head, index)?;
+        let node = link.take()?.into_inner();
+        *link = node.next;
+        self.len -= 1;
+        if index == self.len {
+            // that was the last node; walking to the new one costs no more than finding it did
+            self.find_tail();
+        }
+        Some(node.elem)
+    }
+
+    // Reverse the list in place.
+    pub fn reverse(&mut self) {
+        // the front node ends up last
+        self.tail = self.head.as_ref().map(NodeBox::as_ptr);
+        let mut rest = self.head.take();
+        while let Some(mut node) = rest {
+            rest = node.next.take();
+            node.next = self.head.take();
+            self.head = Some(node);
+        }
+    }
+
+    // Move all of `other`'s elements onto the end of this list in O(1), leaving `other` empty.
+    pub fn append(&mut self, other: &mut List<T>) {
+        let head = other.head.take();
+        match self.tail {
+            // we have the list, and so the last node, to ourselves
+            Some(tail) => unsafe { (*tail.as_ptr()).next = head },
+            None => self.head = head,
+        }
+        if let Some(tail) = other.tail.take() {
+            self.tail = Some(tail);
+        }
+        self.len += mem::take(&mut other.len);
+    }
+
+    // Split the list in two at `at`: this list keeps the elements before it, and the rest are
+    // returned. Panics if `at` is past the end of the list.
+    pub fn split_off(&mut self, at: usize) -> List<T> {
+        let link = nth_link(&mut self.head, at).expect("split index is out of bounds");
+        let head = link.take();
+        let len = self.len - at;
+        self.len = at;
+        let tail = match head {
+            Some(_) => {
+                let tail = self.tail;
+                self.find_tail();
+                tail
+            }
+            None => None,
+        };
+        List { head, tail, len }
+    }
+
+    // Keep the first `len` elements and drop the rest. Does nothing if the list is no longer than
+    // that.
+    pub fn truncate(&mut self, len: usize) {
+        if len < self.len {
+            // dropped as a list, so it doesn't recurse
+            drop(self.split_off(len));
+        }
+    }
+
+    // Keep only the elements `f` returns true for, in order.
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&T) -> bool,
+    {
+        self.retain_mut(|elem| f(elem))
+    }
+
+    pub fn retain_mut<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        self.extract_if(|elem| !f(elem)).for_each(drop);
+    }
+
+    // Remove all but the first of each run of consecutive elements that `same_bucket` says are
+    // the same. Like `Vec::dedup_by`, it's called as `same_bucket(elem, kept)`, where `kept` is
+    // the element before `elem` that's being kept.
+    pub fn dedup_by<F>(&mut self, mut same_bucket: F)
+    where
+        F: FnMut(&mut T, &mut T) -> bool,
+    {
+        // `kept_ptr` is the same node as `kept`, for `tail`
+        let (mut kept, mut kept_ptr): (&mut Node<T>, _) = match self.head.as_mut() {
+            Some(node) => {
+                let ptr = node.as_ptr();
+                (node, ptr)
+            }
+            None => return,
+        };
+        while let Some(next) = kept.next.as_mut() {
+            if same_bucket(&mut next.elem, &mut kept.elem) {
+                let removed = kept.next.take().unwrap().into_inner();
+                kept.next = removed.next;
+                if kept.next.is_none() {
+                    self.tail = Some(kept_ptr);
+                }
+                self.len -= 1;
+                // the removed element is dropped here, once the list is consistent again, in
+                // case dropping it panics
+            } else {
+                let next = kept.next.as_mut().unwrap();
+                kept_ptr = next.as_ptr();
+                kept = next;
+            }
+        }
+    }
+
+    // An iterator that removes the elements `filter` returns true for, and yields them. `filter`
+    // can mutate the elements it looks at. Dropping the iterator early leaves the rest of the
+    // list alone.
+    pub fn extract_if<F>(&mut self, filter: F) -> ExtractIf<'_, T, F>
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        ExtractIf {
+            link: Some(&mut self.head),
+            len: &mut self.len,
+            tail: &mut self.tail,
+            prev: None,
+            filter,
+        }
+    }
+
+    // Point `tail` at the last node again, for the operations that walk the whole list anyway.
+    fn find_tail(&mut self) {
+        let mut tail = None;
+        let mut link = &self.head;
+        while let Some(node) = link {
+            tail = Some(node.as_ptr());
+            link = &node.next;
+        }
+        self.tail = tail;
+    }
+}
+
+// The link that holds the node at `index`: the (empty) link at the end if `index` is the length
+// of the list, or None if it's past that.
+fn nth_link<T>(link: &mut Link<T>, index: usize) -> Option<&mut Link<T>> {
+    let mut cur = link;
+    for _ in 0..index {
+        cur = &mut cur.as_mut()?.next;
+    }
+    Some(cur)
+}
+
+pub struct ExtractIf<'a, T, F> {
+    // the link to the next node to look at
+    link: Option<&'a mut Link<T>>,
+    // the list's length and last node
+    len: &'a mut usize,
+    tail: &'a mut Option<NonNull<Node<T>>>,
+    // the node `link` belongs to, or None if it's the head
+    prev: Option<NonNull<Node<T>>>,
+    filter: F,
+}
+
+impl<'a, T, F> Iterator for ExtractIf<'a, T, F>
+where
+    F: FnMut(&mut T) -> bool,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let link = self.link.take()?;
+            let node = link.as_mut()?;
+            if (self.filter)(&mut node.elem) {
+                // unlink it, and look at the node that takes its place next
+                let node = link.take().unwrap().into_inner();
+                *link = node.next;
+                if link.is_none() {
+                    *self.tail = self.prev;
+                }
+                *self.len -= 1;
+                self.link = Some(link);
+                return Some(node.elem);
+            }
+            self.prev = link.as_ref().map(NodeBox::as_ptr);
+            self.link = Some(&mut link.as_mut().unwrap().next);
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Cursors
+//
+// A cursor sits on an element, or on the ghost before the front of the list. It can only move
+// forward, and it edits after itself: that's where a singly-linked list can be changed in O(1).
+// On the ghost, "after" is the front of the list. Cursors don't wrap around: `move_next` at the
+// last element stays put and returns false. `index` is the position of the current element, or
+// None on the ghost.
+//
+// Rather than the current node, a cursor holds the link the current node hangs off (on the ghost,
+// the list's head). That's what lets a safe `CursorMut` reach everything it edits.
+
+//
+// Cursor
+//
+pub struct Cursor<'a, T> {
+    link: &'a Link<T>,
+    index: Option<usize>,
+}
+
+impl<T> List<T> {
+    // a read-only cursor on the ghost before the front element
+    pub fn cursor(&self) -> Cursor<'_, T> {
+        Cursor {
+            link: &self.head,
+            index: None,
+        }
+    }
+}
+
+impl<'a, T> Cursor<'a, T> {
+    pub fn index(&self) -> Option<usize> {
+        self.index
+    }
+
+    // Move to the next element. At the last element, stay put and return false.
+    pub fn move_next(&mut self) -> bool {
+        let next = self.next_link();
+        if next.is_none() {
+            return false;
+        }
+        self.link = next;
+        self.index = Some(self.index.map_or(0, |idx| idx + 1));
+        true
+    }
+
+    pub fn current(&self) -> Option<&'a T> {
+        self.index?;
+        self.link.as_ref().map(|node| &node.elem)
+    }
+
+    pub fn peek_next(&self) -> Option<&'a T> {
+        self.next_link().as_ref().map(|node| &node.elem)
+    }
+
+    // the link after the cursor
+    fn next_link(&self) -> &'a Link<T> {
+        match (self.index, self.link) {
+            (Some(_), Some(node)) => &node.next,
+            _ => self.link,
+        }
+    }
+}
+
+impl<'a, T> Clone for Cursor<'a, T> {
+    fn clone(&self) -> Self {
+        Cursor { ..*self }
+    }
+}
+
+//
+// CursorMut
+//
+pub struct CursorMut<'a, T> {
+    // Always Some. It's an Option so that `move_next` can move it out and put the next link back.
+    link: Option<&'a mut Link<T>>,
+    index: Option<usize>,
+    // the list's length and last node
+    len: &'a mut usize,
+    tail: &'a mut Option<NonNull<Node<T>>>,
+}
+
+impl<T> List<T> {
+    // an editing cursor on the ghost before the front element
+    pub fn cursor_mut(&mut self) -> CursorMut<'_, T> {
+        CursorMut {
+            link: Some(&mut self.head),
+            index: None,
+            len: &mut self.len,
+            tail: &mut self.tail,
+        }
+    }
+}
+
+impl<'a, T> CursorMut<'a, T> {
+    pub fn index(&self) -> Option<usize> {
+        self.index
+    }
+
+    // Move to the next element. At the last element, stay put and return false.
+    pub fn move_next(&mut self) -> bool {
+        if self.as_cursor().peek_next().is_none() {
+            return false;
+        }
+        let link = self.link.take().unwrap();
+        self.link = Some(match self.index {
+            Some(_) => &mut link.as_mut().unwrap().next,
+            // on the ghost, the front element already hangs off `link`
+            None => link,
+        });
+        self.index = Some(self.index.map_or(0, |idx| idx + 1));
+        true
+    }
+
+    pub fn current(&mut self) -> Option<&mut T> {
+        self.index?;
+        self.link
+            .as_deref_mut()?
+            .as_mut()
+            .map(|node| &mut node.elem)
+    }
+
+    pub fn peek_next(&mut self) -> Option<&mut T> {
+        self.next_link().as_mut().map(|node| &mut node.elem)
+    }
+
+    // a read-only view of this cursor, borrowing it for the view's lifetime
+    pub fn as_cursor(&self) -> Cursor<'_, T> {
+        Cursor {
+            link: self.link.as_deref().unwrap(),
+            index: self.index,
+        }
+    }
+
+    // Insert `elem` after the current element. On the ghost, `elem` becomes the new front.
+    pub fn insert_after(&mut self, elem: T) {
+        let link = self.next_link();
+        let next = link.take();
+        let at_end = next.is_none();
+        let node = link.insert(NodeBox::new(Node { elem, next })).as_ptr();
+        if at_end {
+            *self.tail = Some(node);
+        }
+        *self.len += 1;
+    }
+
+    // Remove and return the element after the current one. On the ghost, that's the front.
+    pub fn remove_after(&mut self) -> Option<T> {
+        let link = self.next_link();
+        let node = link.take()?.into_inner();
+        *link = node.next;
+        if link.is_none() {
+            *self.tail = self.current_node();
+        }
+        *self.len -= 1;
+        Some(node.elem)
+    }
+
+    // Split the list in two after the current element. Everything after the cursor is returned
+    // and the original list keeps everything up to and including the current element. On the
+    // ghost the entire list is returned.
+    pub fn split_after(&mut self) -> List<T> {
+        let head = self.next_link().take();
+        let kept = self.index.map_or(0, |idx| idx + 1);
+        let len = *self.len - kept;
+        *self.len = kept;
+        let tail = match head {
+            Some(_) => mem::replace(self.tail, self.current_node()),
+            None => None,
+        };
+        List { head, tail, len }
+    }
+
+    // Insert the whole of `input` between the current element and the one after it, in O(1). On
+    // the ghost `input` is prepended to the front of the list.
+    pub fn splice_after(&mut self, mut input: List<T>) {
+        let last = match input.tail.take() {
+            Some(last) => last,
+            None => return,
+        };
+        let rest = self.next_link().take();
+        if rest.is_none() {
+            *self.tail = Some(last);
+        }
+        // we own `input`, and so its last node
+        unsafe { (*last.as_ptr()).next = rest };
+        *self.next_link() = input.head.take();
+        *self.len += mem::take(&mut input.len);
+    }
+
+    // the current node, or None on the ghost
+    fn current_node(&self) -> Option<NonNull<Node<T>>> {
+        self.index?;
+        self.link.as_deref()?.as_ref().map(NodeBox::as_ptr)
+    }
+
+    // the link after the cursor
+    fn next_link(&mut self) -> &mut Link<T> {
+        let link = self.link.as_deref_mut().unwrap();
+        match self.index {
+            Some(_) => &mut link.as_mut().unwrap().next,
+            None => link,
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Standard traits
+//
+// Everything here walks the list with a loop (usually via `iter`) rather than recursing down the
+// `next` links, so these are as stack-safe as `Drop`. Deriving them would recurse.
+
+// Build a list in iteration order, so that `list.iter().collect::<List<_>>()` gives back an equal
+// list rather than a reversed one.
+impl<T> FromIterator<T> for List<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut list = List::new();
+        list.extend(iter);
+        list
+    }
+}
+
+// Extending a stack puts the new elements on top, keeping them in iteration order:
+// extending [3, 4] with [1, 2] gives [1, 2, 3, 4]. On an empty list this is the same as `collect`.
+impl<T> Extend<T> for List<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        // Build the new elements into a list of their own, and link this one onto the end of it
+        // once they're all there. If `iter` panics, this list is left as it was.
+        let mut new = List::new();
+        // `tail` is always the (empty) link at the end of the new elements
+        let mut tail = &mut new.head;
+        for elem in iter {
+            let node = tail.insert(NodeBox::new(Node { elem, next: None }));
+            new.tail = Some(node.as_ptr());
+            tail = &mut node.next;
+            new.len += 1;
+        }
+        new.append(self);
+        *self = new;
+    }
+}
+
+impl<T: Clone> Clone for List<T> {
+    fn clone(&self) -> Self {
+        self.iter().cloned().collect()
+    }
+}
+
+impl<T: Debug> Debug for List<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: PartialEq> PartialEq for List<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Eq> Eq for List<T> {}
+
+// Lists compare lexicographically, like slices.
+impl<T: PartialOrd> PartialOrd for List<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.iter().partial_cmp(other.iter())
+    }
+}
+
+impl<T: Ord> Ord for List<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.iter().cmp(other.iter())
+    }
+}
+
+impl<T: Hash> Hash for List<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // Hash the length first, like slices do. Without it, nested lists like [[1], [2]] and
+        // [[1, 2]] would feed the hasher the same values.
+        self.len.hash(state);
+        for elem in self.iter() {
+            elem.hash(state);
+        }
+    }
 }
 
 //////////////////////////////////////////////////////////////////////////////
@@ -178,6 +1004,12 @@ impl<'a, T> Iterator for IterMut<'a, T> {
 #[cfg(test)]
 mod test {
     use super::List;
+    use std::cmp::Ordering;
+    use std::collections::hash_map::DefaultHasher;
+    use std::collections::HashMap;
+    use std::hash::{Hash, Hasher};
+    use std::io::{self, Write};
+    use std::panic::{self, AssertUnwindSafe};
 
     #[test]
     fn basic() {
@@ -219,6 +1051,7 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::option_map_unit_fn)] // the closure form is the point of the example
     fn peek() {
         let mut list = List::new();
 
@@ -288,4 +1121,607 @@ mod test {
         assert_eq!(iter_mut.next(), Some(&mut 1));
         assert_eq!(iter_mut.next(), None);
     }
+
+    #[test]
+    fn collect_and_extend() {
+        let list: List<i32> = vec![1, 2, 3].into_iter().collect();
+        assert_eq!(list.iter().collect::<Vec<_>>(), vec![&1, &2, &3]);
+
+        // extend puts the new elements on top, in order
+        let mut list = list;
+        list.extend(vec![-1, 0]);
+        assert_eq!(list.iter().collect::<Vec<_>>(), vec![&-1, &0, &1, &2, &3]);
+        list.extend(Vec::new());
+        assert_eq!(list.peek(), Some(&-1));
+
+        let mut empty = List::new();
+        empty.extend(vec![1, 2, 3]);
+        assert_eq!(empty, vec![1, 2, 3].into_iter().collect());
+    }
+
+    #[test]
+    fn for_loops() {
+        let mut list: List<i32> = vec![1, 2, 3].into_iter().collect();
+        for x in &mut list {
+            *x *= 2;
+        }
+        let mut total = 0;
+        for x in &list {
+            total += *x;
+        }
+        assert_eq!(total, 12);
+        let mut v = Vec::new();
+        for x in list {
+            v.push(x);
+        }
+        assert_eq!(v, vec![2, 4, 6]);
+    }
+
+    #[test]
+    fn traits() {
+        let list: List<i32> = vec![1, 2, 3].into_iter().collect();
+        let copy = list.clone();
+        assert_eq!(list, copy);
+        assert_eq!(List::<i32>::default(), List::new());
+        assert_eq!(format!("{:?}", list), "[1, 2, 3]");
+        assert_eq!(format!("{:?}", List::<i32>::new()), "[]");
+
+        let shorter: List<i32> = vec![1, 2].into_iter().collect();
+        let bigger: List<i32> = vec![1, 3].into_iter().collect();
+        assert_ne!(list, shorter);
+        assert!(shorter < list);
+        assert!(list < bigger);
+        assert_eq!(list.cmp(&copy), Ordering::Equal);
+        assert_eq!(
+            List::<f64>::new().partial_cmp(&List::new()),
+            Some(Ordering::Equal)
+        );
+
+        // lists can be used as map keys
+        let mut map = HashMap::new();
+        map.insert(list, "list");
+        map.insert(shorter, "shorter");
+        assert_eq!(map.get(&copy), Some(&"list"));
+        assert_eq!(map.get(&bigger), None);
+    }
+
+    #[test]
+    fn sort() {
+        // every length up to 40, in a scrambled order
+        let mut seed: u32 = 3;
+        for n in 0..40 {
+            let mut v: Vec<u32> = (0..n)
+                .map(|_| {
+                    seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                    (seed >> 16) % 10
+                })
+                .collect();
+            let mut list: List<u32> = v.iter().copied().collect();
+            assert_eq!(list.is_sorted(), v.windows(2).all(|w| w[0] <= w[1]));
+            list.sort();
+            v.sort();
+            assert!(list.iter().eq(v.iter()));
+            assert!(list.is_sorted());
+        }
+
+        let mut list: List<i32> = vec![3, 1, 2].into_iter().collect();
+        list.sort_by(|a, b| b.cmp(a));
+        assert_eq!(list, vec![3, 2, 1].into_iter().collect());
+        list.sort_unstable_by(|a, b| a.cmp(b));
+        assert_eq!(list, vec![1, 2, 3].into_iter().collect());
+    }
+
+    #[test]
+    fn sort_is_stable() {
+        let pairs = vec![(2, 'a'), (1, 'b'), (2, 'c'), (1, 'd'), (0, 'e'), (2, 'f')];
+        let mut list: List<(i32, char)> = pairs.into_iter().collect();
+        list.sort_by_key(|&(key, _)| key);
+        let order: String = list.iter().map(|&(_, c)| c).collect();
+        assert_eq!(order, "ebdacf");
+    }
+
+    // Sorting moves nodes, not elements: every element stays at the same address.
+    #[test]
+    fn sort_relinks_nodes() {
+        let mut list: List<i32> = vec![5, 3, 4, 1, 2].into_iter().collect();
+        let addresses = |list: &List<i32>| {
+            let mut v: Vec<*const i32> = list.iter().map(|x| x as *const i32).collect();
+            v.sort();
+            v
+        };
+        let before = addresses(&list);
+        let three = list.iter().nth(1).unwrap() as *const i32;
+        list.sort();
+        assert_eq!(addresses(&list), before);
+        assert_eq!(list.iter().nth(2).unwrap() as *const i32, three);
+    }
+
+    #[test]
+    fn merge_sorted() {
+        let mut list: List<i32> = vec![1, 3, 5].into_iter().collect();
+        list.merge_sorted(vec![0, 3, 6, 7].into_iter().collect());
+        assert_eq!(list, vec![0, 1, 3, 3, 5, 6, 7].into_iter().collect());
+
+        let mut empty = List::new();
+        empty.merge_sorted(vec![1, 2].into_iter().collect());
+        assert_eq!(empty, vec![1, 2].into_iter().collect());
+        empty.merge_sorted(List::new());
+        assert_eq!(empty, vec![1, 2].into_iter().collect());
+    }
+
+    // An `Ord` that can't compare 4.
+    #[derive(PartialEq, Eq, Debug)]
+    struct Touchy(i32);
+
+    impl Ord for Touchy {
+        fn cmp(&self, other: &Self) -> Ordering {
+            if self.0 == 4 || other.0 == 4 {
+                panic!("can't compare 4");
+            }
+            self.0.cmp(&other.0)
+        }
+    }
+
+    impl PartialOrd for Touchy {
+        fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    // Like `slice::sort_by`: if the comparison panics, every element is still in the list.
+    #[test]
+    fn sort_panic_keeps_every_element() {
+        // sorting 8 elements takes at least 12 comparisons
+        for panic_at in 1..=12 {
+            let mut list: List<i32> = vec![5, 2, 7, 0, 3, 6, 1, 4].into_iter().collect();
+            let mut calls = 0;
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                list.sort_by(|a, b| {
+                    calls += 1;
+                    if calls == panic_at {
+                        panic!("comparison {} failed", calls);
+                    }
+                    a.cmp(b)
+                })
+            }));
+            assert!(result.is_err());
+            assert_eq!(list.len(), 8);
+            let mut elems: Vec<i32> = list.iter().copied().collect();
+            elems.sort();
+            assert_eq!(elems, (0..8).collect::<Vec<_>>());
+        }
+
+        let mut list: List<Touchy> = vec![1, 3, 5].into_iter().map(Touchy).collect();
+        let other = vec![0, 4, 6].into_iter().map(Touchy).collect();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| list.merge_sorted(other)));
+        assert!(result.is_err());
+        assert_eq!(list.len(), 6);
+        let mut elems: Vec<i32> = list.iter().map(|t| t.0).collect();
+        elems.sort();
+        assert_eq!(elems, vec![0, 1, 3, 4, 5, 6]);
+    }
+
+    // A panic partway through one of these leaves the tail pointer at the real last node, so
+    // appending to the list afterwards is fine.
+    #[test]
+    fn panics_keep_the_tail_valid() {
+        let mut list: List<i32> = (0..8).collect();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| list.sort_by(|_, _| panic!("sort"))));
+        assert!(result.is_err());
+        list.append(&mut vec![8].into_iter().collect());
+        assert_eq!(list.len(), 9);
+        assert_eq!(list.iter().last(), Some(&8));
+
+        let mut list: List<Touchy> = vec![0, 1].into_iter().map(Touchy).collect();
+        let other = vec![4, 5].into_iter().map(Touchy).collect();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| list.merge_sorted(other)));
+        assert!(result.is_err());
+        list.append(&mut vec![Touchy(6)].into_iter().collect());
+        assert_eq!(list.len(), 5);
+        assert_eq!(list.iter().last(), Some(&Touchy(6)));
+
+        let mut list: List<i32> = (0..3).collect();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            list.extend((3..6).map(|x| if x == 5 { panic!("extend") } else { x }))
+        }));
+        assert!(result.is_err());
+        assert_eq!(list, (0..3).collect());
+        list.append(&mut vec![3].into_iter().collect());
+        assert_eq!(list.len(), 4);
+        assert_eq!(list.iter().last(), Some(&3));
+
+        // dropping the last element panics, after it's been unlinked
+        struct PanicOnDrop(i32);
+        impl Drop for PanicOnDrop {
+            fn drop(&mut self) {
+                if self.0 < 0 {
+                    panic!("dropped {}", self.0);
+                }
+            }
+        }
+        let mut list: List<PanicOnDrop> =
+            vec![PanicOnDrop(1), PanicOnDrop(-1)].into_iter().collect();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| list.dedup_by(|_, _| true)));
+        assert!(result.is_err());
+        list.append(&mut vec![PanicOnDrop(2)].into_iter().collect());
+        assert_eq!(list.len(), 2);
+        assert_eq!(list.iter().map(|x| x.0).collect::<Vec<_>>(), vec![1, 2]);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // too slow under Miri
+    fn sort_is_stack_safe() {
+        let mut list: List<i32> = (0..1000000).rev().collect();
+        assert!(!list.is_sorted());
+        list.sort();
+        assert!(list.is_sorted());
+        assert!(list.iter().copied().eq(0..1000000));
+    }
+
+    fn from_vec(v: Vec<i32>) -> List<i32> {
+        v.into_iter().collect()
+    }
+
+    #[test]
+    fn editing() {
+        let mut list = from_vec(vec![1, 2, 3]);
+        assert_eq!(list.get(1), Some(&2));
+        assert_eq!(list.get(3), None);
+        *list.get_mut(0).unwrap() = 10;
+
+        list.insert(0, 0);
+        list.insert(4, 4);
+        list.insert(2, 5);
+        assert_eq!(list, from_vec(vec![0, 10, 5, 2, 3, 4]));
+
+        assert_eq!(list.remove(2), Some(5));
+        assert_eq!(list.remove(0), Some(0));
+        assert_eq!(list.remove(3), Some(4));
+        assert_eq!(list.remove(3), None);
+        assert_eq!(list, from_vec(vec![10, 2, 3]));
+
+        let mut tail = list.split_off(1);
+        assert_eq!(list, from_vec(vec![10]));
+        assert_eq!(tail, from_vec(vec![2, 3]));
+        assert_eq!(tail.split_off(2), List::new());
+
+        list.append(&mut tail);
+        assert_eq!(list, from_vec(vec![10, 2, 3]));
+        assert_eq!(tail, List::new());
+        let mut empty = List::new();
+        empty.append(&mut list);
+        assert_eq!(empty, from_vec(vec![10, 2, 3]));
+
+        empty.truncate(5);
+        assert_eq!(empty, from_vec(vec![10, 2, 3]));
+        empty.truncate(1);
+        assert_eq!(empty, from_vec(vec![10]));
+        empty.truncate(0);
+        assert_eq!(empty, List::new());
+    }
+
+    #[test]
+    #[should_panic(expected = "insertion index is out of bounds")]
+    fn insert_past_the_end() {
+        from_vec(vec![1, 2]).insert(3, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "split index is out of bounds")]
+    fn split_off_past_the_end() {
+        from_vec(vec![1, 2]).split_off(3);
+    }
+
+    // Reversing moves nodes, not elements.
+    #[test]
+    fn reverse() {
+        let mut list = from_vec(vec![1, 2, 3, 4]);
+        let first = list.peek().unwrap() as *const i32;
+        list.reverse();
+        assert_eq!(list, from_vec(vec![4, 3, 2, 1]));
+        assert_eq!(list.get(3).unwrap() as *const i32, first);
+
+        let mut empty = List::<i32>::new();
+        empty.reverse();
+        assert_eq!(empty, List::new());
+    }
+
+    #[test]
+    fn retain_and_dedup() {
+        let mut list = from_vec((0..10).collect());
+        list.retain(|x| x % 3 != 0);
+        assert_eq!(list, from_vec(vec![1, 2, 4, 5, 7, 8]));
+        list.retain_mut(|x| {
+            *x *= 10;
+            *x > 30
+        });
+        assert_eq!(list, from_vec(vec![40, 50, 70, 80]));
+
+        let mut list = from_vec(vec![1, 1, 2, 3, 3, 3, 1, 4, 4]);
+        list.dedup_by(|a, b| a == b);
+        assert_eq!(list, from_vec(vec![1, 2, 3, 1, 4]));
+
+        // `same_bucket` compares against the element being kept, not the one just before
+        let mut list = from_vec(vec![10, 11, 12, 13, 20, 21, 30]);
+        list.dedup_by(|a, b| *a - *b < 3);
+        assert_eq!(list, from_vec(vec![10, 13, 20, 30]));
+    }
+
+    #[test]
+    fn extract_if() {
+        let mut list = from_vec((1..=10).collect());
+        let evens: Vec<i32> = list.extract_if(|x| *x % 2 == 0).collect();
+        assert_eq!(evens, vec![2, 4, 6, 8, 10]);
+        assert_eq!(list, from_vec(vec![1, 3, 5, 7, 9]));
+
+        // stopping early leaves the rest where it was
+        let first = list
+            .extract_if(|x| {
+                *x += 1;
+                *x > 2
+            })
+            .next();
+        assert_eq!(first, Some(4));
+        assert_eq!(list, from_vec(vec![2, 5, 7, 9]));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // too slow under Miri
+    fn editing_is_stack_safe() {
+        let mut list: List<i32> = (0..1000000).collect();
+        list.reverse();
+        assert_eq!(list.peek(), Some(&999999));
+        let mut tail = list.split_off(500000);
+        assert_eq!(tail.peek(), Some(&499999));
+        list.retain(|x| x % 2 == 0);
+        list.append(&mut tail);
+        assert_eq!(list.get(250000), Some(&499999));
+        list.truncate(1);
+        assert_eq!(list, from_vec(vec![999998]));
+    }
+
+    #[test]
+    fn cursor() {
+        let list = from_vec(vec![1, 2, 3]);
+        let mut cursor = list.cursor();
+        assert_eq!(cursor.index(), None);
+        assert_eq!(cursor.current(), None);
+        assert_eq!(cursor.peek_next(), Some(&1));
+
+        assert!(cursor.move_next());
+        assert_eq!(cursor.index(), Some(0));
+        assert_eq!(cursor.current(), Some(&1));
+        let saved = cursor.clone();
+        assert!(cursor.move_next());
+        assert!(cursor.move_next());
+        assert_eq!(cursor.current(), Some(&3));
+        assert_eq!(cursor.peek_next(), None);
+
+        // no wrapping around at the end
+        assert!(!cursor.move_next());
+        assert_eq!(cursor.index(), Some(2));
+        assert_eq!(cursor.current(), Some(&3));
+        assert_eq!(saved.current(), Some(&1));
+
+        let empty = List::<i32>::new();
+        let mut cursor = empty.cursor();
+        assert!(!cursor.move_next());
+        assert_eq!(cursor.index(), None);
+        assert_eq!(cursor.peek_next(), None);
+    }
+
+    #[test]
+    fn cursor_mut() {
+        let mut list = from_vec(vec![1, 2, 3]);
+        let mut cursor = list.cursor_mut();
+        assert_eq!(cursor.current(), None);
+
+        // on the ghost, "after" is the front
+        cursor.insert_after(0);
+        assert_eq!(cursor.peek_next(), Some(&mut 0));
+        assert!(cursor.move_next());
+        assert!(cursor.move_next());
+        *cursor.current().unwrap() *= 10;
+        assert_eq!(cursor.as_cursor().current(), Some(&10));
+
+        cursor.insert_after(15);
+        assert_eq!(cursor.remove_after(), Some(15));
+        assert_eq!(cursor.remove_after(), Some(2));
+        assert_eq!(cursor.index(), Some(1));
+        assert_eq!(list, from_vec(vec![0, 10, 3]));
+
+        let mut cursor = list.cursor_mut();
+        cursor.move_next();
+        cursor.splice_after(from_vec(vec![4, 5]));
+        cursor.splice_after(List::new());
+        assert_eq!(cursor.peek_next(), Some(&mut 4));
+        while cursor.move_next() {}
+        assert_eq!(cursor.index(), Some(4));
+        cursor.splice_after(from_vec(vec![6]));
+        cursor.insert_after(7);
+        assert_eq!(list, from_vec(vec![0, 4, 5, 10, 3, 7, 6]));
+
+        let mut cursor = list.cursor_mut();
+        cursor.move_next();
+        cursor.move_next();
+        let tail = cursor.split_after();
+        assert_eq!(cursor.split_after(), List::new());
+        assert_eq!(cursor.remove_after(), None);
+        cursor.splice_after(from_vec(vec![8]));
+        assert_eq!(list, from_vec(vec![0, 4, 8]));
+        assert_eq!(tail, from_vec(vec![5, 10, 3, 7, 6]));
+
+        // on the ghost, the whole list
+        let mut cursor = list.cursor_mut();
+        assert_eq!(cursor.remove_after(), Some(0));
+        let all = cursor.split_after();
+        assert_eq!(all, from_vec(vec![4, 8]));
+        assert_eq!(list, List::new());
+    }
+
+    // Removing every other element in one pass over a long list: O(n) with a cursor, where
+    // `remove(index)` in a loop would be O(n^2).
+    #[test]
+    #[cfg_attr(miri, ignore)] // too slow under Miri
+    fn cursor_mut_one_pass() {
+        let mut list: List<i32> = (0..1000000).collect();
+        let mut cursor = list.cursor_mut();
+        while cursor.move_next() {
+            cursor.remove_after();
+        }
+        assert_eq!(cursor.index(), Some(499999));
+        assert!(list.iter().copied().eq((0..1000000).step_by(2)));
+    }
+
+    // Every operation that links or unlinks nodes keeps `len` in step with the actual nodes.
+    #[test]
+    fn len_is_kept_up_to_date() {
+        fn check(list: &List<i32>, len: usize) {
+            assert_eq!(list.len(), len);
+            assert_eq!(list.iter().count(), len);
+            assert_eq!(list.is_empty(), len == 0);
+            // and so is the tail
+            let last = list.iter().last().map(|elem| elem as *const i32);
+            assert_eq!(
+                list.tail
+                    .map(|node| unsafe { &(*node.as_ptr()).elem as *const i32 }),
+                last
+            );
+        }
+
+        let mut list = List::new();
+        check(&list, 0);
+        list.pop();
+        check(&list, 0);
+        list.push(1);
+        list.extend(vec![2, 3, 4]);
+        check(&list, 4);
+        list.insert(2, 5);
+        check(&list, 5);
+        list.remove(0);
+        list.remove(10);
+        check(&list, 4);
+
+        let mut tail = list.split_off(1);
+        check(&list, 1);
+        check(&tail, 3);
+        list.append(&mut tail);
+        check(&list, 4);
+        check(&tail, 0);
+        list.merge_sorted(vec![0, 9].into_iter().collect());
+        check(&list, 6);
+        list.truncate(10);
+        list.truncate(5);
+        check(&list, 5);
+
+        list.retain(|x| *x != 5);
+        check(&list, 4);
+        list.extend(vec![7, 7]);
+        list.dedup_by(|a, b| a == b);
+        check(&list, 5);
+        assert_eq!(list.extract_if(|x| *x > 3).count(), 2);
+        check(&list, 3);
+
+        let mut cursor = list.cursor_mut();
+        cursor.insert_after(8);
+        cursor.move_next();
+        cursor.remove_after();
+        cursor.splice_after(vec![1, 2, 3].into_iter().collect());
+        check(&list, 6);
+        let mut cursor = list.cursor_mut();
+        cursor.move_next();
+        cursor.move_next();
+        let rest = cursor.split_after();
+        check(&list, 2);
+        check(&rest, 4);
+        check(&list.clone(), 2);
+        check(&rest.into_iter().skip(1).collect(), 3);
+
+        // everything that can change which node is last
+        list.extend(vec![4, 6, 5]);
+        list.reverse();
+        check(&list, 5);
+        list.sort();
+        check(&list, 5);
+        list.remove(4);
+        check(&list, 4);
+        assert_eq!(list.extract_if(|x| *x == 5).count(), 1);
+        check(&list, 3);
+        let mut cursor = list.cursor_mut();
+        while cursor.peek_next().is_some() {
+            cursor.move_next();
+        }
+        cursor.splice_after(vec![7, 8].into_iter().collect());
+        cursor.splice_after(List::new());
+        check(&list, 5);
+        let mut cursor = list.cursor_mut();
+        cursor.move_next();
+        cursor.move_next();
+        cursor.move_next();
+        cursor.insert_after(9);
+        cursor.move_next();
+        cursor.remove_after();
+        cursor.remove_after();
+        check(&list, 4);
+        let mut cursor = list.cursor_mut();
+        cursor.splice_after(vec![0].into_iter().collect());
+        check(&list, 5);
+        list.append(&mut List::new());
+        check(&list, 5);
+        let mut empty = List::new();
+        empty.append(&mut list);
+        check(&empty, 5);
+        check(&list, 0);
+        list.push(0);
+        check(&list, 1);
+        list.append(&mut empty);
+        list.dedup_by(|a, b| a == b);
+        check(&list, 5);
+        list.truncate(0);
+        check(&list, 0);
+    }
+
+    #[test]
+    fn exact_size_iterators() {
+        let mut list: List<i32> = (0..5).collect();
+
+        let mut iter = list.iter();
+        assert_eq!(iter.len(), 5);
+        iter.next();
+        assert_eq!(iter.size_hint(), (4, Some(4)));
+        assert_eq!(iter.by_ref().count(), 4);
+        assert_eq!(iter.len(), 0);
+        assert_eq!(iter.next(), None);
+
+        let mut iter_mut = list.iter_mut();
+        iter_mut.next();
+        assert_eq!(iter_mut.len(), 4);
+
+        let mut into_iter = list.into_iter();
+        into_iter.next();
+        into_iter.next();
+        assert_eq!(into_iter.len(), 3);
+        assert_eq!(into_iter.collect::<Vec<_>>(), vec![2, 3, 4]);
+    }
+
+    // Like `test_drop` in first.rs: these all walk a million-element list and would overflow the
+    // stack if they recursed.
+    #[test]
+    #[cfg_attr(miri, ignore)] // too slow under Miri
+    fn traits_are_stack_safe() {
+        let list: List<i32> = (0..1000000).collect();
+        let copy = list.clone();
+        assert!(list == copy);
+        assert_eq!(list.cmp(&copy), Ordering::Equal);
+        assert_eq!(list.partial_cmp(&copy), Some(Ordering::Equal));
+
+        let hash = |l: &List<i32>| {
+            let mut hasher = DefaultHasher::new();
+            l.hash(&mut hasher);
+            hasher.finish()
+        };
+        assert_eq!(hash(&list), hash(&copy));
+
+        write!(io::sink(), "{:?}", list).unwrap();
+    }
 }
@@ -0,0 +1,1176 @@
+// A production-quality unsafe doubly-linked deque.
+//
+// Features:
+//     - O(1) push, pop and peek at both ends
+//     - double-ended Iter, IterMut and IntoIter
+//     - Cursor and CursorMut for O(1) editing in the middle of the list
+//     - covariant in T, Send/Sync when T is, and sound under drop check
+//
+// Inspired by:
+// https://rust-unofficial.github.io/too-many-lists/sixth.html
+// and the (unstable) cursor API of `std::collections::LinkedList`.
+//
+// All links are `NonNull` raw pointers. A node's `Box` is only created in `push_*`/`insert_*` (and
+// immediately turned into a raw pointer) and only rebuilt when the node is unlinked again, so no
+// `&mut` ever aliases another live pointer to the same node.
+//
+// `NonNull<T>` is covariant, which is what we want for a list that owns its `T`s. The
+// `PhantomData<T>` tells drop check that dropping a `List<T>` may drop `T`s.
+
+use std::cmp::Ordering;
+use std::fmt::{self, Debug};
+use std::hash::{Hash, Hasher};
+use std::iter::{FromIterator, FusedIterator};
+use std::marker::PhantomData;
+use std::mem;
+use std::ptr::NonNull;
+
+//////////////////////////////////////////////////////////////////////////////
+// Data structures
+//
+// Linked list layout:
+// [] = stack
+// () = heap
+//
+// [front] -> (A) <-> (B) <-> (C) <- [back]
+//
+// A cursor points either at a node or at the "ghost" non-element that sits between the back and
+// the front of the list:
+//
+//   ghost -> (A) <-> (B) <-> (C) -> ghost
+//
+
+pub struct List<T> {
+    front: Link<T>,
+    back: Link<T>,
+    len: usize,
+    _boo: PhantomData<T>,
+}
+
+type Link<T> = Option<NonNull<Node<T>>>;
+
+struct Node<T> {
+    prev: Link<T>,
+    next: Link<T>,
+    elem: T,
+}
+
+impl<T> Node<T> {
+    // Allocate a node and leak it as a raw pointer. The list becomes responsible for freeing it
+    // with `Box::from_raw`.
+    fn alloc(prev: Link<T>, next: Link<T>, elem: T) -> NonNull<Node<T>> {
+        let boxed = Box::new(Node { prev, next, elem });
+        // Box::into_raw never returns null
+        unsafe { NonNull::new_unchecked(Box::into_raw(boxed)) }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Implementation
+
+impl<T> List<T> {
+    pub fn new() -> Self {
+        List {
+            front: None,
+            back: None,
+            len: 0,
+            _boo: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        while self.pop_front().is_some() {}
+    }
+
+    pub fn push_front(&mut self, elem: T) {
+        let new = Node::alloc(None, self.front, elem);
+        unsafe {
+            match self.front {
+                Some(old) => (*old.as_ptr()).prev = Some(new),
+                None => self.back = Some(new),
+            }
+        }
+        self.front = Some(new);
+        self.len += 1;
+    }
+
+    pub fn push_back(&mut self, elem: T) {
+        let new = Node::alloc(self.back, None, elem);
+        unsafe {
+            match self.back {
+                Some(old) => (*old.as_ptr()).next = Some(new),
+                None => self.front = Some(new),
+            }
+        }
+        self.back = Some(new);
+        self.len += 1;
+    }
+
+    pub fn pop_front(&mut self) -> Option<T> {
+        self.front.map(|node| unsafe {
+            // take back ownership of the node so it gets freed
+            let boxed_node = Box::from_raw(node.as_ptr());
+            self.front = boxed_node.next;
+            match self.front {
+                Some(new) => (*new.as_ptr()).prev = None,
+                None => self.back = None,
+            }
+            self.len -= 1;
+            boxed_node.elem
+        })
+    }
+
+    pub fn pop_back(&mut self) -> Option<T> {
+        self.back.map(|node| unsafe {
+            let boxed_node = Box::from_raw(node.as_ptr());
+            self.back = boxed_node.prev;
+            match self.back {
+                Some(new) => (*new.as_ptr()).next = None,
+                None => self.front = None,
+            }
+            self.len -= 1;
+            boxed_node.elem
+        })
+    }
+
+    pub fn front(&self) -> Option<&T> {
+        unsafe { self.front.map(|node| &(*node.as_ptr()).elem) }
+    }
+
+    pub fn front_mut(&mut self) -> Option<&mut T> {
+        unsafe { self.front.map(|node| &mut (*node.as_ptr()).elem) }
+    }
+
+    pub fn back(&self) -> Option<&T> {
+        unsafe { self.back.map(|node| &(*node.as_ptr()).elem) }
+    }
+
+    pub fn back_mut(&mut self) -> Option<&mut T> {
+        unsafe { self.back.map(|node| &mut (*node.as_ptr()).elem) }
+    }
+
+    // Move all of `other`'s elements onto the back of this list in O(1), leaving `other` empty.
+    pub fn append(&mut self, other: &mut Self) {
+        match self.back {
+            Some(back) => {
+                if let Some(other_front) = other.front.take() {
+                    unsafe {
+                        (*back.as_ptr()).next = Some(other_front);
+                        (*other_front.as_ptr()).prev = Some(back);
+                    }
+                    self.back = other.back.take();
+                    self.len += mem::replace(&mut other.len, 0);
+                }
+            }
+            None => mem::swap(self, other),
+        }
+    }
+
+    // Move all of `other`'s elements onto the front of this list in O(1), leaving `other` empty.
+    fn prepend(&mut self, other: &mut Self) {
+        mem::swap(self, other);
+        self.append(other);
+    }
+}
+
+impl<T> Default for List<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for List<T> {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+// The list owns its elements outright, so it can cross threads exactly when `T` can. Raw pointers
+// opt us out of these automatically.
+unsafe impl<T: Send> Send for List<T> {}
+unsafe impl<T: Sync> Sync for List<T> {}
+
+//////////////////////////////////////////////////////////////////////////////
+// Standard traits
+
+impl<T: Clone> Clone for List<T> {
+    fn clone(&self) -> Self {
+        self.iter().cloned().collect()
+    }
+}
+
+impl<T> Extend<T> for List<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        for item in iter {
+            self.push_back(item);
+        }
+    }
+}
+
+impl<T> FromIterator<T> for List<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut list = Self::new();
+        list.extend(iter);
+        list
+    }
+}
+
+impl<T: Debug> Debug for List<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self).finish()
+    }
+}
+
+impl<T: PartialEq> PartialEq for List<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other)
+    }
+}
+
+impl<T: Eq> Eq for List<T> {}
+
+impl<T: PartialOrd> PartialOrd for List<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.iter().partial_cmp(other)
+    }
+}
+
+impl<T: Ord> Ord for List<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.iter().cmp(other)
+    }
+}
+
+impl<T: Hash> Hash for List<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.len().hash(state);
+        for item in self {
+            item.hash(state);
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Iteration
+
+//
+// Iter
+//
+pub struct Iter<'a, T> {
+    front: Link<T>,
+    back: Link<T>,
+    len: usize,
+    _boo: PhantomData<&'a T>,
+}
+
+impl<T> List<T> {
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            front: self.front,
+            back: self.back,
+            len: self.len,
+            _boo: PhantomData,
+        }
+    }
+}
+
+impl<'a, T> IntoIterator for &'a List<T> {
+    type IntoIter = Iter<'a, T>;
+    type Item = &'a T;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    // `len` tells us when the two ends have met, so we never need to compare pointers.
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+        self.front.map(|node| unsafe {
+            self.len -= 1;
+            self.front = (*node.as_ptr()).next;
+            &(*node.as_ptr()).elem
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, T> DoubleEndedIterator for Iter<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+        self.back.map(|node| unsafe {
+            self.len -= 1;
+            self.back = (*node.as_ptr()).prev;
+            &(*node.as_ptr()).elem
+        })
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Iter<'a, T> {}
+impl<'a, T> FusedIterator for Iter<'a, T> {}
+
+// Shared references to the elements cross threads, so these need `T: Sync`.
+unsafe impl<'a, T: Sync> Send for Iter<'a, T> {}
+unsafe impl<'a, T: Sync> Sync for Iter<'a, T> {}
+
+//
+// IterMut
+//
+pub struct IterMut<'a, T> {
+    front: Link<T>,
+    back: Link<T>,
+    len: usize,
+    _boo: PhantomData<&'a mut T>,
+}
+
+impl<T> List<T> {
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        IterMut {
+            front: self.front,
+            back: self.back,
+            len: self.len,
+            _boo: PhantomData,
+        }
+    }
+}
+
+impl<'a, T> IntoIterator for &'a mut List<T> {
+    type IntoIter = IterMut<'a, T>;
+    type Item = &'a mut T;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+impl<'a, T> Iterator for IterMut<'a, T> {
+    type Item = &'a mut T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+        self.front.map(|node| unsafe {
+            self.len -= 1;
+            self.front = (*node.as_ptr()).next;
+            &mut (*node.as_ptr()).elem
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, T> DoubleEndedIterator for IterMut<'a, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.len == 0 {
+            return None;
+        }
+        self.back.map(|node| unsafe {
+            self.len -= 1;
+            self.back = (*node.as_ptr()).prev;
+            &mut (*node.as_ptr()).elem
+        })
+    }
+}
+
+impl<'a, T> ExactSizeIterator for IterMut<'a, T> {}
+impl<'a, T> FusedIterator for IterMut<'a, T> {}
+
+unsafe impl<'a, T: Send> Send for IterMut<'a, T> {}
+unsafe impl<'a, T: Sync> Sync for IterMut<'a, T> {}
+
+//
+// IntoIter
+//
+pub struct IntoIter<T> {
+    list: List<T>,
+}
+
+impl<T> IntoIterator for List<T> {
+    type IntoIter = IntoIter<T>;
+    type Item = T;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter { list: self }
+    }
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.list.pop_front()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.list.len, Some(self.list.len))
+    }
+}
+
+impl<T> DoubleEndedIterator for IntoIter<T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.list.pop_back()
+    }
+}
+
+impl<T> ExactSizeIterator for IntoIter<T> {}
+impl<T> FusedIterator for IntoIter<T> {}
+
+//////////////////////////////////////////////////////////////////////////////
+// Cursors
+//
+// A cursor sits on an element, or on the ghost between the back and the front. Moving past either
+// end lands on the ghost, and moving off the ghost wraps around to the other end. `index` is the
+// position of the current element, or None on the ghost.
+
+//
+// Cursor
+//
+pub struct Cursor<'a, T> {
+    list: &'a List<T>,
+    cur: Link<T>,
+    index: Option<usize>,
+}
+
+impl<T> List<T> {
+    // a read-only cursor on the front element (the ghost if the list is empty)
+    pub fn cursor_front(&self) -> Cursor<'_, T> {
+        Cursor {
+            list: self,
+            cur: self.front,
+            index: self.front.map(|_| 0),
+        }
+    }
+
+    // a read-only cursor on the back element (the ghost if the list is empty)
+    pub fn cursor_back(&self) -> Cursor<'_, T> {
+        Cursor {
+            list: self,
+            cur: self.back,
+            index: self.back.map(|_| self.len - 1),
+        }
+    }
+}
+
+impl<'a, T> Cursor<'a, T> {
+    pub fn index(&self) -> Option<usize> {
+        self.index
+    }
+
+    pub fn move_next(&mut self) {
+        match self.cur {
+            Some(cur) => unsafe {
+                self.cur = (*cur.as_ptr()).next;
+                self.index = self.cur.and(self.index).map(|idx| idx + 1);
+            },
+            None => {
+                self.cur = self.list.front;
+                self.index = self.cur.map(|_| 0);
+            }
+        }
+    }
+
+    pub fn move_prev(&mut self) {
+        match self.cur {
+            Some(cur) => unsafe {
+                self.cur = (*cur.as_ptr()).prev;
+                self.index = self.cur.and(self.index).map(|idx| idx - 1);
+            },
+            None => {
+                self.cur = self.list.back;
+                self.index = self.cur.map(|_| self.list.len - 1);
+            }
+        }
+    }
+
+    pub fn current(&self) -> Option<&'a T> {
+        unsafe { self.cur.map(|node| &(*node.as_ptr()).elem) }
+    }
+
+    pub fn peek_next(&self) -> Option<&'a T> {
+        unsafe {
+            let next = match self.cur {
+                Some(cur) => (*cur.as_ptr()).next,
+                None => self.list.front,
+            };
+            next.map(|node| &(*node.as_ptr()).elem)
+        }
+    }
+
+    pub fn peek_prev(&self) -> Option<&'a T> {
+        unsafe {
+            let prev = match self.cur {
+                Some(cur) => (*cur.as_ptr()).prev,
+                None => self.list.back,
+            };
+            prev.map(|node| &(*node.as_ptr()).elem)
+        }
+    }
+}
+
+impl<'a, T> Clone for Cursor<'a, T> {
+    fn clone(&self) -> Self {
+        Cursor { ..*self }
+    }
+}
+
+unsafe impl<'a, T: Sync> Send for Cursor<'a, T> {}
+unsafe impl<'a, T: Sync> Sync for Cursor<'a, T> {}
+
+//
+// CursorMut
+//
+pub struct CursorMut<'a, T> {
+    list: &'a mut List<T>,
+    cur: Link<T>,
+    index: Option<usize>,
+}
+
+impl<T> List<T> {
+    // an editing cursor on the front element (the ghost if the list is empty)
+    pub fn cursor_front_mut(&mut self) -> CursorMut<'_, T> {
+        CursorMut {
+            cur: self.front,
+            index: self.front.map(|_| 0),
+            list: self,
+        }
+    }
+
+    // an editing cursor on the back element (the ghost if the list is empty)
+    pub fn cursor_back_mut(&mut self) -> CursorMut<'_, T> {
+        CursorMut {
+            cur: self.back,
+            index: self.back.map(|_| self.len - 1),
+            list: self,
+        }
+    }
+}
+
+impl<'a, T> CursorMut<'a, T> {
+    pub fn index(&self) -> Option<usize> {
+        self.index
+    }
+
+    pub fn move_next(&mut self) {
+        match self.cur {
+            Some(cur) => unsafe {
+                self.cur = (*cur.as_ptr()).next;
+                self.index = self.cur.and(self.index).map(|idx| idx + 1);
+            },
+            None => {
+                self.cur = self.list.front;
+                self.index = self.cur.map(|_| 0);
+            }
+        }
+    }
+
+    pub fn move_prev(&mut self) {
+        match self.cur {
+            Some(cur) => unsafe {
+                self.cur = (*cur.as_ptr()).prev;
+                self.index = self.cur.and(self.index).map(|idx| idx - 1);
+            },
+            None => {
+                self.cur = self.list.back;
+                self.index = self.cur.map(|_| self.list.len - 1);
+            }
+        }
+    }
+
+    pub fn current(&mut self) -> Option<&mut T> {
+        unsafe { self.cur.map(|node| &mut (*node.as_ptr()).elem) }
+    }
+
+    pub fn peek_next(&mut self) -> Option<&mut T> {
+        unsafe {
+            let next = match self.cur {
+                Some(cur) => (*cur.as_ptr()).next,
+                None => self.list.front,
+            };
+            next.map(|node| &mut (*node.as_ptr()).elem)
+        }
+    }
+
+    pub fn peek_prev(&mut self) -> Option<&mut T> {
+        unsafe {
+            let prev = match self.cur {
+                Some(cur) => (*cur.as_ptr()).prev,
+                None => self.list.back,
+            };
+            prev.map(|node| &mut (*node.as_ptr()).elem)
+        }
+    }
+
+    // a read-only view of this cursor, borrowing it for the view's lifetime
+    pub fn as_cursor(&self) -> Cursor<'_, T> {
+        Cursor {
+            list: self.list,
+            cur: self.cur,
+            index: self.index,
+        }
+    }
+
+    // Insert `elem` after the current element. On the ghost, `elem` becomes the new front.
+    pub fn insert_after(&mut self, elem: T) {
+        match self.cur {
+            Some(cur) => unsafe {
+                let next = (*cur.as_ptr()).next;
+                let new = Node::alloc(Some(cur), next, elem);
+                (*cur.as_ptr()).next = Some(new);
+                match next {
+                    Some(next) => (*next.as_ptr()).prev = Some(new),
+                    None => self.list.back = Some(new),
+                }
+                self.list.len += 1;
+            },
+            None => self.list.push_front(elem),
+        }
+    }
+
+    // Insert `elem` before the current element. On the ghost, `elem` becomes the new back.
+    pub fn insert_before(&mut self, elem: T) {
+        match self.cur {
+            Some(cur) => unsafe {
+                let prev = (*cur.as_ptr()).prev;
+                let new = Node::alloc(prev, Some(cur), elem);
+                (*cur.as_ptr()).prev = Some(new);
+                match prev {
+                    Some(prev) => (*prev.as_ptr()).next = Some(new),
+                    None => self.list.front = Some(new),
+                }
+                self.list.len += 1;
+                // the current element moved one place further from the front
+                self.index = self.index.map(|idx| idx + 1);
+            },
+            None => self.list.push_back(elem),
+        }
+    }
+
+    // Remove and return the current element, moving the cursor on to the next one (or the ghost).
+    // Does nothing on the ghost.
+    pub fn remove_current(&mut self) -> Option<T> {
+        let cur = self.cur?;
+        unsafe {
+            let boxed_node = Box::from_raw(cur.as_ptr());
+            let Node { prev, next, elem } = *boxed_node;
+            match prev {
+                Some(prev) => (*prev.as_ptr()).next = next,
+                None => self.list.front = next,
+            }
+            match next {
+                Some(next) => (*next.as_ptr()).prev = prev,
+                None => self.list.back = prev,
+            }
+            self.list.len -= 1;
+            self.cur = next;
+            // the next element slides into the current index
+            self.index = self.cur.and(self.index);
+            Some(elem)
+        }
+    }
+
+    // Split the list in two before the current element. Everything before the cursor is returned
+    // and the original list keeps the current element and everything after it. On the ghost the
+    // entire list is returned.
+    pub fn split_before(&mut self) -> List<T> {
+        match (self.cur, self.index) {
+            (Some(cur), Some(index)) => unsafe {
+                let old_len = self.list.len;
+                let prev = (*cur.as_ptr()).prev;
+
+                // the returned list is [front, prev], which is empty when we're at the front
+                let output = List {
+                    front: prev.and(self.list.front),
+                    back: prev,
+                    len: index,
+                    _boo: PhantomData,
+                };
+                if let Some(prev) = prev {
+                    (*cur.as_ptr()).prev = None;
+                    (*prev.as_ptr()).next = None;
+                }
+
+                self.list.front = Some(cur);
+                self.list.len = old_len - index;
+                self.index = Some(0);
+                output
+            },
+            _ => mem::take(self.list),
+        }
+    }
+
+    // Split the list in two after the current element. Everything after the cursor is returned
+    // and the original list keeps everything up to and including the current element. On the
+    // ghost the entire list is returned.
+    pub fn split_after(&mut self) -> List<T> {
+        match (self.cur, self.index) {
+            (Some(cur), Some(index)) => unsafe {
+                let old_len = self.list.len;
+                let next = (*cur.as_ptr()).next;
+
+                let output = List {
+                    front: next,
+                    back: next.and(self.list.back),
+                    len: old_len - index - 1,
+                    _boo: PhantomData,
+                };
+                if let Some(next) = next {
+                    (*cur.as_ptr()).next = None;
+                    (*next.as_ptr()).prev = None;
+                }
+
+                self.list.back = Some(cur);
+                self.list.len = index + 1;
+                output
+            },
+            _ => mem::take(self.list),
+        }
+    }
+
+    // Insert the whole of `input` between the current element and the one before it. On the
+    // ghost `input` is appended to the back of the list.
+    pub fn splice_before(&mut self, mut input: List<T>) {
+        let (in_front, in_back) = match (input.front, input.back) {
+            (Some(front), Some(back)) => (front, back),
+            _ => return,
+        };
+        match self.cur {
+            Some(cur) => unsafe {
+                match (*cur.as_ptr()).prev {
+                    Some(prev) => {
+                        (*prev.as_ptr()).next = Some(in_front);
+                        (*in_front.as_ptr()).prev = Some(prev);
+                    }
+                    None => self.list.front = Some(in_front),
+                }
+                (*cur.as_ptr()).prev = Some(in_back);
+                (*in_back.as_ptr()).next = Some(cur);
+
+                self.index = self.index.map(|idx| idx + input.len);
+                self.list.len += input.len;
+                // `input` no longer owns any nodes
+                input.front = None;
+                input.back = None;
+                input.len = 0;
+            },
+            None => self.list.append(&mut input),
+        }
+    }
+
+    // Insert the whole of `input` between the current element and the one after it. On the
+    // ghost `input` is prepended to the front of the list.
+    pub fn splice_after(&mut self, mut input: List<T>) {
+        let (in_front, in_back) = match (input.front, input.back) {
+            (Some(front), Some(back)) => (front, back),
+            _ => return,
+        };
+        match self.cur {
+            Some(cur) => unsafe {
+                match (*cur.as_ptr()).next {
+                    Some(next) => {
+                        (*next.as_ptr()).prev = Some(in_back);
+                        (*in_back.as_ptr()).next = Some(next);
+                    }
+                    None => self.list.back = Some(in_back),
+                }
+                (*cur.as_ptr()).next = Some(in_front);
+                (*in_front.as_ptr()).prev = Some(cur);
+
+                self.list.len += input.len;
+                input.front = None;
+                input.back = None;
+                input.len = 0;
+            },
+            None => self.list.prepend(&mut input),
+        }
+    }
+}
+
+unsafe impl<'a, T: Send> Send for CursorMut<'a, T> {}
+unsafe impl<'a, T: Sync> Sync for CursorMut<'a, T> {}
+
+//////////////////////////////////////////////////////////////////////////////
+// Tests
+
+#[cfg(test)]
+mod test {
+    use super::{IntoIter, Iter, List};
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+    use std::rc::Rc;
+
+    fn list_from<T: Clone>(v: &[T]) -> List<T> {
+        v.iter().cloned().collect()
+    }
+
+    fn hash_of<T: Hash>(x: &T) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        x.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn check_links<T: PartialEq + std::fmt::Debug>(list: &List<T>) {
+        // walking forwards and backwards must agree with each other and with `len`
+        let forward: Vec<&T> = list.iter().collect();
+        let mut backward: Vec<&T> = list.iter().rev().collect();
+        backward.reverse();
+        assert_eq!(forward, backward);
+        assert_eq!(forward.len(), list.len());
+    }
+
+    #[test]
+    fn basic_front() {
+        let mut list = List::new();
+
+        // Try to break an empty list
+        assert_eq!(list.len(), 0);
+        assert_eq!(list.pop_front(), None);
+        assert_eq!(list.len(), 0);
+
+        // Try to break a one item list
+        list.push_front(10);
+        assert_eq!(list.len(), 1);
+        assert_eq!(list.pop_front(), Some(10));
+        assert_eq!(list.len(), 0);
+        assert_eq!(list.pop_front(), None);
+
+        // Mess around
+        list.push_front(10);
+        list.push_front(20);
+        list.push_front(30);
+        assert_eq!(list.len(), 3);
+        assert_eq!(list.pop_front(), Some(30));
+        list.push_front(40);
+        assert_eq!(list.pop_front(), Some(40));
+        assert_eq!(list.pop_front(), Some(20));
+        assert_eq!(list.pop_front(), Some(10));
+        assert_eq!(list.pop_front(), None);
+        assert!(list.is_empty());
+    }
+
+    #[test]
+    fn basic_back() {
+        let mut list = List::new();
+        assert_eq!(list.pop_back(), None);
+
+        list.push_back(10);
+        assert_eq!(list.pop_back(), Some(10));
+        assert_eq!(list.pop_back(), None);
+
+        list.push_back(10);
+        list.push_back(20);
+        list.push_front(0);
+        assert_eq!(list.len(), 3);
+        assert_eq!(list.front(), Some(&0));
+        assert_eq!(list.back(), Some(&20));
+        *list.front_mut().unwrap() = 1;
+        *list.back_mut().unwrap() = 21;
+        assert_eq!(list.pop_back(), Some(21));
+        assert_eq!(list.pop_back(), Some(10));
+        assert_eq!(list.pop_back(), Some(1));
+        assert_eq!(list.pop_back(), None);
+        assert_eq!(list.front(), None);
+        assert_eq!(list.back(), None);
+    }
+
+    #[test]
+    fn iter() {
+        let list = list_from(&[1, 2, 3, 4, 5]);
+        let mut iter = list.iter();
+        assert_eq!(iter.len(), 5);
+        assert_eq!(iter.next(), Some(&1));
+        assert_eq!(iter.next_back(), Some(&5));
+        assert_eq!(iter.next(), Some(&2));
+        assert_eq!(iter.next_back(), Some(&4));
+        assert_eq!(iter.len(), 1);
+        assert_eq!(iter.next(), Some(&3));
+        assert_eq!(iter.next_back(), None);
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn iter_mut() {
+        let mut list = list_from(&[1, 2, 3]);
+        for elem in &mut list {
+            *elem *= 10;
+        }
+        let mut iter = list.iter_mut();
+        assert_eq!(iter.next_back(), Some(&mut 30));
+        assert_eq!(iter.next(), Some(&mut 10));
+        assert_eq!(iter.next(), Some(&mut 20));
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next_back(), None);
+    }
+
+    #[test]
+    fn into_iter() {
+        let list = list_from(&[1, 2, 3, 4]);
+        let mut iter = list.into_iter();
+        assert_eq!(iter.size_hint(), (4, Some(4)));
+        assert_eq!(iter.next_back(), Some(4));
+        assert_eq!(iter.next(), Some(1));
+        assert_eq!(iter.next_back(), Some(3));
+        assert_eq!(iter.next(), Some(2));
+        assert_eq!(iter.next(), None);
+
+        // dropping a partially consumed iterator frees the rest
+        let tracker = Rc::new(());
+        let mut list = List::new();
+        for _ in 0..3 {
+            list.push_back(Rc::clone(&tracker));
+        }
+        let mut iter = list.into_iter();
+        assert!(iter.next().is_some());
+        assert_eq!(Rc::strong_count(&tracker), 3);
+        drop(iter);
+        assert_eq!(Rc::strong_count(&tracker), 1);
+    }
+
+    #[test]
+    fn traits() {
+        let list = list_from(&[1, 2, 3]);
+        let same = list.clone();
+        let longer = list_from(&[1, 2, 3, 4]);
+        let bigger = list_from(&[1, 3]);
+
+        assert_eq!(list, same);
+        assert_ne!(list, longer);
+        assert!(list < longer);
+        assert!(list < bigger);
+        assert_eq!(hash_of(&list), hash_of(&same));
+        assert_eq!(format!("{:?}", list), "[1, 2, 3]");
+        assert_eq!(List::<i32>::default(), List::new());
+
+        let mut extended = list.clone();
+        extended.extend(vec![4]);
+        assert_eq!(extended, longer);
+    }
+
+    #[test]
+    fn append() {
+        let mut a = list_from(&[1, 2]);
+        let mut b = list_from(&[3, 4]);
+        a.append(&mut b);
+        assert!(b.is_empty());
+        assert_eq!(a, list_from(&[1, 2, 3, 4]));
+        check_links(&a);
+
+        let mut empty = List::new();
+        empty.append(&mut a);
+        assert!(a.is_empty());
+        assert_eq!(empty, list_from(&[1, 2, 3, 4]));
+        empty.append(&mut a);
+        assert_eq!(empty.len(), 4);
+    }
+
+    #[test]
+    fn cursor_move_peek() {
+        let list = list_from(&[1, 2, 3, 4, 5, 6]);
+        let mut cursor = list.cursor_front();
+        assert_eq!(cursor.current(), Some(&1));
+        assert_eq!(cursor.peek_next(), Some(&2));
+        assert_eq!(cursor.peek_prev(), None);
+        assert_eq!(cursor.index(), Some(0));
+        cursor.move_prev();
+        assert_eq!(cursor.current(), None);
+        assert_eq!(cursor.peek_next(), Some(&1));
+        assert_eq!(cursor.peek_prev(), Some(&6));
+        assert_eq!(cursor.index(), None);
+        cursor.move_next();
+        cursor.move_next();
+        assert_eq!(cursor.current(), Some(&2));
+        assert_eq!(cursor.index(), Some(1));
+
+        let mut cursor = list.cursor_back();
+        assert_eq!(cursor.current(), Some(&6));
+        assert_eq!(cursor.index(), Some(5));
+        cursor.move_next();
+        assert_eq!(cursor.current(), None);
+        assert_eq!(cursor.index(), None);
+        cursor.move_prev();
+        cursor.move_prev();
+        assert_eq!(cursor.current(), Some(&5));
+        assert_eq!(cursor.index(), Some(4));
+
+        let mut list = List::new();
+        list.push_back(1);
+        let mut cursor = list.cursor_front_mut();
+        *cursor.current().unwrap() = 10;
+        cursor.move_next();
+        assert_eq!(cursor.current(), None);
+        *cursor.peek_next().unwrap() = 11;
+        assert_eq!(cursor.as_cursor().peek_prev(), Some(&11));
+        assert_eq!(list.front(), Some(&11));
+
+        let empty: List<i32> = List::new();
+        let mut cursor = empty.cursor_front();
+        assert_eq!(cursor.current(), None);
+        cursor.move_next();
+        cursor.move_prev();
+        assert_eq!(cursor.current(), None);
+        assert_eq!(cursor.index(), None);
+    }
+
+    #[test]
+    fn cursor_insert_remove() {
+        let mut list = list_from(&[1, 2, 3]);
+        {
+            let mut cursor = list.cursor_front_mut();
+            cursor.move_next(); // at 2
+            cursor.insert_before(10);
+            assert_eq!(cursor.index(), Some(2));
+            cursor.insert_after(20);
+            assert_eq!(cursor.current(), Some(&mut 2));
+            cursor.move_next(); // at 20
+            assert_eq!(cursor.remove_current(), Some(20));
+            assert_eq!(cursor.current(), Some(&mut 3));
+            assert_eq!(cursor.index(), Some(3));
+            assert_eq!(cursor.remove_current(), Some(3));
+            // removing the back lands on the ghost
+            assert_eq!(cursor.current(), None);
+            assert_eq!(cursor.index(), None);
+            assert_eq!(cursor.remove_current(), None);
+            // inserting at the ghost wraps to the ends
+            cursor.insert_before(100);
+            cursor.insert_after(0);
+        }
+        assert_eq!(list, list_from(&[0, 1, 10, 2, 100]));
+        check_links(&list);
+
+        {
+            let mut cursor = list.cursor_front_mut();
+            while cursor.remove_current().is_some() {}
+        }
+        assert!(list.is_empty());
+        check_links(&list);
+    }
+
+    #[test]
+    fn cursor_split() {
+        let mut list = list_from(&[1, 2, 3, 4, 5, 6]);
+        let mut cursor = list.cursor_front_mut();
+        cursor.move_next();
+        cursor.move_next(); // at 3
+        let before = cursor.split_before();
+        assert_eq!(cursor.index(), Some(0));
+        assert_eq!(cursor.current(), Some(&mut 3));
+        let after = cursor.split_after();
+        assert_eq!(cursor.index(), Some(0));
+        assert_eq!(before, list_from(&[1, 2]));
+        assert_eq!(after, list_from(&[4, 5, 6]));
+        assert_eq!(list, list_from(&[3]));
+        check_links(&before);
+        check_links(&after);
+        check_links(&list);
+
+        // splitting at the ends returns empty lists
+        let mut list = list_from(&[1, 2]);
+        let mut cursor = list.cursor_front_mut();
+        assert!(cursor.split_before().is_empty());
+        let mut cursor = list.cursor_back_mut();
+        assert!(cursor.split_after().is_empty());
+        assert_eq!(list, list_from(&[1, 2]));
+
+        // splitting at the ghost takes everything
+        let mut cursor = list.cursor_back_mut();
+        cursor.move_next();
+        let all = cursor.split_after();
+        assert_eq!(all, list_from(&[1, 2]));
+        assert!(list.is_empty());
+    }
+
+    #[test]
+    fn cursor_splice() {
+        let mut list = list_from(&[1, 2, 3]);
+        {
+            let mut cursor = list.cursor_front_mut();
+            cursor.move_next(); // at 2
+            cursor.splice_before(list_from(&[10, 11]));
+            assert_eq!(cursor.index(), Some(3));
+            assert_eq!(cursor.current(), Some(&mut 2));
+            cursor.splice_after(list_from(&[20, 21]));
+            assert_eq!(cursor.current(), Some(&mut 2));
+            cursor.splice_after(List::new());
+            cursor.splice_before(List::new());
+            assert_eq!(cursor.index(), Some(3));
+
+            // splicing at the ghost goes on the ends
+            cursor.move_next();
+            cursor.move_next();
+            cursor.move_next();
+            cursor.move_next();
+            assert_eq!(cursor.current(), None);
+            cursor.splice_before(list_from(&[100]));
+            cursor.splice_after(list_from(&[0]));
+        }
+        assert_eq!(list, list_from(&[0, 1, 10, 11, 2, 20, 21, 3, 100]));
+        check_links(&list);
+
+        // splicing into an empty list
+        let mut list = List::new();
+        list.cursor_front_mut().splice_after(list_from(&[1, 2]));
+        list.cursor_front_mut().splice_before(list_from(&[0]));
+        assert_eq!(list, list_from(&[0, 1, 2]));
+        check_links(&list);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // too slow under Miri
+    fn test_drop() {
+        let mut list = List::new();
+        for i in 0..100000 {
+            list.push_back(i);
+        }
+        // list is dropped
+    }
+
+    // These only need to compile.
+    #[allow(dead_code)]
+    fn assert_properties() {
+        fn is_send<T: Send>() {}
+        fn is_sync<T: Sync>() {}
+
+        is_send::<List<i32>>();
+        is_sync::<List<i32>>();
+        is_send::<IntoIter<i32>>();
+        is_sync::<IntoIter<i32>>();
+        is_send::<Iter<i32>>();
+        is_sync::<Iter<i32>>();
+        is_send::<super::IterMut<i32>>();
+        is_sync::<super::IterMut<i32>>();
+        is_send::<super::Cursor<i32>>();
+        is_sync::<super::Cursor<i32>>();
+        is_send::<super::CursorMut<i32>>();
+        is_sync::<super::CursorMut<i32>>();
+
+        fn list_covariant<'a, T>(x: List<&'static T>) -> List<&'a T> {
+            x
+        }
+        fn iter_covariant<'i, 'a, T>(x: Iter<'i, &'static T>) -> Iter<'i, &'a T> {
+            x
+        }
+        fn into_iter_covariant<'a, T>(x: IntoIter<&'static T>) -> IntoIter<&'a T> {
+            x
+        }
+    }
+}
@@ -0,0 +1,437 @@
+// A lazy, memoized, persistent list (a stream).
+//
+// Improvements over `third::List`:
+//     - lazy: each node is only computed the first time somebody looks at it, so streams can be
+//       infinite (`iterate`, `repeat`, `unfold`), and `map`, `filter`, `zip` and `take` do no
+//       work up front
+//     - memoized: a node is computed at most once, and every clone of the stream shares the
+//       result, so several consumers of one stream share the prefix they've computed
+//
+// Inspired by:
+// Haskell's lists, and Chris Okasaki, "Purely Functional Data Structures", 1998, section 4.2.
+//
+// A stream is an `Rc` to a cell holding either a thunk (a closure that computes the node), or the
+// node it computed: nothing for the end of the stream, or an element and the rest of the stream.
+//
+// [stream] -> (head 0, tail) -> (head 1, tail) -> (thunk) ... not computed yet
+//
+// Since the thunks are boxed closures that get stored away, the elements and closures given to
+// the functions that build streams have to be `'static`.
+
+use std::cell::{Cell, OnceCell};
+use std::rc::Rc;
+
+//////////////////////////////////////////////////////////////////////////////
+// Data structures
+
+pub struct Stream<T> {
+    cell: Rc<Lazy<T>>,
+}
+
+struct Lazy<T> {
+    // None for the end of the stream
+    value: OnceCell<Option<Node<T>>>,
+    // taken when the node is computed
+    thunk: Cell<Option<Thunk<T>>>,
+}
+
+type Thunk<T> = Box<dyn FnOnce() -> Option<Node<T>>>;
+
+struct Node<T> {
+    head: T,
+    tail: Stream<T>,
+}
+
+pub struct Iter<'a, T> {
+    next: &'a Stream<T>,
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Implementation
+
+impl<T> Stream<T> {
+    fn forced(value: Option<Node<T>>) -> Self {
+        Stream {
+            cell: Rc::new(Lazy {
+                value: OnceCell::from(value),
+                thunk: Cell::new(None),
+            }),
+        }
+    }
+
+    fn suspended(thunk: Thunk<T>) -> Self {
+        Stream {
+            cell: Rc::new(Lazy {
+                value: OnceCell::new(),
+                thunk: Cell::new(Some(thunk)),
+            }),
+        }
+    }
+
+    // Compute the first node, if that hasn't been done yet.
+    fn force(&self) -> Option<&Node<T>> {
+        self.cell
+            .value
+            .get_or_init(|| {
+                let thunk = self
+                    .cell
+                    .thunk
+                    .take()
+                    .expect("stream forced by its own thunk, or after its thunk panicked");
+                thunk()
+            })
+            .as_ref()
+    }
+
+    pub fn empty() -> Self {
+        Stream::forced(None)
+    }
+
+    pub fn cons(head: T, tail: Stream<T>) -> Self {
+        Stream::forced(Some(Node { head, tail }))
+    }
+
+    // the first element, computing it if needed
+    pub fn head(&self) -> Option<&T> {
+        self.force().map(|node| &node.head)
+    }
+
+    // The rest of the stream. Computes the first node if needed, but nothing after it.
+    pub fn tail(&self) -> Option<Stream<T>> {
+        self.force().map(|node| node.tail.clone())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.force().is_none()
+    }
+
+    // Whether the first node has been computed yet. Never computes anything.
+    pub fn is_evaluated(&self) -> bool {
+        self.cell.value.get().is_some()
+    }
+
+    // Iterate over the elements, computing them as needed. Never ends if the stream is infinite.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter { next: self }
+    }
+}
+
+impl<T: 'static> Stream<T> {
+    // The stream of `f(state)`, `f(state')`, ... for as long as `f` returns `Some((elem, state'))`.
+    pub fn unfold<S, F>(state: S, f: F) -> Self
+    where
+        S: 'static,
+        F: Fn(S) -> Option<(T, S)> + 'static,
+    {
+        unfold_rc(state, Rc::new(f))
+    }
+
+    // `f` applied to every element
+    pub fn map<U, F>(&self, f: F) -> Stream<U>
+    where
+        U: 'static,
+        F: Fn(&T) -> U + 'static,
+    {
+        map_rc(self.clone(), Rc::new(f))
+    }
+}
+
+impl<T: Clone + 'static> Stream<T> {
+    // The infinite stream `seed`, `f(seed)`, `f(f(seed))`, ...
+    pub fn iterate<F>(seed: T, f: F) -> Self
+    where
+        F: Fn(&T) -> T + 'static,
+    {
+        Stream::forced(Some(iterate_node(seed, Rc::new(f))))
+    }
+
+    // The infinite stream `elem`, `elem`, `elem`, ...
+    pub fn repeat(elem: T) -> Self {
+        Stream::iterate(elem, T::clone)
+    }
+
+    // the elements that satisfy `pred`
+    pub fn filter<P>(&self, pred: P) -> Stream<T>
+    where
+        P: Fn(&T) -> bool + 'static,
+    {
+        filter_rc(self.clone(), Rc::new(pred))
+    }
+
+    // the first `n` elements
+    pub fn take(&self, n: usize) -> Stream<T> {
+        take_n(self.clone(), n)
+    }
+
+    // pairs of elements from both streams, until either one ends
+    pub fn zip<U: Clone + 'static>(&self, other: &Stream<U>) -> Stream<(T, U)> {
+        zip_both(self.clone(), other.clone())
+    }
+}
+
+// The functions below build the thunks. Each thunk computes one node, and puts a new thunk in its
+// tail, so forcing an element never recurses further than the layers of `map`/`filter`/etc.
+// stacked on top of each other.
+
+fn unfold_rc<T, S, F>(state: S, f: Rc<F>) -> Stream<T>
+where
+    T: 'static,
+    S: 'static,
+    F: Fn(S) -> Option<(T, S)> + 'static,
+{
+    Stream::suspended(Box::new(move || {
+        f(state).map(|(head, state)| Node {
+            head,
+            tail: unfold_rc(state, f),
+        })
+    }))
+}
+
+fn iterate_node<T, F>(elem: T, f: Rc<F>) -> Node<T>
+where
+    T: Clone + 'static,
+    F: Fn(&T) -> T + 'static,
+{
+    let prev = elem.clone();
+    Node {
+        head: elem,
+        tail: Stream::suspended(Box::new(move || Some(iterate_node(f(&prev), f)))),
+    }
+}
+
+fn map_rc<T, U, F>(stream: Stream<T>, f: Rc<F>) -> Stream<U>
+where
+    T: 'static,
+    U: 'static,
+    F: Fn(&T) -> U + 'static,
+{
+    Stream::suspended(Box::new(move || {
+        let node = stream.force()?;
+        Some(Node {
+            head: f(&node.head),
+            tail: map_rc(node.tail.clone(), f),
+        })
+    }))
+}
+
+fn filter_rc<T, P>(stream: Stream<T>, pred: Rc<P>) -> Stream<T>
+where
+    T: Clone + 'static,
+    P: Fn(&T) -> bool + 'static,
+{
+    Stream::suspended(Box::new(move || {
+        // skip ahead to the next element that passes, with a loop so long runs of elements that
+        // don't pass can't overflow the stack
+        let mut stream = stream;
+        loop {
+            let tail = {
+                let node = stream.force()?;
+                if pred(&node.head) {
+                    return Some(Node {
+                        head: node.head.clone(),
+                        tail: filter_rc(node.tail.clone(), pred),
+                    });
+                }
+                node.tail.clone()
+            };
+            stream = tail;
+        }
+    }))
+}
+
+fn take_n<T: Clone + 'static>(stream: Stream<T>, n: usize) -> Stream<T> {
+    if n == 0 {
+        return Stream::empty();
+    }
+    Stream::suspended(Box::new(move || {
+        let node = stream.force()?;
+        Some(Node {
+            head: node.head.clone(),
+            tail: take_n(node.tail.clone(), n - 1),
+        })
+    }))
+}
+
+fn zip_both<T, U>(left: Stream<T>, right: Stream<U>) -> Stream<(T, U)>
+where
+    T: Clone + 'static,
+    U: Clone + 'static,
+{
+    Stream::suspended(Box::new(move || {
+        let (l, r) = (left.force()?, right.force()?);
+        Some(Node {
+            head: (l.head.clone(), r.head.clone()),
+            tail: zip_both(l.tail.clone(), r.tail.clone()),
+        })
+    }))
+}
+
+impl<T> Default for Stream<T> {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+// Cloning a stream only copies the pointer to its first cell. The clones share everything,
+// including whatever gets computed later.
+impl<T> Clone for Stream<T> {
+    fn clone(&self) -> Self {
+        Stream {
+            cell: Rc::clone(&self.cell),
+        }
+    }
+}
+
+// A non-recursive Drop implementation, so dropping a long computed stream doesn't blow the stack.
+// While we hold the only pointer to a cell, we take its tail out of it before it's dropped.
+impl<T> Drop for Stream<T> {
+    fn drop(&mut self) {
+        let mut next = take_tail(&mut self.cell);
+        while let Some(mut stream) = next {
+            next = take_tail(&mut stream.cell);
+        }
+    }
+}
+
+fn take_tail<T>(cell: &mut Rc<Lazy<T>>) -> Option<Stream<T>> {
+    let node = Rc::get_mut(cell)?.value.take().flatten()?;
+    Some(node.tail)
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Iteration
+
+impl<'a, T> IntoIterator for &'a Stream<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.next.force()?;
+        self.next = &node.tail;
+        Some(&node.head)
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Tests
+
+#[cfg(test)]
+mod test {
+    use super::Stream;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    #[test]
+    fn basics() {
+        let empty: Stream<i32> = Stream::empty();
+        assert!(empty.is_empty());
+        assert_eq!(empty.head(), None);
+        assert!(empty.tail().is_none());
+
+        let stream = Stream::cons(1, Stream::cons(2, Stream::empty()));
+        assert_eq!(stream.head(), Some(&1));
+        assert_eq!(stream.tail().unwrap().head(), Some(&2));
+        assert_eq!(stream.iter().collect::<Vec<_>>(), vec![&1, &2]);
+    }
+
+    #[test]
+    fn infinite() {
+        let naturals = Stream::iterate(0u64, |x| x + 1);
+        assert_eq!(
+            naturals.iter().take(5).copied().collect::<Vec<_>>(),
+            vec![0, 1, 2, 3, 4]
+        );
+
+        let ones = Stream::repeat(1);
+        assert_eq!(ones.iter().take(3).collect::<Vec<_>>(), vec![&1, &1, &1]);
+
+        let fib = Stream::unfold((0u64, 1u64), |(a, b)| Some((a, (b, a + b))));
+        assert_eq!(
+            fib.iter().take(10).copied().collect::<Vec<_>>(),
+            vec![0, 1, 1, 2, 3, 5, 8, 13, 21, 34]
+        );
+
+        let countdown = Stream::unfold(3, |n| if n > 0 { Some((n, n - 1)) } else { None });
+        assert_eq!(countdown.iter().collect::<Vec<_>>(), vec![&3, &2, &1]);
+    }
+
+    #[test]
+    fn combinators() {
+        let naturals = Stream::iterate(0u64, |x| x + 1);
+        let squares = naturals.map(|x| x * x);
+        let odd_squares = squares.filter(|x| x % 2 == 1);
+        assert_eq!(
+            odd_squares.take(4).iter().copied().collect::<Vec<_>>(),
+            vec![1, 9, 25, 49]
+        );
+
+        let pairs = naturals.zip(&squares.tail().unwrap());
+        assert_eq!(
+            pairs.take(3).iter().copied().collect::<Vec<_>>(),
+            vec![(0, 1), (1, 4), (2, 9)]
+        );
+
+        // zip stops at the shorter stream
+        assert_eq!(naturals.take(2).zip(&naturals).iter().count(), 2);
+        assert!(naturals.take(0).is_empty());
+        assert_eq!(naturals.take(2).filter(|&x| x > 5).head(), None);
+    }
+
+    // Nothing is computed until it's looked at, and nothing is computed twice, even by different
+    // clones of the stream.
+    #[test]
+    fn lazy_and_memoized() {
+        let calls = Rc::new(Cell::new(0));
+        let counter = Rc::clone(&calls);
+        let stream = Stream::iterate(0, move |x| {
+            counter.set(counter.get() + 1);
+            x + 1
+        });
+        let mapped = stream.map(|x| x * 10);
+        assert_eq!(calls.get(), 0);
+        assert!(!mapped.is_evaluated());
+
+        let other = mapped.clone();
+        assert_eq!(mapped.iter().nth(5), Some(&50));
+        assert_eq!(calls.get(), 5);
+        assert!(other.is_evaluated());
+        assert_eq!(other.iter().nth(5), Some(&50));
+        assert_eq!(calls.get(), 5);
+        assert_eq!(other.iter().nth(6), Some(&60));
+        assert_eq!(calls.get(), 6);
+    }
+
+    // Forcing a long stream and dropping it again must not recurse, and neither must filtering
+    // out a long run of elements.
+    #[test]
+    fn test_drop() {
+        let naturals = Stream::iterate(0u64, |x| x + 1);
+        let evens = naturals.filter(|x| x % 2 == 0);
+        assert_eq!(evens.iter().nth(500000), Some(&1000000));
+        assert_eq!(naturals.filter(|&x| x >= 1000000).head(), Some(&1000000));
+        let mapped = naturals.map(|x| x + 1);
+        assert_eq!(mapped.iter().nth(1000000), Some(&1000001));
+        // all the streams are dropped here
+    }
+
+    // Every element is dropped exactly once, whether or not the stream was computed.
+    #[test]
+    fn drops_elements() {
+        let elem = Rc::new(());
+        let stream = Stream::repeat(Rc::clone(&elem)).take(100);
+        assert_eq!(stream.iter().take(50).count(), 50);
+        let _unevaluated = Stream::repeat(Rc::clone(&elem));
+        assert!(Rc::strong_count(&elem) > 50);
+        drop(stream);
+        drop(_unevaluated);
+        assert_eq!(Rc::strong_count(&elem), 1);
+    }
+}
@@ -27,26 +27,32 @@
 //
 // To do this in Rust, we do reference counting using `Rc`.
 
+use std::cmp::Ordering;
+use std::fmt::{self, Debug};
+use std::hash::{Hash, Hasher};
+use std::iter::FusedIterator;
 use std::rc::Rc;
 
 //////////////////////////////////////////////////////////////////////////////
 // Data Structures
 
-#[derive(Debug)]
 pub struct List<T> {
     head: Link<T>,
 }
 
 type Link<T> = Option<Rc<Node<T>>>;
 
-#[derive(Debug)]
 struct Node<T> {
     elem: T,
     next: Link<T>,
+    // The length of the list that starts at this node. Nodes never change, so every version of
+    // the list knows its length without walking it.
+    len: usize,
 }
 
 pub struct Iter<'a, T> {
     next: Option<&'a Node<T>>,
+    len: usize,
 }
 
 //////////////////////////////////////////////////////////////////////////////
@@ -60,12 +66,22 @@ impl<T> List<T> {
     pub fn append(&self, elem: T) -> List<T> {
         List {
             head: Some(Rc::new(Node {
-                elem: elem,
+                elem,
                 next: self.head.clone(),
+                len: self.len() + 1,
             })),
         }
     }
 
+    // the number of elements, in O(1)
+    pub fn len(&self) -> usize {
+        self.head.as_ref().map_or(0, |node| node.len)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.is_none()
+    }
+
     pub fn tail(&self) -> Option<List<T>> {
         self.head.as_ref().map(|rc_node| List {
             head: rc_node.next.clone(),
@@ -78,7 +94,23 @@ impl<T> List<T> {
 
     pub fn iter(&self) -> Iter<'_, T> {
         Iter {
-            next: self.head.as_ref().map(|node| &**node),
+            next: self.head.as_deref(),
+            len: self.len(),
+        }
+    }
+}
+
+impl<T> Default for List<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Cloning a list only copies the pointer to its head; the nodes themselves are shared.
+impl<T> Clone for List<T> {
+    fn clone(&self) -> Self {
+        List {
+            head: self.head.clone(),
         }
     }
 }
@@ -104,10 +136,457 @@ impl<'a, T> Iterator for Iter<'a, T> {
 
     fn next(&mut self) -> Option<Self::Item> {
         self.next.map(|node| {
-            self.next = node.next.as_ref().map(|next_node| &**next_node);
+            self.next = node.next.as_deref();
+            self.len -= 1;
             &node.elem
         })
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len, Some(self.len))
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Iter<'a, T> {}
+impl<'a, T> FusedIterator for Iter<'a, T> {}
+
+//////////////////////////////////////////////////////////////////////////////
+// Sharing
+//
+// Ways to see how much different versions of a list share with each other.
+
+impl<T> List<T> {
+    // Whether both lists are the same nodes (or both empty). If so they're equal, in O(1).
+    pub fn ptr_eq(&self, other: &List<T>) -> bool {
+        match (&self.head, &other.head) {
+            (Some(left), Some(right)) => Rc::ptr_eq(left, right),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+
+    // How many lists (including this one, and other lists' tails) point at the first node, or
+    // None if the list is empty.
+    pub fn strong_count_of_head(&self) -> Option<usize> {
+        self.head.as_ref().map(Rc::strong_count)
+    }
+
+    // Whether no node of this list is shared with any other list. Only walks the list as far as
+    // the first shared node.
+    pub fn is_uniquely_owned(&self) -> bool {
+        let mut link = self.head.as_ref();
+        while let Some(node) = link {
+            if Rc::strong_count(node) > 1 {
+                return false;
+            }
+            link = node.next.as_ref();
+        }
+        true
+    }
+
+    // The longest suffix that both lists share the nodes of, found in O(n) using pointer
+    // comparisons. The elements before it are the difference between the two versions.
+    pub fn common_suffix(&self, other: &List<T>) -> List<T> {
+        let (self_len, other_len) = (self.len(), other.len());
+        // A shared suffix is the same length in both lists, so line the lists up at the end.
+        let mut left = self.link_after(self_len.saturating_sub(other_len));
+        let mut right = other.link_after(other_len.saturating_sub(self_len));
+        loop {
+            match (left, right) {
+                (Some(l), Some(r)) if !Rc::ptr_eq(l, r) => {
+                    left = l.next.as_ref();
+                    right = r.next.as_ref();
+                }
+                (shared, _) => {
+                    return List {
+                        head: shared.cloned(),
+                    }
+                }
+            }
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Transients
+//
+// A `Transient` is a list that can be changed in place, for building or editing a list in bulk
+// without allocating a new `List` for every step:
+//
+// let mut t = list.transient();
+// t.push(1);
+// t.map_in_place(|x| *x += 1);
+// let list2 = t.freeze();
+//
+// It never changes a node that another list can see, so `list` above is left as it was. Nodes
+// that only the transient owns are changed in place, with `Rc::try_unwrap`/`Rc::make_mut`, and a
+// shared node is copied before it's changed. `pop` and `head_mut` only copy the head, so the rest
+// of the list stays shared.
+
+pub struct Transient<T> {
+    list: List<T>,
+}
+
+impl<T: Clone> Clone for Node<T> {
+    fn clone(&self) -> Self {
+        Node {
+            elem: self.elem.clone(),
+            next: self.next.clone(),
+            len: self.len,
+        }
+    }
+}
+
+impl<T> List<T> {
+    // Turn the list into a transient. Clone the list first to keep this version around: that's
+    // O(1), and the transient will then copy nodes before it changes them.
+    pub fn transient(self) -> Transient<T> {
+        Transient { list: self }
+    }
+}
+
+impl<T> Transient<T> {
+    pub fn new() -> Self {
+        Transient { list: List::new() }
+    }
+
+    // Turn the transient back into an immutable list.
+    pub fn freeze(self) -> List<T> {
+        self.list
+    }
+
+    pub fn push(&mut self, elem: T) {
+        let len = self.list.len() + 1;
+        let next = self.list.head.take();
+        self.list.head = Some(Rc::new(Node { elem, next, len }));
+    }
+
+    pub fn head(&self) -> Option<&T> {
+        self.list.head()
+    }
+
+    pub fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+}
+
+impl<T: Clone> Transient<T> {
+    // Pop the head. It's moved out if nobody else has the node, and cloned if they do.
+    pub fn pop(&mut self) -> Option<T> {
+        self.list
+            .head
+            .take()
+            .map(|node| match Rc::try_unwrap(node) {
+                Ok(node) => {
+                    self.list.head = node.next;
+                    node.elem
+                }
+                Err(node) => {
+                    self.list.head = node.next.clone();
+                    node.elem.clone()
+                }
+            })
+    }
+
+    // A mutable reference to the head, copying the head node first if it's shared.
+    pub fn head_mut(&mut self) -> Option<&mut T> {
+        self.list
+            .head
+            .as_mut()
+            .map(|node| &mut Rc::make_mut(node).elem)
+    }
+
+    // Apply `f` to every element, copying the nodes from the first shared one on.
+    pub fn map_in_place<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&mut T),
+    {
+        let mut link = &mut self.list.head;
+        while let Some(node) = link {
+            // Copying a shared node makes its `next` shared too, so once we copy one node we
+            // copy all the ones after it.
+            let node = Rc::make_mut(node);
+            f(&mut node.elem);
+            link = &mut node.next;
+        }
+    }
+}
+
+impl<T> Default for Transient<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Combinators
+//
+// Persistent versions of the usual list functions. None of them touch `self`, and each result
+// shares as much of `self` as it can: a suffix that comes through unchanged is reused, not copied.
+// E.g. `drop(n)` is just a walk down the list, and `concat` only copies the first list.
+//
+// They all use loops rather than recursion, so they work on lists of any length.
+
+// Push `prefix` onto the front of `rest`, so that `prefix[0]` ends up at the head.
+fn with_prefix<T>(prefix: Vec<T>, mut rest: List<T>) -> List<T> {
+    for elem in prefix.into_iter().rev() {
+        rest = rest.append(elem);
+    }
+    rest
+}
+
+impl<T> List<T> {
+    // the nth element, counting from 0 at the head
+    pub fn nth(&self, n: usize) -> Option<&T> {
+        self.iter().nth(n)
+    }
+
+    // the list without its first `n` elements, or an empty list if it's shorter than that
+    pub fn drop(&self, n: usize) -> List<T> {
+        List {
+            head: self.link_after(n).cloned(),
+        }
+    }
+
+    // The link `n` nodes down the list.
+    fn link_after(&self, n: usize) -> Option<&Rc<Node<T>>> {
+        let mut link = self.head.as_ref();
+        for _ in 0..n {
+            link = link?.next.as_ref();
+        }
+        link
+    }
+
+    // fold the elements from the head to the end
+    pub fn fold<B, F>(&self, init: B, f: F) -> B
+    where
+        F: FnMut(B, &T) -> B,
+    {
+        self.iter().fold(init, f)
+    }
+
+    // fold the elements from the end back to the head
+    pub fn fold_right<B, F>(&self, init: B, mut f: F) -> B
+    where
+        F: FnMut(&T, B) -> B,
+    {
+        let elems: Vec<&T> = self.iter().collect();
+        elems.into_iter().rev().fold(init, |acc, elem| f(elem, acc))
+    }
+
+    // a new list of `f` applied to every element (the element type changes, so nothing is shared)
+    pub fn map<U, F>(&self, f: F) -> List<U>
+    where
+        F: FnMut(&T) -> U,
+    {
+        with_prefix(self.iter().map(f).collect(), List::new())
+    }
+
+    // The first `n` elements, and a pointer to the rest of the list.
+    fn split_link(&self, n: usize) -> (Vec<&T>, Option<&Rc<Node<T>>>) {
+        let mut prefix = Vec::new();
+        let mut link = self.head.as_ref();
+        while prefix.len() < n {
+            match link {
+                Some(node) => {
+                    prefix.push(&node.elem);
+                    link = node.next.as_ref();
+                }
+                None => break,
+            }
+        }
+        (prefix, link)
+    }
+}
+
+impl<T: Clone> List<T> {
+    // the first `n` elements, or the whole list if it's shorter than that
+    pub fn take(&self, n: usize) -> List<T> {
+        match self.split_link(n) {
+            (_, None) => self.clone(),
+            (prefix, Some(_)) => with_prefix(prefix.into_iter().cloned().collect(), List::new()),
+        }
+    }
+
+    // `(self.take(n), self.drop(n))`
+    pub fn split_at(&self, n: usize) -> (List<T>, List<T>) {
+        match self.split_link(n) {
+            (_, None) => (self.clone(), List::new()),
+            (prefix, rest) => (
+                with_prefix(prefix.into_iter().cloned().collect(), List::new()),
+                List {
+                    head: rest.cloned(),
+                },
+            ),
+        }
+    }
+
+    // the longest prefix whose elements all satisfy `pred`
+    pub fn take_while<P>(&self, mut pred: P) -> List<T>
+    where
+        P: FnMut(&T) -> bool,
+    {
+        let mut prefix = Vec::new();
+        for elem in self.iter() {
+            if !pred(elem) {
+                return with_prefix(prefix, List::new());
+            }
+            prefix.push(elem.clone());
+        }
+        self.clone()
+    }
+
+    // The elements that satisfy `pred`. Everything after the last element that doesn't is shared.
+    pub fn filter<P>(&self, mut pred: P) -> List<T>
+    where
+        P: FnMut(&T) -> bool,
+    {
+        let mut kept = Vec::new();
+        // `kept[..copied]` come before the last rejected element, and `shared` is what's after it
+        let mut copied = 0;
+        let mut shared = self.head.as_ref();
+        let mut link = self.head.as_ref();
+        while let Some(node) = link {
+            if pred(&node.elem) {
+                kept.push(&node.elem);
+            } else {
+                copied = kept.len();
+                shared = node.next.as_ref();
+            }
+            link = node.next.as_ref();
+        }
+        kept.truncate(copied);
+        with_prefix(
+            kept.into_iter().cloned().collect(),
+            List {
+                head: shared.cloned(),
+            },
+        )
+    }
+
+    // the elements in reverse order
+    pub fn reverse(&self) -> List<T> {
+        let mut list = List::new();
+        for elem in self.iter() {
+            list = list.append(elem.clone());
+        }
+        list
+    }
+
+    // the elements of `self` followed by the elements of `other`; all of `other` is shared
+    pub fn concat(&self, other: &List<T>) -> List<T> {
+        with_prefix(self.iter().cloned().collect(), other.clone())
+    }
+
+    // pairs of elements from both lists, as long as the shorter one
+    pub fn zip<U: Clone>(&self, other: &List<U>) -> List<(T, U)> {
+        with_prefix(
+            self.iter()
+                .zip(other.iter())
+                .map(|(x, y)| (x.clone(), y.clone()))
+                .collect(),
+            List::new(),
+        )
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Standard traits
+//
+// Deriving these would recurse down the `next` links and overflow the stack on long lists, so
+// they're written as loops instead.
+//
+// Two versions of a persistent list often share most of their nodes. Once the comparisons below
+// reach a node that both lists share, the rest of the lists are the same nodes, so they stop
+// there instead of walking the shared suffix. (This treats a shared suffix as equal to itself
+// even if `T`'s `PartialEq` isn't reflexive, e.g. for a NaN float.)
+
+impl<T: Debug> Debug for List<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: PartialEq> PartialEq for List<T> {
+    fn eq(&self, other: &Self) -> bool {
+        if self.len() != other.len() {
+            return false;
+        }
+        let mut left = self.head.as_ref();
+        let mut right = other.head.as_ref();
+        loop {
+            match (left, right) {
+                (None, None) => return true,
+                (Some(l), Some(r)) => {
+                    if Rc::ptr_eq(l, r) {
+                        return true;
+                    }
+                    if l.elem != r.elem {
+                        return false;
+                    }
+                    left = l.next.as_ref();
+                    right = r.next.as_ref();
+                }
+                _ => return false,
+            }
+        }
+    }
+}
+
+impl<T: Eq> Eq for List<T> {}
+
+// Compare two lists lexicographically using `cmp` on the elements, stopping early at a shared
+// suffix.
+fn compare_by<T, F>(left: &List<T>, right: &List<T>, mut cmp: F) -> Option<Ordering>
+where
+    F: FnMut(&T, &T) -> Option<Ordering>,
+{
+    let mut left = left.head.as_ref();
+    let mut right = right.head.as_ref();
+    loop {
+        match (left, right) {
+            (None, None) => return Some(Ordering::Equal),
+            (None, Some(_)) => return Some(Ordering::Less),
+            (Some(_), None) => return Some(Ordering::Greater),
+            (Some(l), Some(r)) => {
+                if Rc::ptr_eq(l, r) {
+                    return Some(Ordering::Equal);
+                }
+                match cmp(&l.elem, &r.elem) {
+                    Some(Ordering::Equal) => {}
+                    non_eq => return non_eq,
+                }
+                left = l.next.as_ref();
+                right = r.next.as_ref();
+            }
+        }
+    }
+}
+
+impl<T: PartialOrd> PartialOrd for List<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        compare_by(self, other, T::partial_cmp)
+    }
+}
+
+impl<T: Ord> Ord for List<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        compare_by(self, other, |l, r| Some(l.cmp(r))).unwrap_or(Ordering::Equal)
+    }
+}
+
+impl<T: Hash> Hash for List<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // hash the length first, like slices do
+        self.len().hash(state);
+        for elem in self.iter() {
+            elem.hash(state);
+        }
+    }
 }
 
 //////////////////////////////////////////////////////////////////////////////
@@ -115,9 +594,21 @@ impl<'a, T> Iterator for Iter<'a, T> {
 
 #[cfg(test)]
 mod test {
-    use super::List;
+    use super::{List, Transient};
+    use std::cmp::Ordering;
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+    use std::io::{self, Write};
+    use std::rc::Rc;
+
+    fn hash_of<T: Hash>(x: &T) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        x.hash(&mut hasher);
+        hasher.finish()
+    }
 
     #[test]
+    #[allow(clippy::nonminimal_bool)]
     fn basic() {
         let list: List<i32> = List::new();
         assert_eq!(list.head(), None);
@@ -126,7 +617,8 @@ mod test {
         let list2 = list.append(0).append(1).append(2);
         assert_eq!(list2.head(), Some(&2));
         assert!(!list2.tail().is_none());
-        // can't directly compare tail to another list yet...
+        assert_eq!(list2.tail(), Some(List::new().append(0).append(1)));
+        assert_eq!(list.tail(), None);
     }
 
     #[test]
@@ -139,6 +631,36 @@ mod test {
         assert_eq!(iter.next(), None);
     }
 
+    // Every version of a list knows its own length, however many nodes it shares with others.
+    #[test]
+    fn len() {
+        let empty: List<i32> = List::new();
+        assert_eq!(empty.len(), 0);
+        assert!(empty.is_empty());
+
+        let list = empty.append(0).append(1).append(2);
+        let longer = list.append(3);
+        let other = list.tail().unwrap().append(10);
+        assert_eq!((list.len(), longer.len(), other.len()), (3, 4, 3));
+        assert!(!list.is_empty());
+        assert_eq!(list.drop(2).len(), 1);
+        assert_eq!(longer.concat(&other).len(), 7);
+        assert_eq!(longer.filter(|x| x % 2 == 0).len(), 2);
+
+        let mut t = longer.clone().transient();
+        t.push(4);
+        t.pop();
+        t.pop();
+        assert_eq!(t.freeze().len(), 3);
+
+        let mut iter = longer.iter();
+        assert_eq!(iter.len(), 4);
+        iter.next();
+        assert_eq!(iter.size_hint(), (3, Some(3)));
+        assert_eq!(iter.by_ref().count(), 3);
+        assert_eq!(iter.next(), None);
+    }
+
     // If the Drop impl for List is commented out above, this test will cause the stack to
     // overflow.
     #[test]
@@ -153,4 +675,297 @@ mod test {
         assert_eq!(list.head(), Some(&1024));
         // list and list2 share a tail and are both dropped here
     }
+
+    #[test]
+    fn traits() {
+        let list = List::new().append(3).append(2).append(1);
+        assert_eq!(format!("{:?}", list), "[1, 2, 3]");
+        assert_eq!(format!("{:?}", List::<i32>::new()), "[]");
+
+        // equal lists, with and without shared nodes
+        let rebuilt = List::new().append(3).append(2).append(1);
+        let shares_tail = list.tail().unwrap().append(1);
+        assert_eq!(list, rebuilt);
+        assert_eq!(list, shares_tail);
+        assert_eq!(hash_of(&list), hash_of(&rebuilt));
+
+        // unequal lists
+        let shorter = list.tail().unwrap();
+        let bigger = list.tail().unwrap().append(5);
+        assert_ne!(list, shorter);
+        assert_ne!(list, bigger);
+        assert!(shorter > list);
+        assert!(list < bigger);
+        assert_eq!(list.cmp(&shares_tail), Ordering::Equal);
+        assert_eq!(
+            List::new().append(1).cmp(&List::new().append(1).append(0)),
+            Ordering::Greater
+        );
+        assert_eq!(List::<i32>::new().cmp(&list), Ordering::Less);
+    }
+
+    // Two versions sharing a long suffix compare in time proportional to the part they don't
+    // share. Elements in the shared suffix are never even looked at.
+    #[test]
+    fn compare_shared_suffix() {
+        use std::cell::Cell;
+
+        // counts how often it's compared
+        struct Counted<'a>(i32, &'a Cell<usize>);
+        impl<'a> PartialEq for Counted<'a> {
+            fn eq(&self, other: &Self) -> bool {
+                self.1.set(self.1.get() + 1);
+                self.0 == other.0
+            }
+        }
+        impl<'a> PartialOrd for Counted<'a> {
+            fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+                self.1.set(self.1.get() + 1);
+                self.0.partial_cmp(&other.0)
+            }
+        }
+
+        let count = Cell::new(0);
+        let mut shared = List::new();
+        for i in 0..1000 {
+            shared = shared.append(Counted(i, &count));
+        }
+        let left = shared
+            .append(Counted(-1, &count))
+            .append(Counted(-2, &count));
+        let right = shared
+            .append(Counted(-1, &count))
+            .append(Counted(-2, &count));
+
+        assert!(left == right);
+        assert_eq!(count.get(), 2);
+        assert_eq!(left.partial_cmp(&right), Some(Ordering::Equal));
+        assert_eq!(count.get(), 4);
+    }
+
+    fn from_vec(elems: Vec<i32>) -> List<i32> {
+        let mut list = List::new();
+        for elem in elems.into_iter().rev() {
+            list = list.append(elem);
+        }
+        list
+    }
+
+    fn to_vec<T: Clone>(list: &List<T>) -> Vec<T> {
+        list.iter().cloned().collect()
+    }
+
+    // Whether `suffix` is made of the same nodes as the last part of `list`.
+    fn shares_suffix<T>(list: &List<T>, suffix: &List<T>) -> bool {
+        let mut link = list.head.as_ref();
+        loop {
+            match (link, suffix.head.as_ref()) {
+                (_, None) => return true,
+                (Some(node), Some(target)) if Rc::ptr_eq(node, target) => return true,
+                (Some(node), _) => link = node.next.as_ref(),
+                (None, Some(_)) => return false,
+            }
+        }
+    }
+
+    #[test]
+    fn combinators() {
+        let list = from_vec(vec![1, 2, 3, 4, 5]);
+        let empty = List::<i32>::new();
+
+        assert_eq!(list.nth(0), Some(&1));
+        assert_eq!(list.nth(4), Some(&5));
+        assert_eq!(list.nth(5), None);
+
+        assert_eq!(to_vec(&list.drop(2)), vec![3, 4, 5]);
+        assert_eq!(to_vec(&list.drop(9)), vec![]);
+        assert_eq!(to_vec(&list.take(2)), vec![1, 2]);
+        assert_eq!(to_vec(&list.take(9)), vec![1, 2, 3, 4, 5]);
+        let (front, back) = list.split_at(3);
+        assert_eq!((to_vec(&front), to_vec(&back)), (vec![1, 2, 3], vec![4, 5]));
+        assert_eq!(to_vec(&list.take_while(|&x| x < 3)), vec![1, 2]);
+        assert_eq!(to_vec(&list.take_while(|&x| x > 3)), vec![]);
+
+        assert_eq!(list.fold(0, |acc, x| acc * 10 + x), 12345);
+        assert_eq!(list.fold_right(0, |x, acc| acc * 10 + x), 54321);
+        assert_eq!(
+            to_vec(&list.map(|x| x.to_string())),
+            vec!["1", "2", "3", "4", "5"]
+        );
+        assert_eq!(to_vec(&list.filter(|x| x % 2 == 1)), vec![1, 3, 5]);
+        assert_eq!(to_vec(&list.reverse()), vec![5, 4, 3, 2, 1]);
+        assert_eq!(
+            to_vec(&list.drop(3).concat(&list)),
+            vec![4, 5, 1, 2, 3, 4, 5]
+        );
+        assert_eq!(to_vec(&list.zip(&list.drop(3))), vec![(1, 4), (2, 5)]);
+
+        assert_eq!(empty.fold(0, |acc, x| acc + x), 0);
+        assert_eq!(to_vec(&empty.reverse()), vec![]);
+        assert_eq!(to_vec(&empty.concat(&list)), to_vec(&list));
+        assert_eq!(to_vec(&list.zip(&empty)), vec![]);
+    }
+
+    #[test]
+    fn combinators_share_suffixes() {
+        let list = from_vec(vec![1, 2, 3, 4, 5, 6]);
+
+        assert!(shares_suffix(&list, &list.drop(2)));
+        assert!(shares_suffix(&list, &list.split_at(2).1));
+        assert!(shares_suffix(&list.drop(2).concat(&list), &list));
+
+        // whole lists are shared when nothing is cut off
+        assert!(Rc::ptr_eq(
+            list.take(6).head.as_ref().unwrap(),
+            list.head.as_ref().unwrap()
+        ));
+        assert!(Rc::ptr_eq(
+            list.take_while(|_| true).head.as_ref().unwrap(),
+            list.head.as_ref().unwrap()
+        ));
+        assert!(Rc::ptr_eq(
+            list.filter(|_| true).head.as_ref().unwrap(),
+            list.head.as_ref().unwrap()
+        ));
+
+        // filter shares everything after the last element it drops
+        let filtered = list.filter(|&x| x != 3);
+        assert_eq!(to_vec(&filtered), vec![1, 2, 4, 5, 6]);
+        assert!(shares_suffix(&filtered, &list.drop(3)));
+        assert!(!shares_suffix(&filtered, &list.drop(1)));
+    }
+
+    #[test]
+    fn sharing() {
+        let base = from_vec(vec![3, 4, 5]);
+        let left = base.append(2).append(1);
+        let right = base.append(20);
+
+        // cloning shares every node
+        let copy = left.clone();
+        assert!(copy.ptr_eq(&left));
+        assert!(!left.ptr_eq(&from_vec(vec![1, 2, 3, 4, 5])));
+        assert!(List::<i32>::new().ptr_eq(&List::new()));
+        assert_eq!(left.strong_count_of_head(), Some(2));
+        assert_eq!(List::<i32>::new().strong_count_of_head(), None);
+
+        // `base` is shared by `left` and `right`, and `left` by `copy`
+        assert!(!left.is_uniquely_owned());
+        drop(copy);
+        assert!(!left.is_uniquely_owned());
+        assert_eq!(base.strong_count_of_head(), Some(3));
+        assert!(from_vec(vec![1, 2]).is_uniquely_owned());
+        assert!(List::<i32>::new().is_uniquely_owned());
+
+        let suffix = left.common_suffix(&right);
+        assert!(suffix.ptr_eq(&base));
+        assert!(right.common_suffix(&left).ptr_eq(&base));
+        assert!(left.common_suffix(&left).ptr_eq(&left));
+        assert!(left.common_suffix(&base).ptr_eq(&base));
+        // equal elements, but no shared nodes
+        let rebuilt = from_vec(vec![1, 2, 3, 4, 5]);
+        assert!(left.common_suffix(&rebuilt).ptr_eq(&List::new()));
+        assert!(left.common_suffix(&List::new()).ptr_eq(&List::new()));
+    }
+
+    #[test]
+    fn transient() {
+        let mut t = Transient::new();
+        assert_eq!(t.pop(), None);
+        assert_eq!(t.head_mut(), None);
+        t.push(3);
+        t.push(2);
+        t.push(1);
+        assert_eq!(t.head(), Some(&1));
+        assert_eq!(t.len(), 3);
+        assert_eq!(t.pop(), Some(1));
+        *t.head_mut().unwrap() = 20;
+        t.map_in_place(|x| *x *= 10);
+        let list = t.freeze();
+        assert_eq!(to_vec(&list), vec![200, 30]);
+        assert!(list.is_uniquely_owned());
+    }
+
+    // Editing a transient of a shared list leaves the other versions alone, and only copies the
+    // nodes it has to.
+    #[test]
+    fn transient_copy_on_write() {
+        let base = from_vec(vec![3, 4, 5]);
+        let old = base.append(2).append(1);
+
+        // a uniquely owned head is changed in place
+        let mut t = old.clone().append(0).transient();
+        let head = t.list.head.as_ref().map(Rc::as_ptr);
+        *t.head_mut().unwrap() = 100;
+        assert_eq!(t.list.head.as_ref().map(Rc::as_ptr), head);
+
+        // popping a shared node clones its element, and leaves it in `old`
+        assert_eq!(t.pop(), Some(100));
+        assert_eq!(t.pop(), Some(1));
+        *t.head_mut().unwrap() = 200;
+        let edited = t.freeze();
+        assert_eq!(to_vec(&edited), vec![200, 3, 4, 5]);
+        assert_eq!(to_vec(&old), vec![1, 2, 3, 4, 5]);
+        // only the node that was changed was copied
+        assert!(edited.common_suffix(&old).ptr_eq(&base));
+
+        // mapping copies everything from the first shared node on
+        let mut t = base.clone().append(2).append(1).transient();
+        t.map_in_place(|x| *x = -*x);
+        let mapped = t.freeze();
+        assert_eq!(to_vec(&mapped), vec![-1, -2, -3, -4, -5]);
+        assert_eq!(to_vec(&base), vec![3, 4, 5]);
+        assert!(mapped.common_suffix(&base).ptr_eq(&List::new()));
+    }
+
+    // If the transient's drop or `map_in_place` recursed, this would overflow the stack.
+    #[test]
+    fn transient_is_stack_safe() {
+        let mut t = Transient::new();
+        for i in 0..1000000 {
+            t.push(i);
+        }
+        let list = t.freeze();
+        let mut t = list.clone().transient();
+        t.map_in_place(|x| *x += 1);
+        assert_eq!(t.head(), Some(&1000000));
+        assert_eq!(list.head(), Some(&999999));
+        // `t` and `list` are dropped here
+    }
+
+    // These all walk million-element lists and would overflow the stack if they recursed.
+    #[test]
+    fn combinators_are_stack_safe() {
+        let list = from_vec((0..1000000).collect());
+        assert_eq!(list.nth(999999), Some(&999999));
+        assert_eq!(list.fold(0u64, |acc, &x| acc + x as u64), 499999500000);
+        assert_eq!(
+            list.fold_right(0u64, |&x, acc| acc + x as u64),
+            499999500000
+        );
+        assert_eq!(list.map(|&x| x + 1).nth(999999), Some(&1000000));
+        assert_eq!(list.filter(|&x| x % 2 == 0).nth(1), Some(&2));
+        assert_eq!(list.reverse().head(), Some(&999999));
+        assert_eq!(list.concat(&list).nth(1000000), Some(&0));
+        assert_eq!(list.take(999999).nth(999998), Some(&999998));
+        assert_eq!(list.drop(999999).head(), Some(&999999));
+        assert_eq!(list.split_at(500000).1.head(), Some(&500000));
+        assert_eq!(list.zip(&list.drop(1)).nth(999998), Some(&(999998, 999999)));
+        assert_eq!(list.take_while(|&x| x < 999999).nth(999998), Some(&999998));
+    }
+
+    // These all walk million-element lists and would overflow the stack if they recursed.
+    #[test]
+    fn traits_are_stack_safe() {
+        let mut list = List::new();
+        let mut copy = List::new();
+        for i in 0..1000000 {
+            list = list.append(i);
+            copy = copy.append(i);
+        }
+        assert!(list == copy);
+        assert_eq!(list.cmp(&copy), Ordering::Equal);
+        assert_eq!(hash_of(&list), hash_of(&copy));
+        write!(io::sink(), "{:?}", list).unwrap();
+    }
 }
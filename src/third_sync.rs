@@ -0,0 +1,327 @@
+// A thread-safe persistent singly-linked stack implementation.
+//
+// Improvements over `third`:
+//     - nodes are shared with `Arc`, so versions of the list can be sent to and shared between
+//       threads
+//     - `List<T>` is `Send + Sync` whenever `T` is
+//
+// The API is the same as `third::List`. Every version is immutable, so sharing nodes between
+// threads needs no locking at all; the only synchronization is the atomic reference count.
+//
+//               thread 1: X ---+
+//                              v
+// shared tail ---------------> B -> C -> D
+//                              ^
+//               thread 2: Y ---+
+
+use std::cmp::Ordering;
+use std::fmt::{self, Debug};
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+//////////////////////////////////////////////////////////////////////////////
+// Data Structures
+
+pub struct List<T> {
+    head: Link<T>,
+}
+
+type Link<T> = Option<Arc<Node<T>>>;
+
+struct Node<T> {
+    elem: T,
+    next: Link<T>,
+    // the length of the list that starts at this node, as in `third::List`
+    len: usize,
+}
+
+pub struct Iter<'a, T> {
+    next: Option<&'a Node<T>>,
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Implementation
+
+impl<T> List<T> {
+    pub fn new() -> Self {
+        List { head: None }
+    }
+
+    pub fn append(&self, elem: T) -> List<T> {
+        List {
+            head: Some(Arc::new(Node {
+                elem,
+                next: self.head.clone(),
+                len: self.len() + 1,
+            })),
+        }
+    }
+
+    // the number of elements, in O(1)
+    pub fn len(&self) -> usize {
+        self.head.as_ref().map_or(0, |node| node.len)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.is_none()
+    }
+
+    pub fn tail(&self) -> Option<List<T>> {
+        self.head.as_ref().map(|arc_node| List {
+            head: arc_node.next.clone(),
+        })
+    }
+
+    pub fn head(&self) -> Option<&T> {
+        self.head.as_ref().map(|arc_node| &arc_node.elem)
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            next: self.head.as_deref(),
+        }
+    }
+}
+
+impl<T> Default for List<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for List<T> {
+    fn drop(&mut self) {
+        let mut head = self.head.take();
+        while let Some(node) = head {
+            // Same idea as `third::List`: if this is the last pointer to the node, unlink it and
+            // carry on down the list, otherwise stop since someone else holds it.
+            //
+            // `Arc::into_inner` rather than `try_unwrap` because another thread may be dropping
+            // a list that shares this node right now. With `try_unwrap` both threads can fail
+            // (each sees a count of 2), then both drop their `Arc`, and whichever is last frees
+            // the node through the ordinary recursive drop. `into_inner` guarantees exactly one
+            // of them gets the node.
+            head = Arc::into_inner(node).and_then(|mut node| node.next.take());
+        }
+    }
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next.map(|node| {
+            self.next = node.next.as_deref();
+            &node.elem
+        })
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Standard traits
+//
+// Deriving these would recurse down the `next` links and overflow the stack on long lists, so
+// they're written as loops instead.
+//
+// Two versions of a persistent list often share most of their nodes. Once the comparisons below
+// reach a node that both lists share, the rest of the lists are the same nodes, so they stop
+// there instead of walking the shared suffix. (This treats a shared suffix as equal to itself
+// even if `T`'s `PartialEq` isn't reflexive, e.g. for a NaN float.)
+
+impl<T: Debug> Debug for List<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: PartialEq> PartialEq for List<T> {
+    fn eq(&self, other: &Self) -> bool {
+        let mut left = self.head.as_ref();
+        let mut right = other.head.as_ref();
+        loop {
+            match (left, right) {
+                (None, None) => return true,
+                (Some(l), Some(r)) => {
+                    if Arc::ptr_eq(l, r) {
+                        return true;
+                    }
+                    if l.elem != r.elem {
+                        return false;
+                    }
+                    left = l.next.as_ref();
+                    right = r.next.as_ref();
+                }
+                _ => return false,
+            }
+        }
+    }
+}
+
+impl<T: Eq> Eq for List<T> {}
+
+// Compare two lists lexicographically using `cmp` on the elements, stopping early at a shared
+// suffix.
+fn compare_by<T, F>(left: &List<T>, right: &List<T>, mut cmp: F) -> Option<Ordering>
+where
+    F: FnMut(&T, &T) -> Option<Ordering>,
+{
+    let mut left = left.head.as_ref();
+    let mut right = right.head.as_ref();
+    loop {
+        match (left, right) {
+            (None, None) => return Some(Ordering::Equal),
+            (None, Some(_)) => return Some(Ordering::Less),
+            (Some(_), None) => return Some(Ordering::Greater),
+            (Some(l), Some(r)) => {
+                if Arc::ptr_eq(l, r) {
+                    return Some(Ordering::Equal);
+                }
+                match cmp(&l.elem, &r.elem) {
+                    Some(Ordering::Equal) => {}
+                    non_eq => return non_eq,
+                }
+                left = l.next.as_ref();
+                right = r.next.as_ref();
+            }
+        }
+    }
+}
+
+impl<T: PartialOrd> PartialOrd for List<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        compare_by(self, other, T::partial_cmp)
+    }
+}
+
+impl<T: Ord> Ord for List<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        compare_by(self, other, |l, r| Some(l.cmp(r))).unwrap_or(Ordering::Equal)
+    }
+}
+
+impl<T: Hash> Hash for List<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // hash the length first, like slices do
+        self.len().hash(state);
+        for elem in self.iter() {
+            elem.hash(state);
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Tests
+
+#[cfg(test)]
+mod test {
+    use super::List;
+    use std::thread;
+
+    #[test]
+    fn basic() {
+        let list: List<i32> = List::new();
+        assert_eq!(list.head(), None);
+        assert!(list.tail().is_none());
+        assert!(list.is_empty());
+
+        let list2 = list.append(0).append(1).append(2);
+        assert_eq!(list2.head(), Some(&2));
+        assert_eq!(list2.len(), 3);
+        assert_eq!(list2.tail().unwrap().len(), 2);
+        assert_eq!(list2.tail(), Some(List::new().append(0).append(1)));
+        assert_eq!(format!("{:?}", list2), "[2, 1, 0]");
+    }
+
+    #[test]
+    fn iter() {
+        let list = List::new().append(0).append(1).append(2);
+        let mut iter = list.iter();
+        assert_eq!(iter.next(), Some(&2));
+        assert_eq!(iter.next(), Some(&1));
+        assert_eq!(iter.next(), Some(&0));
+        assert_eq!(iter.next(), None);
+    }
+
+    // If the Drop impl for List is commented out above, this test will cause the stack to
+    // overflow.
+    #[test]
+    fn test_drop() {
+        let mut list = List::new();
+        for i in 0..1000000 {
+            list = list.append(i);
+        }
+        let list2 = list.append(42);
+        assert_eq!(list2.head(), Some(&42));
+        list = list.append(1024);
+        assert_eq!(list.head(), Some(&1024));
+        // list and list2 share a tail and are both dropped here
+    }
+
+    // Several threads build their own versions on top of one shared tail at the same time.
+    #[test]
+    fn extend_shared_tail() {
+        let mut shared = List::new();
+        for i in 0..1000 {
+            shared = shared.append(i);
+        }
+
+        let versions: Vec<List<i32>> = thread::scope(|s| {
+            let handles: Vec<_> = (0..8)
+                .map(|t| {
+                    let shared = &shared;
+                    s.spawn(move || {
+                        let mut list = shared.append(-t);
+                        for i in 0..1000 {
+                            list = list.append(t * 1000 + i);
+                        }
+                        list
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        // the shared tail is untouched, and every version sits on top of it
+        assert_eq!(shared.len(), 1000);
+        assert_eq!(shared.head(), Some(&999));
+        for (t, version) in versions.iter().enumerate() {
+            let t = t as i32;
+            let mut iter = version.iter();
+            for i in (0..1000).rev() {
+                assert_eq!(iter.next(), Some(&(t * 1000 + i)));
+            }
+            assert_eq!(iter.next(), Some(&-t));
+            assert!(iter.eq(shared.iter()));
+        }
+    }
+
+    // Versions that share a long tail are dropped concurrently from many threads. Exactly one
+    // thread must end up freeing the shared nodes, and none of them may recurse.
+    #[test]
+    fn drop_shared_tail_concurrently() {
+        let mut shared = List::new();
+        for i in 0..100000 {
+            shared = shared.append(i);
+        }
+        let versions: Vec<List<i32>> = (0..8).map(|t| shared.append(t)).collect();
+        drop(shared);
+
+        thread::scope(|s| {
+            for version in versions {
+                s.spawn(move || drop(version));
+            }
+        });
+    }
+
+    // This only needs to compile.
+    #[allow(dead_code)]
+    fn assert_properties() {
+        fn is_send<T: Send>() {}
+        fn is_sync<T: Sync>() {}
+
+        is_send::<List<i32>>();
+        is_sync::<List<i32>>();
+        is_send::<super::Iter<i32>>();
+        is_sync::<super::Iter<i32>>();
+    }
+}
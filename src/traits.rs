@@ -0,0 +1,647 @@
+// Common traits for the lists in this crate, so code (and tests) can be written once for all of
+// them.
+//
+// Features:
+//     - `Stack`: a mutable last-in, first-out container
+//     - `Fifo`: a mutable first-in, first-out queue
+//     - `PersistentStack`: an immutable stack, where pushing and popping return new versions
+//     - one conformance suite per trait, run against every implementation
+//
+// Inspired by:
+// https://doc.rust-lang.org/std/collections/index.html
+//
+// `peek` and `peek_mut` return associated types rather than plain references because
+// `fourth::List` can only lend out its elements through `Ref`/`RefMut` guards. Everything else
+// just uses `&T` and `&mut T`.
+//
+// Double-ended lists are both a `Stack` (pushing and popping at the front) and a `Fifo` (pushing
+// at the back, popping at the front). The persistent queues take `&mut self` in `Fifo` by
+// swapping in the new version, which means cloning the element they pop.
+//
+// Not every type fits. `treiber::Stack` and `ms_queue::Queue` are shared through `&self` and can
+// only hand out copies of their elements, `stream::Stream` may be infinite, and `zipper::Zipper`
+// is a position in a list rather than a container.
+//
+// All the implementations live here and forward to the inherent methods, so the traits don't
+// need to be imported to use a list.
+
+use std::cell::{Ref, RefMut};
+use std::ops::{Deref, DerefMut};
+
+use crate::finger_tree::{FingerTree, Size};
+use crate::{arena, bankers_queue, fifth, fifth_unsafe, first, fourth, random_access};
+use crate::{real_time_queue, second, sixth, third, third_sync, unrolled};
+
+//////////////////////////////////////////////////////////////////////////////
+// Traits
+
+pub trait Stack<T> {
+    type Peek<'a>: Deref<Target = T>
+    where
+        Self: 'a;
+    type PeekMut<'a>: DerefMut<Target = T>
+    where
+        Self: 'a;
+
+    fn push(&mut self, elem: T);
+
+    // the most recently pushed element, or None if the stack is empty
+    fn pop(&mut self) -> Option<T>;
+
+    fn peek(&self) -> Option<Self::Peek<'_>>;
+
+    fn peek_mut(&mut self) -> Option<Self::PeekMut<'_>>;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+pub trait Fifo<T> {
+    type Peek<'a>: Deref<Target = T>
+    where
+        Self: 'a;
+
+    fn push(&mut self, elem: T);
+
+    // the oldest element, or None if the queue is empty
+    fn pop(&mut self) -> Option<T>;
+
+    fn peek(&self) -> Option<Self::Peek<'_>>;
+
+    fn is_empty(&self) -> bool {
+        self.peek().is_none()
+    }
+}
+
+pub trait PersistentStack<T>: Sized {
+    // a new stack with `elem` on top; `self` is left alone
+    fn push(&self, elem: T) -> Self;
+
+    fn peek(&self) -> Option<&T>;
+
+    // the stack without its top element, or None if it's empty
+    fn pop(&self) -> Option<Self>;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Stacks
+
+impl Stack<i32> for first::List {
+    type Peek<'a> = &'a i32;
+    type PeekMut<'a> = &'a mut i32;
+
+    fn push(&mut self, elem: i32) {
+        first::List::push(self, elem)
+    }
+    fn pop(&mut self) -> Option<i32> {
+        first::List::pop(self)
+    }
+    fn peek(&self) -> Option<&i32> {
+        first::List::peek(self)
+    }
+    fn peek_mut(&mut self) -> Option<&mut i32> {
+        first::List::peek_mut(self)
+    }
+    fn len(&self) -> usize {
+        first::List::len(self)
+    }
+}
+
+impl<T> Stack<T> for second::List<T> {
+    type Peek<'a>
+        = &'a T
+    where
+        T: 'a;
+    type PeekMut<'a>
+        = &'a mut T
+    where
+        T: 'a;
+
+    fn push(&mut self, elem: T) {
+        second::List::push(self, elem)
+    }
+    fn pop(&mut self) -> Option<T> {
+        second::List::pop(self)
+    }
+    fn peek(&self) -> Option<&T> {
+        second::List::peek(self)
+    }
+    fn peek_mut(&mut self) -> Option<&mut T> {
+        second::List::peek_mut(self)
+    }
+    fn len(&self) -> usize {
+        second::List::len(self)
+    }
+}
+
+impl<T> Stack<T> for arena::List<T> {
+    type Peek<'a>
+        = &'a T
+    where
+        T: 'a;
+    type PeekMut<'a>
+        = &'a mut T
+    where
+        T: 'a;
+
+    // the handle to the new node is dropped; it's only needed to edit the node later
+    fn push(&mut self, elem: T) {
+        arena::List::push(self, elem);
+    }
+    fn pop(&mut self) -> Option<T> {
+        arena::List::pop(self)
+    }
+    fn peek(&self) -> Option<&T> {
+        arena::List::peek(self)
+    }
+    fn peek_mut(&mut self) -> Option<&mut T> {
+        arena::List::peek_mut(self)
+    }
+    fn len(&self) -> usize {
+        arena::List::len(self)
+    }
+}
+
+impl<T, const N: usize> Stack<T> for unrolled::List<T, N> {
+    type Peek<'a>
+        = &'a T
+    where
+        T: 'a;
+    type PeekMut<'a>
+        = &'a mut T
+    where
+        T: 'a;
+
+    fn push(&mut self, elem: T) {
+        unrolled::List::push(self, elem)
+    }
+    fn pop(&mut self) -> Option<T> {
+        unrolled::List::pop(self)
+    }
+    fn peek(&self) -> Option<&T> {
+        unrolled::List::peek(self)
+    }
+    fn peek_mut(&mut self) -> Option<&mut T> {
+        unrolled::List::peek_mut(self)
+    }
+    fn len(&self) -> usize {
+        unrolled::List::len(self)
+    }
+}
+
+impl<T> Stack<T> for fourth::List<T> {
+    type Peek<'a>
+        = Ref<'a, T>
+    where
+        T: 'a;
+    type PeekMut<'a>
+        = RefMut<'a, T>
+    where
+        T: 'a;
+
+    fn push(&mut self, elem: T) {
+        fourth::List::push_front(self, elem)
+    }
+    fn pop(&mut self) -> Option<T> {
+        fourth::List::pop_front(self)
+    }
+    fn peek(&self) -> Option<Ref<'_, T>> {
+        fourth::List::peek_front(self)
+    }
+    fn peek_mut(&mut self) -> Option<RefMut<'_, T>> {
+        fourth::List::peek_front_mut(self)
+    }
+    fn len(&self) -> usize {
+        fourth::List::len(self)
+    }
+}
+
+impl<T> Stack<T> for sixth::List<T> {
+    type Peek<'a>
+        = &'a T
+    where
+        T: 'a;
+    type PeekMut<'a>
+        = &'a mut T
+    where
+        T: 'a;
+
+    fn push(&mut self, elem: T) {
+        sixth::List::push_front(self, elem)
+    }
+    fn pop(&mut self) -> Option<T> {
+        sixth::List::pop_front(self)
+    }
+    fn peek(&self) -> Option<&T> {
+        sixth::List::front(self)
+    }
+    fn peek_mut(&mut self) -> Option<&mut T> {
+        sixth::List::front_mut(self)
+    }
+    fn len(&self) -> usize {
+        sixth::List::len(self)
+    }
+}
+
+// Popping and peeking mutably copy the head node if it's shared with another version.
+impl<T: Clone> Stack<T> for third::Transient<T> {
+    type Peek<'a>
+        = &'a T
+    where
+        T: 'a;
+    type PeekMut<'a>
+        = &'a mut T
+    where
+        T: 'a;
+
+    fn push(&mut self, elem: T) {
+        third::Transient::push(self, elem)
+    }
+    fn pop(&mut self) -> Option<T> {
+        third::Transient::pop(self)
+    }
+    fn peek(&self) -> Option<&T> {
+        third::Transient::head(self)
+    }
+    fn peek_mut(&mut self) -> Option<&mut T> {
+        third::Transient::head_mut(self)
+    }
+    fn len(&self) -> usize {
+        third::Transient::len(self)
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Queues
+
+impl<T> Fifo<T> for fifth::Queue<T> {
+    type Peek<'a>
+        = &'a T
+    where
+        T: 'a;
+
+    fn push(&mut self, elem: T) {
+        fifth::Queue::push(self, elem)
+    }
+    fn pop(&mut self) -> Option<T> {
+        fifth::Queue::pop(self)
+    }
+    fn peek(&self) -> Option<&T> {
+        fifth::Queue::peek(self)
+    }
+}
+
+impl<T> Fifo<T> for fifth_unsafe::Queue<T> {
+    type Peek<'a>
+        = &'a T
+    where
+        T: 'a;
+
+    fn push(&mut self, elem: T) {
+        fifth_unsafe::Queue::push(self, elem)
+    }
+    fn pop(&mut self) -> Option<T> {
+        fifth_unsafe::Queue::pop(self)
+    }
+    fn peek(&self) -> Option<&T> {
+        fifth_unsafe::Queue::peek(self)
+    }
+}
+
+impl<T> Fifo<T> for fourth::List<T> {
+    type Peek<'a>
+        = Ref<'a, T>
+    where
+        T: 'a;
+
+    fn push(&mut self, elem: T) {
+        fourth::List::push_back(self, elem)
+    }
+    fn pop(&mut self) -> Option<T> {
+        fourth::List::pop_front(self)
+    }
+    fn peek(&self) -> Option<Ref<'_, T>> {
+        fourth::List::peek_front(self)
+    }
+}
+
+impl<T> Fifo<T> for sixth::List<T> {
+    type Peek<'a>
+        = &'a T
+    where
+        T: 'a;
+
+    fn push(&mut self, elem: T) {
+        sixth::List::push_back(self, elem)
+    }
+    fn pop(&mut self) -> Option<T> {
+        sixth::List::pop_front(self)
+    }
+    fn peek(&self) -> Option<&T> {
+        sixth::List::front(self)
+    }
+}
+
+impl<T: Clone> Fifo<T> for bankers_queue::Queue<T> {
+    type Peek<'a>
+        = &'a T
+    where
+        T: 'a;
+
+    fn push(&mut self, elem: T) {
+        *self = self.push_back(elem);
+    }
+    fn pop(&mut self) -> Option<T> {
+        let (elem, rest) = self.pop_front()?;
+        *self = rest;
+        Some(elem)
+    }
+    fn peek(&self) -> Option<&T> {
+        bankers_queue::Queue::peek(self)
+    }
+}
+
+impl<T: Clone> Fifo<T> for real_time_queue::Queue<T> {
+    type Peek<'a>
+        = &'a T
+    where
+        T: 'a;
+
+    fn push(&mut self, elem: T) {
+        *self = self.push_back(elem);
+    }
+    fn pop(&mut self) -> Option<T> {
+        let (elem, rest) = self.pop_front()?;
+        *self = rest;
+        Some(elem)
+    }
+    fn peek(&self) -> Option<&T> {
+        real_time_queue::Queue::peek(self)
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Persistent stacks
+
+impl<T> PersistentStack<T> for third::List<T> {
+    fn push(&self, elem: T) -> Self {
+        self.append(elem)
+    }
+    fn peek(&self) -> Option<&T> {
+        self.head()
+    }
+    fn pop(&self) -> Option<Self> {
+        self.tail()
+    }
+    fn len(&self) -> usize {
+        third::List::len(self)
+    }
+}
+
+impl<T> PersistentStack<T> for third_sync::List<T> {
+    fn push(&self, elem: T) -> Self {
+        self.append(elem)
+    }
+    fn peek(&self) -> Option<&T> {
+        self.head()
+    }
+    fn pop(&self) -> Option<Self> {
+        self.tail()
+    }
+    fn len(&self) -> usize {
+        third_sync::List::len(self)
+    }
+}
+
+impl<T> PersistentStack<T> for random_access::List<T> {
+    fn push(&self, elem: T) -> Self {
+        self.cons(elem)
+    }
+    fn peek(&self) -> Option<&T> {
+        self.head()
+    }
+    fn pop(&self) -> Option<Self> {
+        self.tail()
+    }
+    fn len(&self) -> usize {
+        random_access::List::len(self)
+    }
+}
+
+// Only for the default `Size` measure, which is what gives the tree its length.
+impl<T> PersistentStack<T> for FingerTree<T, Size> {
+    fn push(&self, elem: T) -> Self {
+        self.push_front(elem)
+    }
+    fn peek(&self) -> Option<&T> {
+        self.front()
+    }
+    fn pop(&self) -> Option<Self> {
+        self.pop_front().map(|(_, rest)| rest)
+    }
+    fn len(&self) -> usize {
+        FingerTree::len(self)
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Tests
+//
+// Each suite is written once against its trait. Adding an implementation only takes another
+// line in the test for that trait.
+
+#[cfg(test)]
+mod test {
+    use super::{Fifo, PersistentStack, Stack};
+    use crate::finger_tree::FingerTree;
+    use crate::{arena, bankers_queue, fifth, fifth_unsafe, first, fourth, random_access};
+    use crate::{real_time_queue, second, sixth, third, third_sync, unrolled};
+
+    // enough to overflow the stack if anything recurses down the list
+    const MANY: i32 = 100000;
+
+    fn stack_conformance<S: Stack<i32> + Default>() {
+        let mut stack = S::default();
+
+        // an empty stack
+        assert!(stack.is_empty());
+        assert_eq!(stack.len(), 0);
+        assert!(stack.peek().is_none());
+        assert!(stack.peek_mut().is_none());
+        assert_eq!(stack.pop(), None);
+
+        // last in, first out
+        stack.push(1);
+        stack.push(2);
+        stack.push(3);
+        assert_eq!(stack.len(), 3);
+        assert!(!stack.is_empty());
+        assert_eq!(*stack.peek().unwrap(), 3);
+        assert_eq!(stack.pop(), Some(3));
+        assert_eq!(stack.len(), 2);
+
+        // editing the top in place
+        *stack.peek_mut().unwrap() *= 10;
+        assert_eq!(*stack.peek().unwrap(), 20);
+
+        // push more onto the pre-popped stack
+        stack.push(4);
+        assert_eq!(stack.pop(), Some(4));
+        assert_eq!(stack.pop(), Some(20));
+        assert_eq!(stack.pop(), Some(1));
+
+        // test for exhaustion
+        assert_eq!(stack.pop(), None);
+        assert_eq!(stack.pop(), None);
+        assert!(stack.is_empty());
+
+        // a long stack, popped down to the end and then dropped full
+        for i in 0..MANY {
+            stack.push(i);
+        }
+        assert_eq!(stack.len(), MANY as usize);
+        for i in (MANY / 2..MANY).rev() {
+            assert_eq!(stack.pop(), Some(i));
+        }
+        assert_eq!(stack.len(), (MANY / 2) as usize);
+    }
+
+    fn fifo_conformance<Q: Fifo<i32> + Default>() {
+        let mut queue = Q::default();
+
+        // an empty queue
+        assert!(queue.is_empty());
+        assert!(queue.peek().is_none());
+        assert_eq!(queue.pop(), None);
+
+        // first in, first out
+        queue.push(1);
+        queue.push(2);
+        queue.push(3);
+        assert!(!queue.is_empty());
+        assert_eq!(*queue.peek().unwrap(), 1);
+        assert_eq!(queue.pop(), Some(1));
+        assert_eq!(queue.pop(), Some(2));
+
+        // push more onto the pre-popped queue
+        queue.push(4);
+        queue.push(5);
+        assert_eq!(*queue.peek().unwrap(), 3);
+        assert_eq!(queue.pop(), Some(3));
+        assert_eq!(queue.pop(), Some(4));
+        assert_eq!(queue.pop(), Some(5));
+
+        // test for exhaustion
+        assert_eq!(queue.pop(), None);
+        assert_eq!(queue.pop(), None);
+        assert!(queue.is_empty());
+
+        // a long queue, pushing and popping in turn, then dropped full
+        for i in 0..MANY {
+            queue.push(i);
+            if i % 2 == 1 {
+                assert_eq!(queue.pop(), Some(i / 2));
+            }
+        }
+        assert_eq!(*queue.peek().unwrap(), MANY / 2);
+    }
+
+    fn persistent_stack_conformance<S: PersistentStack<i32> + Default>() {
+        let empty = S::default();
+
+        // an empty stack
+        assert!(empty.is_empty());
+        assert_eq!(empty.len(), 0);
+        assert_eq!(empty.peek(), None);
+        assert!(empty.pop().is_none());
+
+        // last in, first out
+        let stack = empty.push(1).push(2).push(3);
+        assert_eq!(stack.len(), 3);
+        assert!(!stack.is_empty());
+        assert_eq!(stack.peek(), Some(&3));
+        let popped = stack.pop().unwrap();
+        assert_eq!(popped.peek(), Some(&2));
+        assert_eq!(popped.len(), 2);
+
+        // none of that changed the older versions
+        let pushed = popped.push(20);
+        assert_eq!(pushed.peek(), Some(&20));
+        assert_eq!(stack.peek(), Some(&3));
+        assert_eq!(stack.len(), 3);
+        assert!(empty.is_empty());
+
+        // test for exhaustion
+        let last = popped.pop().unwrap();
+        assert_eq!(last.peek(), Some(&1));
+        let gone = last.pop().unwrap();
+        assert!(gone.is_empty());
+        assert!(gone.pop().is_none());
+
+        // a long stack, popped down halfway while the full version is kept around
+        let mut long = S::default();
+        for i in 0..MANY {
+            long = long.push(i);
+        }
+        let mut rest = long.pop().unwrap();
+        for i in (MANY / 2..MANY - 1).rev() {
+            assert_eq!(rest.peek(), Some(&i));
+            rest = rest.pop().unwrap();
+        }
+        assert_eq!(rest.len(), (MANY / 2) as usize);
+        assert_eq!(long.len(), MANY as usize);
+    }
+
+    #[test]
+    fn stacks() {
+        stack_conformance::<first::List>();
+        stack_conformance::<second::List<i32>>();
+        stack_conformance::<arena::List<i32>>();
+        stack_conformance::<unrolled::List<i32>>();
+        stack_conformance::<unrolled::List<i32, 1>>();
+        stack_conformance::<fourth::List<i32>>();
+        stack_conformance::<sixth::List<i32>>();
+        stack_conformance::<third::Transient<i32>>();
+    }
+
+    #[test]
+    fn fifos() {
+        fifo_conformance::<fifth::Queue<i32>>();
+        fifo_conformance::<fifth_unsafe::Queue<i32>>();
+        fifo_conformance::<fourth::List<i32>>();
+        fifo_conformance::<sixth::List<i32>>();
+        fifo_conformance::<bankers_queue::Queue<i32>>();
+        fifo_conformance::<real_time_queue::Queue<i32>>();
+    }
+
+    #[test]
+    fn persistent_stacks() {
+        persistent_stack_conformance::<third::List<i32>>();
+        persistent_stack_conformance::<third_sync::List<i32>>();
+        persistent_stack_conformance::<random_access::List<i32>>();
+        persistent_stack_conformance::<FingerTree<i32>>();
+    }
+
+    // A transient is still a way to edit a shared list: popping through the trait copies the
+    // shared nodes and leaves the original version alone.
+    #[test]
+    fn transient_stack_keeps_shared_list() {
+        let list = third::List::new().append(1).append(2);
+        let mut transient = list.clone().transient();
+        Stack::push(&mut transient, 3);
+        assert_eq!(Stack::pop(&mut transient), Some(3));
+        assert_eq!(Stack::pop(&mut transient), Some(2));
+        assert_eq!(Stack::len(&transient), 1);
+        assert_eq!(list.head(), Some(&2));
+        assert_eq!(list.len(), 2);
+    }
+}
@@ -0,0 +1,362 @@
+// A lock-free concurrent stack (Treiber stack).
+//
+// Features:
+//     - same push/pop/peek shape as `second::List`, but through `&self`, so the stack can be
+//       shared between threads with an `Arc`
+//     - lock-free: a thread that stalls never blocks the others
+//     - nodes are freed safely using hazard pointers (see `hazard.rs`)
+//
+// Based on:
+// R. Kent Treiber, "Systems Programming: Coping with Parallelism", 1986.
+//
+// The stack is a singly-linked list like `second::List`, except `head` is an `AtomicPtr`. `push`
+// and `pop` read the head, build the new head, and compare-and-swap it in, retrying if another
+// thread got there first.
+//
+// The hard part is `pop`: after reading `head` we dereference it to find `next`, and by then
+// another thread may have popped and freed it. A hazard pointer on `head` keeps it alive while
+// we look at it. It also rules out the ABA problem: a node we hold a hazard on can't be freed,
+// so its address can't be reused by a new node that sneaks into `head` before our CAS. That's why
+// `push` protects `head` too, even though it never reads through it.
+
+use crate::hazard::Domain;
+use std::marker::PhantomData;
+use std::mem::ManuallyDrop;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+//////////////////////////////////////////////////////////////////////////////
+// Data structures
+//
+// Linked list layout:
+// [] = stack
+// () = heap
+// [atomic ptr] -> (elem A, ptr) -> (elem B, ptr) -> (elem C, null)
+
+pub struct Stack<T> {
+    head: AtomicPtr<Node<T>>,
+    domain: Domain,
+    // we own `T`s, for drop check and so that `Stack<T>: Send` only when `T: Send`
+    _marker: PhantomData<T>,
+}
+
+struct Node<T> {
+    // Moved out by `pop` before the node is retired, so the node must not drop it again.
+    elem: ManuallyDrop<T>,
+    // never changes once the node has been pushed
+    next: *mut Node<T>,
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Implementation
+
+impl<T> Stack<T> {
+    pub fn new() -> Self {
+        Stack {
+            head: AtomicPtr::new(ptr::null_mut()),
+            domain: Domain::new(),
+            _marker: PhantomData,
+        }
+    }
+
+    // push an element onto the stack
+    pub fn push(&self, elem: T) {
+        let new = Box::into_raw(Box::new(Node {
+            elem: ManuallyDrop::new(elem),
+            next: ptr::null_mut(),
+        }));
+
+        // We never dereference `head` here, but it still needs a hazard. Otherwise it could be
+        // popped and freed, and a new node allocated at the same address, before our CAS. The CAS
+        // would still succeed and `new.next` would point into the freed node.
+        let hazard = self.domain.hazard();
+        loop {
+            let head = hazard.protect(&self.head);
+            // nobody else can see `new` until the CAS succeeds
+            unsafe { (*new).next = head };
+            // Release: whoever pops `new` must see its contents
+            if self
+                .head
+                .compare_exchange_weak(head, new, Ordering::Release, Ordering::Relaxed)
+                .is_ok()
+            {
+                return;
+            }
+        }
+    }
+
+    // pop an element from the stack, returning None if it is empty
+    pub fn pop(&self) -> Option<T> {
+        let hazard = self.domain.hazard();
+        loop {
+            let head = hazard.protect(&self.head);
+            if head.is_null() {
+                return None;
+            }
+
+            // `head` is protected, so it hasn't been freed
+            let next = unsafe { (*head).next };
+            // SeqCst so the unlink is ordered against other threads publishing hazards on `head`
+            // (see `Hazard::protect` and `Domain::scan`)
+            if self
+                .head
+                .compare_exchange(head, next, Ordering::SeqCst, Ordering::Acquire)
+                .is_ok()
+            {
+                // We unlinked `head`, so its element is ours. Concurrent `peek`s may still be
+                // reading it, but they only copy it.
+                let elem = unsafe { ptr::read(&*(*head).elem) };
+                hazard.reset();
+                unsafe { self.domain.retire(head) };
+                return Some(elem);
+            }
+        }
+    }
+
+    // Peek at the element on top of the stack.
+    //
+    // This returns a copy rather than `&T`: the moment we return, another thread may pop the
+    // element and drop it. Cloning isn't enough either, since the popping thread could drop the
+    // element while we're in the middle of cloning it. A bitwise copy of a `Copy` type is safe.
+    pub fn peek(&self) -> Option<T>
+    where
+        T: Copy,
+    {
+        let hazard = self.domain.hazard();
+        let head = hazard.protect(&self.head);
+        if head.is_null() {
+            None
+        } else {
+            Some(unsafe { *(*head).elem })
+        }
+    }
+
+    // Whether the stack was empty at the moment we looked.
+    pub fn is_empty(&self) -> bool {
+        self.head.load(Ordering::Acquire).is_null()
+    }
+}
+
+impl<T> Default for Stack<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// A non-recursive Drop implementation. We have `&mut self`, so no other thread is using the
+// stack and the nodes can be freed directly. Retired nodes are freed when `domain` drops.
+impl<T> Drop for Stack<T> {
+    fn drop(&mut self) {
+        let mut cur = *self.head.get_mut();
+        while !cur.is_null() {
+            let mut node = unsafe { Box::from_raw(cur) };
+            cur = node.next;
+            unsafe { ManuallyDrop::drop(&mut node.elem) };
+        }
+    }
+}
+
+// Sharing the stack lets any thread pop elements that other threads pushed, so both sending and
+// sharing only need `T: Send`. (`Send` comes from `PhantomData<T>` automatically.)
+unsafe impl<T: Send> Sync for Stack<T> {}
+
+//////////////////////////////////////////////////////////////////////////////
+// Tests
+
+#[cfg(test)]
+mod test {
+    use super::Stack;
+    use std::collections::HashSet;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    // smaller runs under Miri, which is much slower
+    const THREADS: usize = if cfg!(miri) { 4 } else { 8 };
+    const PER_THREAD: usize = if cfg!(miri) { 100 } else { 10000 };
+
+    #[test]
+    fn basic() {
+        let stack = Stack::new();
+
+        // test pop on empty stack
+        assert_eq!(stack.pop(), None);
+        assert!(stack.is_empty());
+
+        stack.push(0);
+        stack.push(1);
+        stack.push(2);
+        assert!(!stack.is_empty());
+
+        // check the popped items
+        assert_eq!(stack.pop(), Some(2));
+        assert_eq!(stack.pop(), Some(1));
+
+        // push more onto the pre-popped stack
+        stack.push(3);
+        stack.push(4);
+
+        assert_eq!(stack.pop(), Some(4));
+        assert_eq!(stack.pop(), Some(3));
+        assert_eq!(stack.pop(), Some(0));
+        assert_eq!(stack.pop(), None);
+
+        // How about for strings?
+        let str_stack = Stack::new();
+        str_stack.push("a".to_string());
+        str_stack.push("b".to_string());
+        assert_eq!(str_stack.pop(), Some("b".to_string()));
+        // "a" is dropped with the stack
+    }
+
+    #[test]
+    fn peek() {
+        let stack = Stack::new();
+        assert_eq!(stack.peek(), None);
+        stack.push(1);
+        stack.push(2);
+        assert_eq!(stack.peek(), Some(2));
+        assert_eq!(stack.pop(), Some(2));
+        assert_eq!(stack.peek(), Some(1));
+    }
+
+    // If the Drop impl recursed, this would overflow the stack.
+    #[test]
+    #[cfg_attr(miri, ignore)] // too slow under Miri
+    fn test_drop() {
+        let stack = Stack::new();
+        for i in 0..1000000 {
+            stack.push(i);
+        }
+        // stack is dropped
+    }
+
+    // Every thread pushes its own distinct values and pops as many values as it pushes. Between
+    // them, the threads must pop every value exactly once.
+    #[test]
+    fn stress_push_pop() {
+        let stack = Arc::new(Stack::new());
+
+        let handles: Vec<_> = (0..THREADS)
+            .map(|t| {
+                let stack = Arc::clone(&stack);
+                thread::spawn(move || {
+                    let mut popped = Vec::new();
+                    for i in 0..PER_THREAD {
+                        stack.push(t * PER_THREAD + i);
+                        // pop every other time, so the stack grows and shrinks
+                        if i % 2 == 1 {
+                            popped.extend(stack.pop());
+                            popped.extend(stack.pop());
+                        }
+                    }
+                    popped
+                })
+            })
+            .collect();
+
+        let mut seen = HashSet::new();
+        for handle in handles {
+            for x in handle.join().unwrap() {
+                assert!(seen.insert(x), "{} popped twice", x);
+            }
+        }
+        while let Some(x) = stack.pop() {
+            assert!(seen.insert(x), "{} popped twice", x);
+        }
+        assert_eq!(seen.len(), THREADS * PER_THREAD);
+    }
+
+    // Producers and consumers on separate threads, with heap-allocated elements so that a
+    // use-after-free or double free would be caught (by the allocator or by Miri).
+    #[test]
+    fn stress_producers_consumers() {
+        let stack = Arc::new(Stack::new());
+        let remaining = Arc::new(AtomicUsize::new(THREADS * PER_THREAD));
+
+        let producers: Vec<_> = (0..THREADS)
+            .map(|t| {
+                let stack = Arc::clone(&stack);
+                thread::spawn(move || {
+                    for i in 0..PER_THREAD {
+                        stack.push(Box::new(t * PER_THREAD + i));
+                    }
+                })
+            })
+            .collect();
+        let consumers: Vec<_> = (0..THREADS)
+            .map(|_| {
+                let stack = Arc::clone(&stack);
+                let remaining = Arc::clone(&remaining);
+                thread::spawn(move || {
+                    let mut popped = Vec::new();
+                    while remaining.load(Ordering::Relaxed) > 0 {
+                        if let Some(x) = stack.pop() {
+                            remaining.fetch_sub(1, Ordering::Relaxed);
+                            popped.push(*x);
+                        } else {
+                            thread::yield_now();
+                        }
+                    }
+                    popped
+                })
+            })
+            .collect();
+
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        let mut all: Vec<usize> = consumers
+            .into_iter()
+            .flat_map(|c| c.join().unwrap())
+            .collect();
+        all.sort_unstable();
+        assert_eq!(all, (0..THREADS * PER_THREAD).collect::<Vec<_>>());
+        assert!(stack.is_empty());
+    }
+
+    // Every element must be dropped exactly once: whether it was popped, or still on the stack
+    // when the stack is dropped.
+    #[test]
+    fn drops_each_element_once() {
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct Counted;
+        impl Drop for Counted {
+            fn drop(&mut self) {
+                DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let stack = Arc::new(Stack::new());
+        let handles: Vec<_> = (0..THREADS)
+            .map(|_| {
+                let stack = Arc::clone(&stack);
+                thread::spawn(move || {
+                    for i in 0..PER_THREAD {
+                        stack.push(Counted);
+                        if i % 3 == 0 {
+                            drop(stack.pop());
+                        }
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        drop(stack);
+        assert_eq!(DROPS.load(Ordering::Relaxed), THREADS * PER_THREAD);
+    }
+
+    // These only need to compile.
+    #[allow(dead_code)]
+    fn assert_properties() {
+        fn is_send<T: Send>() {}
+        fn is_sync<T: Sync>() {}
+
+        is_send::<Stack<i32>>();
+        is_sync::<Stack<i32>>();
+        // only needs T: Send to be shared
+        is_sync::<Stack<std::cell::Cell<i32>>>();
+    }
+}
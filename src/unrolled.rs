@@ -0,0 +1,522 @@
+// An unrolled singly-linked stack.
+//
+// Improvements over `second::List`:
+//     - every heap node holds a chunk of up to `N` elements inline, so small elements like `i32`
+//       cost one pointer per `N` elements instead of one pointer (and one allocation) each
+//     - the chunk size `N` is a const generic, so it can be picked per use case
+//
+// The API matches `second::List`.
+//
+// Only the head chunk is ever partially filled. To avoid allocating and freeing a chunk over and
+// over when pushes and pops alternate right at a chunk boundary, a chunk that `pop` empties stays
+// at the head until the pop after it needs to go past it.
+
+use std::cmp::Ordering;
+use std::fmt::{self, Debug};
+use std::hash::{Hash, Hasher};
+use std::iter::{FromIterator, Rev};
+use std::mem::MaybeUninit;
+use std::slice;
+
+//////////////////////////////////////////////////////////////////////////////
+// Data structures
+//
+// Linked list layout (N = 4):
+// [] = stack
+// () = heap
+// [ptr] -> ([A, B, _, _], len 2, ptr) -> ([C, D, E, F], len 4, ptr) -> ([G, H, I, J], len 4, null)
+//
+// The top of the stack is the last element of the head chunk, so this is the stack
+// B, A, F, E, D, C, J, I, H, G.
+
+pub struct List<T, const N: usize = 16> {
+    head: Link<T, N>,
+    // number of elements, across all chunks
+    len: usize,
+}
+
+type Link<T, const N: usize> = Option<Box<Node<T, N>>>;
+
+struct Node<T, const N: usize> {
+    // `elems[..len]` are initialized
+    elems: [MaybeUninit<T>; N],
+    len: usize,
+    next: Link<T, N>,
+}
+
+impl<T, const N: usize> Node<T, N> {
+    // A chunk size of 0 would make every push allocate an empty chunk forever.
+    const NON_ZERO: () = assert!(N > 0, "chunk size must be at least 1");
+
+    fn new(next: Link<T, N>) -> Box<Self> {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::NON_ZERO;
+        Box::new(Node {
+            elems: std::array::from_fn(|_| MaybeUninit::uninit()),
+            len: 0,
+            next,
+        })
+    }
+
+    fn elems(&self) -> &[T] {
+        // `elems[..len]` are initialized, and `MaybeUninit<T>` has the same layout as `T`
+        unsafe { slice::from_raw_parts(self.elems.as_ptr() as *const T, self.len) }
+    }
+
+    fn elems_mut(&mut self) -> &mut [T] {
+        unsafe { slice::from_raw_parts_mut(self.elems.as_mut_ptr() as *mut T, self.len) }
+    }
+}
+
+impl<T, const N: usize> Drop for Node<T, N> {
+    fn drop(&mut self) {
+        // drop the initialized elements; `next` is dropped iteratively by `List`'s Drop
+        unsafe { std::ptr::drop_in_place(self.elems_mut()) };
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Implementation
+
+impl<T, const N: usize> List<T, N> {
+    // return a new, empty list
+    pub fn new() -> Self {
+        List { head: None, len: 0 }
+    }
+
+    // number of elements in the list
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // push an element onto the stack
+    pub fn push(&mut self, x: T) {
+        if self.head.as_ref().is_none_or(|node| node.len == N) {
+            // no head chunk, or it's full: start a new one
+            let next = self.head.take();
+            self.head = Some(Node::new(next));
+        }
+        if let Some(node) = self.head.as_mut() {
+            node.elems[node.len] = MaybeUninit::new(x);
+            node.len += 1;
+        }
+        self.len += 1;
+    }
+
+    // pop an element from the stack, returning either Some(value) or None if
+    // the stack is empty.
+    pub fn pop(&mut self) -> Option<T> {
+        if self.head.as_ref()?.len == 0 {
+            // The head chunk was emptied by an earlier pop. Now we need to go past it, free it.
+            let mut empty = self.head.take()?;
+            self.head = empty.next.take();
+        }
+        let len = &mut self.len;
+        self.head.as_mut().map(|node| {
+            node.len -= 1;
+            *len -= 1;
+            // `elems[len]` was initialized, and is now outside `elems[..len]` so won't be dropped
+            // again
+            unsafe { node.elems[node.len].as_ptr().read() }
+        })
+    }
+
+    // peek at the element on top of the stack
+    pub fn peek(&self) -> Option<&T> {
+        self.iter().next()
+    }
+
+    // peek at the element on top of the stack, return a mutable ref
+    pub fn peek_mut(&mut self) -> Option<&mut T> {
+        self.iter_mut().next()
+    }
+}
+
+impl<T, const N: usize> Default for List<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// A non-recursive Drop implementation so we don't blow the stack when
+// dropping large lists.
+impl<T, const N: usize> Drop for List<T, N> {
+    fn drop(&mut self) {
+        let mut cur_link = self.head.take();
+        while let Some(mut boxed_node) = cur_link {
+            cur_link = boxed_node.next.take();
+            // boxed_node drops its elements here, but not the rest of the list
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Iteration
+
+//
+// IntoIter
+//
+pub struct IntoIter<T, const N: usize>(List<T, N>);
+
+impl<T, const N: usize> IntoIterator for List<T, N> {
+    type Item = T;
+    type IntoIter = IntoIter<T, N>;
+
+    fn into_iter(self) -> IntoIter<T, N> {
+        IntoIter(self)
+    }
+}
+
+impl<T, const N: usize> Iterator for IntoIter<T, N> {
+    type Item = T;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.pop()
+    }
+}
+
+//
+// Iter
+//
+// Walks the elements of the current chunk from the top down, then moves on to the next chunk.
+pub struct Iter<'a, T, const N: usize> {
+    chunk: Rev<slice::Iter<'a, T>>,
+    next: Option<&'a Node<T, N>>,
+}
+
+impl<T, const N: usize> List<T, N> {
+    pub fn iter(&self) -> Iter<'_, T, N> {
+        Iter {
+            chunk: [].iter().rev(),
+            next: self.head.as_deref(),
+        }
+    }
+}
+
+impl<'a, T, const N: usize> IntoIterator for &'a List<T, N> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T, N>;
+
+    fn into_iter(self) -> Iter<'a, T, N> {
+        self.iter()
+    }
+}
+
+impl<'a, T, const N: usize> Iterator for Iter<'a, T, N> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(elem) = self.chunk.next() {
+                return Some(elem);
+            }
+            let node = self.next.take()?;
+            self.chunk = node.elems().iter().rev();
+            self.next = node.next.as_deref();
+        }
+    }
+}
+
+//
+// IterMut
+//
+pub struct IterMut<'a, T, const N: usize> {
+    chunk: Rev<slice::IterMut<'a, T>>,
+    next: Option<&'a mut Node<T, N>>,
+}
+
+impl<T, const N: usize> List<T, N> {
+    pub fn iter_mut(&mut self) -> IterMut<'_, T, N> {
+        IterMut {
+            chunk: [].iter_mut().rev(),
+            next: self.head.as_deref_mut(),
+        }
+    }
+}
+
+impl<'a, T, const N: usize> IntoIterator for &'a mut List<T, N> {
+    type Item = &'a mut T;
+    type IntoIter = IterMut<'a, T, N>;
+
+    fn into_iter(self) -> IterMut<'a, T, N> {
+        self.iter_mut()
+    }
+}
+
+impl<'a, T, const N: usize> Iterator for IterMut<'a, T, N> {
+    type Item = &'a mut T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(elem) = self.chunk.next() {
+                return Some(elem);
+            }
+            let node = self.next.take()?;
+            // split the node into its elements and its link, so we can borrow both
+            let elems =
+                unsafe { slice::from_raw_parts_mut(node.elems.as_mut_ptr() as *mut T, node.len) };
+            self.chunk = elems.iter_mut().rev();
+            self.next = node.next.as_deref_mut();
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Standard traits
+//
+// Same behaviour as `second::List`: everything walks the list with a loop, and collecting keeps
+// iteration order.
+
+impl<T, const N: usize> FromIterator<T> for List<T, N> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut list = List::new();
+        list.extend(iter);
+        list
+    }
+}
+
+// Extending puts the new elements on top, keeping them in iteration order.
+impl<T, const N: usize> Extend<T> for List<T, N> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        // the first element has to end up on top, so it has to be pushed last
+        let mut items: Vec<T> = iter.into_iter().collect();
+        while let Some(x) = items.pop() {
+            self.push(x);
+        }
+    }
+}
+
+impl<T: Clone, const N: usize> Clone for List<T, N> {
+    fn clone(&self) -> Self {
+        self.iter().cloned().collect()
+    }
+}
+
+impl<T: Debug, const N: usize> Debug for List<T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: PartialEq, const N: usize> PartialEq for List<T, N> {
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+impl<T: Eq, const N: usize> Eq for List<T, N> {}
+
+impl<T: PartialOrd, const N: usize> PartialOrd for List<T, N> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.iter().partial_cmp(other.iter())
+    }
+}
+
+impl<T: Ord, const N: usize> Ord for List<T, N> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.iter().cmp(other.iter())
+    }
+}
+
+impl<T: Hash, const N: usize> Hash for List<T, N> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // hash the length first, like slices do
+        self.len().hash(state);
+        for elem in self.iter() {
+            elem.hash(state);
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// TESTS
+
+#[cfg(test)]
+mod test {
+    use super::List;
+    use std::rc::Rc;
+
+    fn chunks<T, const N: usize>(list: &List<T, N>) -> Vec<usize> {
+        let mut lens = Vec::new();
+        let mut cur = list.head.as_deref();
+        while let Some(node) = cur {
+            lens.push(node.len);
+            cur = node.next.as_deref();
+        }
+        lens
+    }
+
+    #[test]
+    fn basic() {
+        let mut list = List::<i32, 2>::new();
+
+        // test pop on empty list
+        assert_eq!(list.pop(), None);
+
+        // push items onto the list, across a chunk boundary
+        list.push(0);
+        list.push(1);
+        list.push(2);
+        assert_eq!(chunks(&list), vec![1, 2]);
+        assert_eq!(list.len(), 3);
+
+        // check the popped items
+        assert_eq!(list.pop(), Some(2));
+        assert_eq!(list.pop(), Some(1));
+
+        // push more onto the pre-popped list
+        list.push(3);
+        list.push(4);
+
+        assert_eq!(list.pop(), Some(4));
+        assert_eq!(list.pop(), Some(3));
+        assert_eq!(list.pop(), Some(0));
+
+        // test for exhaustion
+        assert_eq!(list.pop(), None);
+        assert_eq!(list.pop(), None);
+        assert!(list.is_empty());
+
+        // How about for strings, with the default chunk size?
+        let mut str_list: List<String> = List::new();
+        str_list.push("a".to_string());
+        str_list.push("b".to_string());
+        str_list.push("c".to_string());
+        assert_eq!(str_list.pop(), Some("c".to_string()));
+        assert_eq!(str_list.pop(), Some("b".to_string()));
+        assert_eq!(str_list.pop(), Some("a".to_string()));
+        assert_eq!(str_list.pop(), None);
+    }
+
+    #[test]
+    fn chunk_size_one() {
+        let mut list = List::<i32, 1>::new();
+        for i in 0..5 {
+            list.push(i);
+        }
+        assert_eq!(chunks(&list), vec![1; 5]);
+        assert_eq!(list.into_iter().collect::<Vec<_>>(), vec![4, 3, 2, 1, 0]);
+    }
+
+    // Alternating push and pop at a chunk boundary reuses the emptied chunk instead of freeing
+    // and reallocating it.
+    #[test]
+    fn boundary_hysteresis() {
+        let mut list = List::<i32, 4>::new();
+        for i in 0..4 {
+            list.push(i);
+        }
+        list.push(4);
+        assert_eq!(chunks(&list), vec![1, 4]);
+        for _ in 0..10 {
+            assert_eq!(list.pop(), Some(4));
+            assert_eq!(chunks(&list), vec![0, 4]);
+            assert_eq!(list.peek(), Some(&3));
+            list.push(4);
+            assert_eq!(chunks(&list), vec![1, 4]);
+        }
+        assert_eq!(list.pop(), Some(4));
+        assert_eq!(list.pop(), Some(3));
+        assert_eq!(chunks(&list), vec![3]);
+    }
+
+    #[test]
+    fn peek() {
+        let mut list = List::<i32, 2>::new();
+
+        // check that the head is empty
+        assert_eq!(list.peek(), None);
+        assert_eq!(list.peek_mut(), None);
+
+        list.push(0);
+        list.push(1);
+        list.push(2);
+
+        // peek at the head
+        assert_eq!(list.peek(), Some(&2));
+        assert_eq!(list.pop(), Some(2));
+        // the head chunk is empty now, peek looks past it
+        assert_eq!(list.peek(), Some(&1));
+        assert_eq!(list.peek_mut(), Some(&mut 1));
+        if let Some(x) = list.peek_mut() {
+            *x = 42;
+        }
+        assert_eq!(list.pop(), Some(42));
+        assert_eq!(list.peek(), Some(&0));
+    }
+
+    #[test]
+    fn iters() {
+        let mut list = List::<i32, 3>::new();
+        for i in 0..7 {
+            list.push(i);
+        }
+        assert_eq!(
+            list.iter().copied().collect::<Vec<_>>(),
+            vec![6, 5, 4, 3, 2, 1, 0]
+        );
+
+        for x in &mut list {
+            *x *= 10;
+        }
+        let mut iter_mut = list.iter_mut();
+        assert_eq!(iter_mut.next(), Some(&mut 60));
+        assert_eq!(iter_mut.next(), Some(&mut 50));
+
+        // an emptied head chunk is skipped
+        list.pop();
+        assert_eq!(list.iter().next(), Some(&50));
+        assert_eq!(list.iter_mut().next(), Some(&mut 50));
+
+        let mut into_iter = list.into_iter();
+        assert_eq!(into_iter.next(), Some(50));
+        assert_eq!(into_iter.next(), Some(40));
+        // the rest are dropped with the iterator
+    }
+
+    #[test]
+    fn traits() {
+        let list: List<i32, 2> = vec![1, 2, 3].into_iter().collect();
+        assert_eq!(list.iter().collect::<Vec<_>>(), vec![&1, &2, &3]);
+        assert_eq!(format!("{:?}", list), "[1, 2, 3]");
+
+        let mut copy = list.clone();
+        assert_eq!(list, copy);
+        copy.extend(vec![-1, 0]);
+        assert_eq!(format!("{:?}", copy), "[-1, 0, 1, 2, 3]");
+        assert!(copy < list);
+        assert_ne!(copy, list);
+    }
+
+    // Every element is dropped exactly once, whether popped or dropped with a partially filled
+    // list.
+    #[test]
+    fn drops_elements() {
+        let elem = Rc::new(0);
+        let mut list = List::<Rc<i32>, 4>::new();
+        for _ in 0..10 {
+            list.push(elem.clone());
+        }
+        drop(list.pop());
+        drop(list.pop());
+        drop(list.pop());
+        assert_eq!(Rc::strong_count(&elem), 8);
+        drop(list);
+        assert_eq!(Rc::strong_count(&elem), 1);
+    }
+
+    // If the Drop impl for List is commented out above, this test will cause the stack to
+    // overflow.
+    #[test]
+    #[cfg_attr(miri, ignore)] // too slow under Miri
+    fn test_drop() {
+        let mut list = List::<i32, 1>::new();
+        for i in 0..1000000 {
+            list.push(i);
+        }
+        // list is dropped
+    }
+}
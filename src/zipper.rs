@@ -0,0 +1,261 @@
+// A persistent zipper over `third::List`: a list with a focus that can be moved and edited.
+//
+// Features:
+//     - O(1) moves left and right
+//     - O(1) replace, insert and delete at the focus
+//     - persistent: every operation returns a new zipper and leaves the old one alone
+//     - turning it back into a list shares every node that hasn't been edited
+//
+// Inspired by:
+// Gérard Huet, "The Zipper", Journal of Functional Programming, 1997.
+//
+// The zipper splits the list at the focus. `right` is the rest of the list starting at the focus,
+// so the focus is the head node of `right`. `left` is a stack of the lists that started at each
+// element before the focus, nearest first:
+//
+// list:    A -> B -> C -> D -> E
+//                    ^ focus
+//
+// left:    [B -> C -> D -> E], [A -> B -> C -> D -> E]
+// right:   C -> D -> E
+//
+// Moving right pushes `right` onto `left` and steps to its tail. Moving left pops the previous
+// list off `left` and links its head back in front of `right`. If that list's tail is still
+// `right` itself, nothing after it has been edited, so its node is reused rather than copied.
+// Rebuilding the list does the same for each element on the left. So a zipper that's only been
+// moved rebuilds the very list it started from, and after an edit only the nodes from the start
+// of the list up to the edit are new.
+
+use crate::third::List;
+
+//////////////////////////////////////////////////////////////////////////////
+// Data structures
+
+pub struct Zipper<T> {
+    // the lists that started at each element before the focus, nearest first
+    left: List<List<T>>,
+    // the rest of the list, starting at the focus; never empty
+    right: List<T>,
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Implementation
+
+impl<T> Zipper<T> {
+    // A zipper focused on the first element of `list`, or None if it's empty.
+    pub fn new(list: &List<T>) -> Option<Self> {
+        if list.is_empty() {
+            return None;
+        }
+        Some(Zipper {
+            left: List::new(),
+            right: list.clone(),
+        })
+    }
+
+    pub fn focus(&self) -> &T {
+        self.right
+            .head()
+            .expect("a zipper's right side starts at the focus")
+    }
+
+    // the zipper focused on the element to the right, or None if we're at the end
+    pub fn move_right(&self) -> Option<Self> {
+        let next = self.right.tail().filter(|next| !next.is_empty())?;
+        Some(Zipper {
+            left: self.left.append(self.right.clone()),
+            right: next,
+        })
+    }
+}
+
+impl<T: Clone> Zipper<T> {
+    // the zipper focused on the element to the left, or None if we're at the start
+    pub fn move_left(&self) -> Option<Self> {
+        Some(Zipper {
+            right: relink(self.left.head()?, &self.right),
+            left: self.left.tail()?,
+        })
+    }
+
+    // replace the focus with `elem`
+    pub fn replace(&self, elem: T) -> Self {
+        Zipper {
+            left: self.left.clone(),
+            right: self.right.drop(1).append(elem),
+        }
+    }
+
+    // Insert `elem` where the focus is, and focus on it. The old focus moves one to the right.
+    pub fn insert(&self, elem: T) -> Self {
+        Zipper {
+            left: self.left.clone(),
+            right: self.right.append(elem),
+        }
+    }
+
+    // Remove the focus, and focus on the element to the right of it instead (or to the left, if
+    // it was the last element). None if it was the only element.
+    pub fn delete(&self) -> Option<Self> {
+        let next = self.right.drop(1);
+        if !next.is_empty() {
+            Some(Zipper {
+                left: self.left.clone(),
+                right: next,
+            })
+        } else {
+            Some(Zipper {
+                right: relink(self.left.head()?, &next),
+                left: self.left.tail()?,
+            })
+        }
+    }
+
+    // Rebuild the whole list. Everything from the first edit on is shared, not copied.
+    pub fn to_list(&self) -> List<T> {
+        let mut list = self.right.clone();
+        for prev in self.left.iter() {
+            list = relink(prev, &list);
+        }
+        list
+    }
+}
+
+// The head of `prev` followed by `rest`. If `prev` is already that list, it's returned as it is.
+fn relink<T: Clone>(prev: &List<T>, rest: &List<T>) -> List<T> {
+    if prev.drop(1).ptr_eq(rest) {
+        prev.clone()
+    } else {
+        let elem = prev.head().expect("lists on the left are never empty");
+        rest.append(elem.clone())
+    }
+}
+
+// O(1): the new zipper shares both sides with this one.
+impl<T> Clone for Zipper<T> {
+    fn clone(&self) -> Self {
+        Zipper {
+            left: self.left.clone(),
+            right: self.right.clone(),
+        }
+    }
+}
+
+//////////////////////////////////////////////////////////////////////////////
+// Tests
+
+#[cfg(test)]
+mod test {
+    use super::Zipper;
+    use crate::third::List;
+
+    fn from_vec(elems: Vec<i32>) -> List<i32> {
+        let mut list = List::new();
+        for elem in elems.into_iter().rev() {
+            list = list.append(elem);
+        }
+        list
+    }
+
+    fn to_vec(zipper: &Zipper<i32>) -> Vec<i32> {
+        zipper.to_list().iter().copied().collect()
+    }
+
+    #[test]
+    fn moves() {
+        assert!(Zipper::new(&List::<i32>::new()).is_none());
+
+        let list = from_vec(vec![1, 2, 3]);
+        let z = Zipper::new(&list).unwrap();
+        assert_eq!(z.focus(), &1);
+        assert!(z.move_left().is_none());
+
+        let z = z.move_right().unwrap().move_right().unwrap();
+        assert_eq!(z.focus(), &3);
+        assert!(z.move_right().is_none());
+        assert_eq!(z.move_left().unwrap().focus(), &2);
+        assert_eq!(to_vec(&z), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn edits() {
+        let list = from_vec(vec![1, 2, 3, 4]);
+        let z = Zipper::new(&list).unwrap().move_right().unwrap();
+
+        let replaced = z.replace(20);
+        assert_eq!(to_vec(&replaced), vec![1, 20, 3, 4]);
+
+        let inserted = z.insert(10);
+        assert_eq!(inserted.focus(), &10);
+        assert_eq!(to_vec(&inserted), vec![1, 10, 2, 3, 4]);
+        assert_eq!(inserted.move_right().unwrap().focus(), &2);
+
+        let deleted = z.delete().unwrap();
+        assert_eq!(deleted.focus(), &3);
+        assert_eq!(to_vec(&deleted), vec![1, 3, 4]);
+
+        // deleting the last element focuses on the one before it
+        let last = deleted.move_right().unwrap();
+        let deleted = last.delete().unwrap();
+        assert_eq!(deleted.focus(), &3);
+        assert_eq!(to_vec(&deleted), vec![1, 3]);
+        assert!(Zipper::new(&from_vec(vec![1])).unwrap().delete().is_none());
+
+        // none of that changed the original zipper, or the list
+        assert_eq!(z.focus(), &2);
+        assert_eq!(to_vec(&z), vec![1, 2, 3, 4]);
+        assert_eq!(list, from_vec(vec![1, 2, 3, 4]));
+    }
+
+    // Moving around copies nothing: a zipper that has only moved rebuilds the list it started
+    // from, and after an edit, everything after the edited element is still shared.
+    #[test]
+    fn to_list_shares_suffix() {
+        let list = from_vec(vec![1, 2, 3, 4, 5]);
+        let z = Zipper::new(&list).unwrap();
+        let moved = z
+            .move_right()
+            .unwrap()
+            .move_right()
+            .unwrap()
+            .move_left()
+            .unwrap();
+        assert!(moved.right.ptr_eq(&list.drop(1)));
+        assert!(moved.to_list().ptr_eq(&list));
+
+        let z = z.move_right().unwrap().replace(20);
+        let edited = z.to_list();
+        assert_eq!(edited, from_vec(vec![1, 20, 3, 4, 5]));
+        assert!(edited.common_suffix(&list).ptr_eq(&list.drop(2)));
+
+        // moving back over the untouched elements doesn't copy them either
+        let z = z.move_right().unwrap().move_right().unwrap();
+        let z = z.move_left().unwrap().move_left().unwrap();
+        assert!(z.right.drop(1).ptr_eq(&list.drop(2)));
+        assert!(z.to_list().common_suffix(&list).ptr_eq(&list.drop(2)));
+
+        // deleting the last element keeps the elements before it
+        let last = Zipper::new(&list).unwrap();
+        let last = (0..4).fold(last, |z, _| z.move_right().unwrap());
+        let deleted = last.delete().unwrap();
+        assert_eq!(deleted.to_list(), from_vec(vec![1, 2, 3, 4]));
+    }
+
+    // Walking over a million elements, and rebuilding from the far end.
+    #[test]
+    fn long_lists() {
+        let mut list = List::new();
+        for i in 0..1000000 {
+            list = list.append(i);
+        }
+        let mut z = Zipper::new(&list).unwrap();
+        while let Some(next) = z.move_right() {
+            z = next;
+        }
+        assert_eq!(z.focus(), &0);
+        let rebuilt = z.replace(-1).to_list();
+        assert_eq!(rebuilt.head(), Some(&999999));
+        assert_eq!(rebuilt.nth(999999), Some(&-1));
+        // the zipper and lists are dropped here
+    }
+}